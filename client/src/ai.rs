@@ -3,30 +3,30 @@ use std::time::Duration;
 use bevy::prelude::*;
 use bevy_prng::WyRand;
 use bevy_rand::global::GlobalRng;
-use common::{
-    ai::{Ai, Easiest, Easy, Hard, Medium},
-    grid::Grid,
-};
+use common::ai::{Ai, Easiest, Easy, Hard, Medium};
 
-use crate::{CellColor, Config, CurrentTurn, Dot, DotCell, GameAssets, GameOperation, GridTray, PlayerConfigEntry, VisualGrid, spawn_dot};
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, GameOperation, PlaceDot, PlayerConfigEntry, VisualGrid, anim::AnimationSettings, board_snapshot,
+    history::HistoryPreview,
+};
 
 #[derive(Resource, Default, Deref, DerefMut)]
 pub struct Ais(Vec<Box<dyn Ai>>);
 
 pub fn tick_ai(
-    mut commands: Commands,
     config: Res<Config>,
     current_player: Res<State<CurrentTurn>>,
     state: Res<State<GameOperation>>,
     mut next_state: ResMut<NextState<GameOperation>>,
     grid: Res<VisualGrid>,
-    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
-    game_assets: Res<GameAssets>,
+    cells: Query<(&DotCell, &CellColor)>,
+    mut place_dot: MessageWriter<PlaceDot>,
     mut rng: Single<&mut WyRand, With<GlobalRng>>,
     time: Res<Time>,
     mut timer: Local<Timer>,
-    grid_tray: Query<Entity, With<GridTray>>,
     mut ais: ResMut<Ais>,
+    animation: Res<AnimationSettings>,
+    preview: Res<HistoryPreview>,
 ) {
     if ais.is_empty() {
         // Init
@@ -36,7 +36,8 @@ pub fn tick_ai(
         ais.push(Box::new(Hard::<Medium<16>, 2>::default()));
     }
 
-    if current_player.0 == 0 || *state != GameOperation::Bot {
+    // Waits for the real board to come back if an earlier position is being shown
+    if current_player.0 == 0 || *state != GameOperation::Bot || preview.0.is_some() {
         return;
     }
 
@@ -46,18 +47,10 @@ pub fn tick_ai(
     };
     let ai = &mut ais[level];
 
-    let mut simple_grid = Grid::new(grid.width() as u8, grid.height() as u8, config.players.len() as u8);
-    for (y, row) in grid.iter().enumerate() {
-        for (x, &cell) in row.iter().enumerate() {
-            let (cell, cell_color, _) = cells.get(cell).unwrap();
-            simple_grid[y][x].dots = cell.dots.len() as u8;
-            simple_grid[y][x].owner = cell_color.player as u8;
-        }
-    }
-    simple_grid.init_capacity();
+    let simple_grid = board_snapshot(&grid, &cells, config.players.len() as u8);
     if state.is_changed() {
         timer.set_mode(TimerMode::Once);
-        timer.set_duration(Duration::from_secs_f32(0.75));
+        timer.set_duration(Duration::from_secs_f32(0.75 / animation.speed_for(&config)));
         timer.reset();
         ai.start_move(&simple_grid);
     }
@@ -89,20 +82,11 @@ pub fn tick_ai(
             ai.start_move(&simple_grid);
             return;
         }
-        let entity = grid[y as usize][x as usize];
-        let (
-            _,
-            mut color,
-            Transform {
-                translation: Vec3 { x, z, .. },
-                ..
-            },
-        ) = cells.get_mut(entity).unwrap();
-        commands
-            .entity(entity)
-            .with_related::<Dot>((spawn_dot(*x, *z, &game_assets), ChildOf(grid_tray.single().unwrap())));
-        color.player = current_player.0;
+        place_dot.write(PlaceDot {
+            player: current_player.0,
+            x: x as usize,
+            y: y as usize,
+        });
         timer.reset();
-        next_state.set(GameOperation::Animating);
     }
 }
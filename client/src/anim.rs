@@ -1,6 +1,7 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{CellColor, Config, Dot, DotCell, GRAY};
+use crate::{CellColor, Config, Dot, DotCell, GRAY, PlayerConfigEntry, palette::PaletteSettings};
 
 #[derive(Component, Deref, DerefMut, Reflect)]
 #[reflect(Component)]
@@ -30,6 +31,42 @@ pub struct TargetUiOpacity(pub f32);
 #[derive(Resource, Reflect)]
 pub struct CurrentUiOpacity(pub f32);
 
+/// The speeds offered in the settings, as multipliers of the normal pace.
+pub const ANIMATION_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+/// How much faster games between bots play out with fast-forward on, on top of [`AnimationSettings::speed`].
+const FAST_FORWARD: f32 = 4.0;
+
+/// How quickly moves play out.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationSettings {
+    /// Scales how long each step of a cascade and each bot move takes.
+    pub speed: f32,
+    /// Skip the cascade and show where it ends up straight away.
+    pub instant: bool,
+    /// Speed up games that only bots are playing.
+    pub fast_forward: bool,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            instant: false,
+            fast_forward: true,
+        }
+    }
+}
+
+impl AnimationSettings {
+    /// The speed to play the game in `config` at.
+    pub fn speed_for(&self, config: &Config) -> f32 {
+        let bots_only = config.players.iter().all(|x| matches!(x, PlayerConfigEntry::Bot { .. }));
+        let speed = self.speed.clamp(ANIMATION_SPEEDS[0], ANIMATION_SPEEDS[ANIMATION_SPEEDS.len() - 1]);
+        if self.fast_forward && bots_only { speed * FAST_FORWARD } else { speed }
+    }
+}
+
 pub fn plugin(app: &mut App) {
     app.add_systems(
         Update,
@@ -42,6 +79,7 @@ pub fn plugin(app: &mut App) {
             run_ui_opacity,
         ),
     )
+    .init_resource::<AnimationSettings>()
     .insert_resource(TargetUiOpacity(0.0))
     .insert_resource(CurrentUiOpacity(0.0));
 }
@@ -96,7 +134,7 @@ fn arrange_dots(cells: Query<(&DotCell, &Transform)>, mut dots: Query<&mut Targe
     }
 }
 
-fn animate_cell_colors(mut cells: Query<(&mut TargetMaterialColor, &CellColor)>, player_config: Res<Config>) {
+fn animate_cell_colors(mut cells: Query<(&mut TargetMaterialColor, &CellColor)>, player_config: Res<Config>, palette: Res<PaletteSettings>) {
     for (mut material, color_idx) in &mut cells {
         let target_color = if color_idx.player == 0 {
             GRAY
@@ -104,7 +142,7 @@ fn animate_cell_colors(mut cells: Query<(&mut TargetMaterialColor, &CellColor)>,
             let Some(player) = player_config.players.get(color_idx.player - 1) else {
                 continue;
             };
-            player.color()
+            palette.player_color(color_idx.player, player)
         };
         material.0 = target_color;
     }
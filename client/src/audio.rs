@@ -0,0 +1,244 @@
+//! Sound effects and music, mixed through the player's volume settings.
+
+use std::time::Duration;
+
+use bevy::{
+    audio::{PlaybackMode, Volume},
+    prelude::*,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{EndGame, GameAssets, MainState, PlaceDot};
+
+/// How many effects can play at once. When they're all busy, the one that started first is cut off.
+const VOICES: usize = 8;
+/// Loops in the background while music is turned on.
+const MUSIC_PATH: &str = "sound/music.flac";
+
+/// Volumes are linear, from 0 to 1.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+    pub muted: bool,
+    pub music_enabled: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            sfx: 0.5,
+            music: 0.5,
+            muted: false,
+            music_enabled: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    fn sfx_volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master * self.sfx }
+    }
+
+    fn music_volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master * self.music }
+    }
+}
+
+#[derive(Message, Clone, Copy, Debug)]
+pub enum Sfx {
+    Place,
+    /// One wave of a cascade. `chain` counts the waves since the move, starting at 1.
+    Cascade {
+        chain: usize,
+    },
+    Elimination,
+    Victory,
+}
+
+#[derive(Resource)]
+struct SfxAssets {
+    place: Handle<Pitch>,
+    elimination: [Handle<Pitch>; 3],
+    victory: [Handle<Pitch>; 4],
+}
+
+impl FromWorld for SfxAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut pitches = world.resource_mut::<Assets<Pitch>>();
+        let mut note = |frequency, secs| pitches.add(Pitch::new(frequency, Duration::from_secs_f32(secs)));
+        Self {
+            place: note(523.25, 0.05),
+            elimination: [note(392.0, 0.15), note(293.66, 0.15), note(196.0, 0.35)],
+            victory: [note(523.25, 0.12), note(659.25, 0.12), note(783.99, 0.12), note(1046.5, 0.45)],
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Sound {
+    Sample(Handle<AudioSource>),
+    Tone(Handle<Pitch>),
+}
+
+struct Queued {
+    /// In seconds since startup.
+    at: f32,
+    sound: Sound,
+    volume: f32,
+    speed: f32,
+}
+
+/// Notes of a jingle that haven't started yet.
+#[derive(Resource, Default)]
+struct SfxQueue(Vec<Queued>);
+
+#[derive(Component, Default)]
+struct SfxVoice {
+    started: f32,
+}
+
+#[derive(Component)]
+struct MusicPlayer;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<AudioSettings>()
+        .init_resource::<SfxAssets>()
+        .init_resource::<SfxQueue>()
+        .add_message::<Sfx>()
+        .add_systems(Startup, spawn_voices)
+        .add_systems(
+            OnEnter(EndGame { game_ended: true }),
+            (|mut sfx: MessageWriter<Sfx>| {
+                sfx.write(Sfx::Victory);
+            })
+            .run_if(in_state(MainState::Game)),
+        )
+        .add_systems(Update, (place_sounds, queue_sfx, play_queued).chain())
+        .add_systems(Update, run_music.run_if(resource_changed::<AudioSettings>));
+}
+
+fn spawn_voices(mut commands: Commands) {
+    for _ in 0..VOICES {
+        commands.spawn(SfxVoice::default());
+    }
+}
+
+fn place_sounds(mut place_dot: MessageReader<PlaceDot>, mut sfx: MessageWriter<Sfx>) {
+    for _ in place_dot.read() {
+        sfx.write(Sfx::Place);
+    }
+}
+
+fn queue_sfx(mut sfx: MessageReader<Sfx>, mut queue: ResMut<SfxQueue>, sfx_assets: Res<SfxAssets>, game_assets: Res<GameAssets>, time: Res<Time>) {
+    let now = time.elapsed_secs();
+    for &effect in sfx.read() {
+        let mut push = |delay, sound, volume, speed| {
+            queue.0.push(Queued {
+                at: now + delay,
+                sound,
+                volume,
+                speed,
+            })
+        };
+        match effect {
+            Sfx::Place => push(0.0, Sound::Tone(sfx_assets.place.clone()), 0.2, 1.0),
+            Sfx::Cascade { chain } => {
+                // Rises a semitone per wave, up to an octave
+                let speed = 2f32.powf(chain.saturating_sub(1).min(12) as f32 / 12.0);
+                push(0.0, Sound::Sample(game_assets.bump_sfx.clone()), 1.0, speed);
+            }
+            Sfx::Elimination => {
+                for (i, note) in sfx_assets.elimination.iter().enumerate() {
+                    push(i as f32 * 0.15, Sound::Tone(note.clone()), 0.25, 1.0);
+                }
+            }
+            Sfx::Victory => {
+                for (i, note) in sfx_assets.victory.iter().enumerate() {
+                    push(i as f32 * 0.12, Sound::Tone(note.clone()), 0.25, 1.0);
+                }
+            }
+        }
+    }
+}
+
+fn play_queued(
+    mut commands: Commands,
+    mut queue: ResMut<SfxQueue>,
+    settings: Res<AudioSettings>,
+    mut voices: Query<(Entity, &mut SfxVoice, Has<AudioSink>, Has<AudioPlayer<AudioSource>>, Has<AudioPlayer<Pitch>>)>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    if !queue.0.iter().any(|x| x.at <= now) {
+        return;
+    }
+    let (due, later) = queue.0.drain(..).partition::<Vec<_>, _>(|x| x.at <= now);
+    queue.0 = later;
+    let volume = settings.sfx_volume();
+    if volume <= 0.0 {
+        return;
+    }
+
+    let mut voices = voices
+        .iter_mut()
+        .map(|(entity, voice, sink, sample, tone)| (entity, voice, sink || sample || tone))
+        .collect::<Vec<_>>();
+    for sound in due {
+        // A free voice if there is one, otherwise whichever has been playing longest
+        let Some((entity, voice, busy)) = voices.iter_mut().min_by(|a, b| (a.2, a.1.started).partial_cmp(&(b.2, b.1.started)).unwrap()) else {
+            return;
+        };
+        let mut entity = commands.entity(*entity);
+        if *busy {
+            entity.remove::<(AudioPlayer<AudioSource>, AudioPlayer<Pitch>, AudioSink, PlaybackSettings)>();
+        }
+        let settings = PlaybackSettings {
+            mode: PlaybackMode::Remove,
+            volume: Volume::Linear(volume * sound.volume),
+            speed: sound.speed,
+            ..default()
+        };
+        match sound.sound {
+            Sound::Sample(handle) => entity.insert((AudioPlayer(handle), settings)),
+            Sound::Tone(handle) => entity.insert((AudioPlayer(handle), settings)),
+        };
+        voice.started = now;
+        *busy = true;
+    }
+}
+
+fn run_music(
+    mut commands: Commands,
+    settings: Res<AudioSettings>,
+    mut music: Query<(Entity, Option<&mut AudioSink>), With<MusicPlayer>>,
+    asset_server: Res<AssetServer>,
+) {
+    let volume = Volume::Linear(settings.music_volume());
+    match music.single_mut() {
+        Ok((entity, _)) if !settings.music_enabled => commands.entity(entity).despawn(),
+        Ok((_, Some(mut sink))) => sink.set_volume(volume),
+        // Still loading, so it'll pick up the volume when it starts
+        Ok((entity, None)) => {
+            commands.entity(entity).insert(PlaybackSettings {
+                volume,
+                ..PlaybackSettings::LOOP
+            });
+        }
+        Err(_) if settings.music_enabled => {
+            commands.spawn((
+                MusicPlayer,
+                AudioPlayer::<AudioSource>(asset_server.load(MUSIC_PATH)),
+                PlaybackSettings {
+                    volume,
+                    ..PlaybackSettings::LOOP
+                },
+            ));
+        }
+        Err(_) => {}
+    }
+}
@@ -0,0 +1,212 @@
+//! Letting the player move the camera around the board: orbit, zoom and pan with the mouse, trackpad or two fingers.
+
+use bevy::{
+    input::{
+        gestures::{PinchGesture, RotationGesture},
+        mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit},
+    },
+    picking::hover::HoverMap,
+    prelude::*,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    EndGame, MainState,
+    anim::{SmoothingSettings, TargetTransform},
+};
+
+/// Radians per logical pixel of dragging.
+const ORBIT_SPEED: f32 = 0.008;
+const MIN_PITCH: f32 = 0.2;
+const MAX_PITCH: f32 = 1.5;
+const MIN_ZOOM: f32 = 0.3;
+const MAX_ZOOM: f32 = 2.5;
+/// How the camera eases while the player is steering it. Much snappier than the fly-in, so it doesn't feel like it lags behind.
+const STEERING_SMOOTHING: SmoothingSettings = SmoothingSettings {
+    translation_decay_rate: 12.0,
+    rotation_decay_rate: 12.0,
+    scale_decay_rate: 1.5,
+};
+/// The camera's own smoothing, for flying in and back to where it started.
+const DEFAULT_SMOOTHING: SmoothingSettings = SmoothingSettings {
+    translation_decay_rate: 1.0,
+    rotation_decay_rate: 1.0,
+    scale_decay_rate: 1.5,
+};
+
+/// The player's changes to the camera, on top of where the game put it.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct FreeCamera {
+    /// Where the camera looks at the board from before the player touches it. It always looks at the middle of the board.
+    home: Vec3,
+    yaw: f32,
+    pitch: f32,
+    /// Multiplies the distance from the board.
+    zoom: f32,
+    /// Moves the point the camera looks at, along the table.
+    pan: Vec2,
+}
+
+impl Default for FreeCamera {
+    fn default() -> Self {
+        Self {
+            home: Vec3::new(0.0, 12.0, 20.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+        }
+    }
+}
+
+impl FreeCamera {
+    /// Starts over from a new home position.
+    pub fn go_home(&mut self, home: Vec3) {
+        *self = Self { home, ..default() };
+    }
+
+    pub fn reset(&mut self) {
+        self.go_home(self.home);
+    }
+
+    pub fn moved(&self) -> bool {
+        *self != Self { home: self.home, ..default() }
+    }
+
+    fn home_pitch(&self) -> f32 {
+        (self.home.y / self.home.length()).asin()
+    }
+
+    fn transform(&self) -> Transform {
+        let pitch = self.home_pitch() + self.pitch;
+        let distance = self.home.length() * self.zoom;
+        let focus = Vec3::new(self.pan.x, 0.0, self.pan.y);
+        let offset = Vec3::new(pitch.cos() * self.yaw.sin(), pitch.sin(), pitch.cos() * self.yaw.cos()) * distance;
+        Transform::from_translation(focus + offset).looking_at(focus, Vec3::Y)
+    }
+
+    fn orbit(&mut self, delta: Vec2) {
+        self.yaw -= delta.x * ORBIT_SPEED;
+        let home_pitch = self.home_pitch();
+        self.pitch = (home_pitch + self.pitch + delta.y * ORBIT_SPEED).clamp(MIN_PITCH, MAX_PITCH) - home_pitch;
+    }
+
+    /// `delta` is in logical pixels, and `scale` is how many pixels tall (or wide, if that's smaller) the view is.
+    fn pan_by(&mut self, delta: Vec2, scale: f32) {
+        // Roughly keeps the board under the pointer at the default zoom
+        let speed = self.home.length() * self.zoom / scale;
+        let right = Vec2::new(self.yaw.cos(), -self.yaw.sin());
+        let forward = Vec2::new(-self.yaw.sin(), -self.yaw.cos());
+        self.pan += (-delta.x * right + delta.y * forward) * speed;
+        // Don't lose the board entirely
+        let limit = self.home.length();
+        self.pan = self.pan.clamp_length_max(limit);
+    }
+
+    fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<FreeCamera>().add_systems(
+        Update,
+        (
+            (steer_camera, apply_free_camera.run_if(resource_changed::<FreeCamera>))
+                .chain()
+                .run_if(steerable),
+            settle_camera.run_if(not(steerable)),
+        ),
+    );
+}
+
+/// While a game is being played or replayed. Once it ends, the camera circles the board on its own.
+fn steerable(main_state: Res<State<MainState>>, end_game: Option<Res<State<EndGame>>>) -> bool {
+    match **main_state {
+        MainState::Replay => true,
+        MainState::Game => !end_game.is_some_and(|x| x.game_ended),
+        _ => false,
+    }
+}
+
+/// Outside of play the game moves the camera itself, so give up the player's changes and let it glide as usual.
+fn settle_camera(mut free_camera: ResMut<FreeCamera>, mut camera: Query<&mut SmoothingSettings, With<Camera3d>>) {
+    if free_camera.moved() {
+        free_camera.reset();
+    }
+    if let Ok(mut smoothing) = camera.single_mut() {
+        *smoothing = DEFAULT_SMOOTHING;
+    }
+}
+
+fn steer_camera(
+    mut free_camera: ResMut<FreeCamera>,
+    (mouse_buttons, mouse_motion, mouse_scroll, key_input): (
+        Res<ButtonInput<MouseButton>>,
+        Res<AccumulatedMouseMotion>,
+        Res<AccumulatedMouseScroll>,
+        Res<ButtonInput<KeyCode>>,
+    ),
+    (mut pinches, mut rotations): (MessageReader<PinchGesture>, MessageReader<RotationGesture>),
+    touches: Res<Touches>,
+    hover_map: Res<HoverMap>,
+    ui_nodes: Query<(), With<Node>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let Ok(window) = window.single() else {
+        return;
+    };
+    let scale = window.width().min(window.height());
+
+    // Right drag orbits; middle drag, or right drag with Shift, pans
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let delta = mouse_motion.delta;
+    if delta != Vec2::ZERO {
+        if mouse_buttons.pressed(MouseButton::Middle) || (shift && mouse_buttons.pressed(MouseButton::Right)) {
+            free_camera.pan_by(delta, scale);
+        } else if mouse_buttons.pressed(MouseButton::Right) {
+            free_camera.orbit(delta);
+        }
+    }
+
+    // Scrolling over a menu or the move list is for them, not the camera
+    let over_ui = hover_map.values().flat_map(|hits| hits.keys()).any(|&entity| ui_nodes.contains(entity));
+    let scroll = match mouse_scroll.unit {
+        MouseScrollUnit::Line => mouse_scroll.delta.y,
+        MouseScrollUnit::Pixel => mouse_scroll.delta.y / 50.0,
+    };
+    if scroll != 0.0 && !over_ui {
+        free_camera.zoom_by(0.9f32.powf(scroll));
+    }
+
+    // Trackpad gestures, where the platform reports them
+    for pinch in pinches.read() {
+        free_camera.zoom_by(1.0 - pinch.0);
+    }
+    for rotation in rotations.read() {
+        free_camera.yaw += rotation.0;
+    }
+
+    // Two fingers pinch to zoom, twist to turn and drag to pan. One finger is left alone for playing.
+    let fingers = touches.iter().take(3).collect::<Vec<_>>();
+    if let [a, b] = fingers[..] {
+        let (now, before) = ((a.position(), b.position()), (a.previous_position(), b.previous_position()));
+        let (span_now, span_before) = (now.1 - now.0, before.1 - before.0);
+        if span_now.length() > 1.0 && span_before.length() > 1.0 {
+            free_camera.zoom_by(span_before.length() / span_now.length());
+            free_camera.yaw -= span_before.angle_to(span_now);
+            let center_delta = (now.0 + now.1 - before.0 - before.1) / 2.0;
+            free_camera.pan_by(center_delta, scale);
+        }
+    }
+}
+
+fn apply_free_camera(free_camera: Res<FreeCamera>, mut camera: Query<(&mut TargetTransform, &mut SmoothingSettings), With<Camera3d>>) {
+    let Ok((mut target, mut smoothing)) = camera.single_mut() else {
+        return;
+    };
+    **target = free_camera.transform();
+    // Glide back slowly on reset, but follow the player closely while they steer
+    let settings = if free_camera.moved() { STEERING_SMOOTHING } else { DEFAULT_SMOOTHING };
+    *smoothing = settings;
+}
@@ -0,0 +1,135 @@
+//! Players' clocks. Online the server keeps the real time and says when someone runs out; these only count down in between.
+//! Local games keep their own time, with the clock picked in the settings.
+
+use bevy::prelude::*;
+
+use crate::{Config, CurrentTurn, GameOperation, MainState, PlaceDot, PlayerConfigEntry, audio::Sfx, history::HistoryPreview, net::TimeControl};
+
+/// The clocks there are to pick from, for hosting a room or for local games. Online rooms have to stay within what the server accepts.
+pub const CLOCK_PRESETS: [TimeControl; 7] = [
+    TimeControl::Unlimited,
+    TimeControl::Fischer { base: 180, increment: 2 },
+    TimeControl::Fischer { base: 300, increment: 3 },
+    TimeControl::Fischer { base: 600, increment: 5 },
+    TimeControl::PerMove { secs: 15 },
+    TimeControl::PerMove { secs: 60 },
+    TimeControl::Correspondence { days: 3 },
+];
+
+/// The clock for hot-seat games and games against bots.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LocalClock(pub TimeControl);
+
+impl LocalClock {
+    pub fn step(&mut self, step: isize) {
+        let current = CLOCK_PRESETS.iter().position(|&x| x == self.0).unwrap_or(0);
+        self.0 = CLOCK_PRESETS[(current as isize + step).rem_euclid(CLOCK_PRESETS.len() as isize) as usize];
+    }
+}
+
+/// Everyone's clock in the game being played.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct GameClocks {
+    /// Seconds left for each player, by seat. Empty without a clock.
+    pub remaining: Vec<f64>,
+    /// The player whose clock is running, 1-indexed.
+    pub running: Option<usize>,
+    /// Players in a local game whose time ran out. Online, the server puts them out of the game instead.
+    pub flagged: Vec<usize>,
+    control: TimeControl,
+}
+
+impl GameClocks {
+    /// Fresh clocks for a local game.
+    pub fn local(control: TimeControl, players: usize) -> Self {
+        Self {
+            remaining: control.allowance().map(|x| vec![x; players]).unwrap_or_default(),
+            control,
+            ..default()
+        }
+    }
+
+    /// Takes what the server says is left on everyone's clock.
+    pub fn set_remaining(&mut self, remaining_ms: &[u64]) {
+        self.remaining = remaining_ms.iter().map(|&x| x as f64 / 1000.0).collect();
+    }
+
+    /// Adds on the increment after a move, or resets the clock for the next one.
+    fn moved(&mut self, player: usize) {
+        let Some(left) = self.remaining.get_mut(player.wrapping_sub(1)) else {
+            return;
+        };
+        match self.control {
+            TimeControl::Unlimited => {}
+            TimeControl::Fischer { increment, .. } => *left += f64::from(increment),
+            TimeControl::PerMove { .. } | TimeControl::Correspondence { .. } => *left = self.control.allowance().unwrap_or(*left),
+        }
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<LocalClock>()
+        .init_resource::<GameClocks>()
+        .add_systems(Update, run_clocks.after(crate::ai::tick_ai).run_if(in_state(MainState::Game)));
+}
+
+/// Runs down the clock of whoever's turn it is, and in local games, puts players out once their time is up.
+fn run_clocks(
+    mut clocks: ResMut<GameClocks>,
+    config: Res<Config>,
+    (game_op, current_turn, mut next_game_op): (
+        Option<Res<State<GameOperation>>>,
+        Option<Res<State<CurrentTurn>>>,
+        ResMut<NextState<GameOperation>>,
+    ),
+    mut moves: MessageReader<PlaceDot>,
+    mut sfx: MessageWriter<Sfx>,
+    time: Res<Time>,
+    preview: Res<HistoryPreview>,
+) {
+    let online = config.players.iter().any(PlayerConfigEntry::online);
+    if online || clocks.remaining.is_empty() {
+        moves.clear();
+    } else {
+        for &PlaceDot { player, .. } in moves.read() {
+            clocks.moved(player);
+        }
+        // Only while someone's choosing a move, not while a cascade plays out or a bot waits for a preview to close
+        let choosing = game_op.is_some_and(|x| match **x {
+            GameOperation::Human => true,
+            GameOperation::Bot => preview.0.is_none(),
+            _ => false,
+        });
+        clocks.running = current_turn.filter(|_| choosing).map(|x| x.0).filter(|&x| x != 0);
+    }
+    let Some(player) = clocks.running else {
+        return;
+    };
+    let Some(left) = clocks.remaining.get_mut(player - 1) else {
+        return;
+    };
+    *left = (*left - time.delta_secs_f64()).max(0.0);
+    if *left == 0.0 && !online && !clocks.flagged.contains(&player) {
+        clocks.flagged.push(player);
+        clocks.running = None;
+        sfx.write(Sfx::Elimination);
+        // Hands the turn on, skipping them from now on
+        next_game_op.set(GameOperation::Animating);
+    }
+}
+
+/// How a clock reads: days and hours for correspondence, tenths of a second once it's nearly out.
+pub fn format_clock(secs: f64) -> String {
+    if secs < 10.0 {
+        return format!("0:0{:.1}", (secs * 10.0).floor() / 10.0);
+    }
+    let secs = secs as u64;
+    let (days, hours, minutes, secs) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}:{minutes:02}:{secs:02}")
+    } else {
+        format!("{minutes}:{secs:02}")
+    }
+}
@@ -0,0 +1,366 @@
+use bevy::prelude::*;
+use common::{grid::Grid, pgn::SingleMove};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, EndGame, GameAssets, GameOperation, GridTray, MainState, PlaceDot, PlayerConfigEntry, VisualGrid, apply_grid,
+    board_snapshot, tutorial::Tutorial,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedMove {
+    pub player: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+/// The board between two moves, once every cascade has settled.
+pub struct Position {
+    pub grid: Grid,
+    /// The player to move, 1-indexed.
+    pub turn: usize,
+    /// How many moves led here.
+    pub moves: usize,
+}
+
+/// Every move made on the current board, in order, along with the positions they led to.
+#[derive(Resource, Default)]
+pub struct GameHistory {
+    pub moves: Vec<RecordedMove>,
+    pub positions: Vec<Position>,
+    /// The position on the board. Anything after it has been undone and can be redone until a new move is made.
+    pub current: usize,
+    /// Set once a move has been taken back in a game against a bot.
+    pub casual: bool,
+    /// A move has been made since the last position was recorded.
+    dirty: bool,
+}
+
+impl GameHistory {
+    /// Rebuilds the positions of a game by replaying its moves on an empty board.
+    ///
+    /// If the moves don't lead to `board`, only `board` itself is kept, so the game can still be continued but not undone.
+    pub fn replay(board: &Grid, moves: Vec<RecordedMove>, turn: usize, casual: bool) -> Self {
+        let only_board = |moves: Vec<RecordedMove>| Self {
+            positions: vec![Position {
+                grid: board.clone(),
+                turn,
+                moves: moves.len(),
+            }],
+            moves,
+            casual,
+            ..default()
+        };
+
+        let Some(grids) = positions_from_moves(board.width(), board.height(), board.player_count(), &moves) else {
+            return only_board(moves);
+        };
+        let grid = grids.last().unwrap();
+        let same_board = grid
+            .iter()
+            .flatten()
+            .zip(board.iter().flatten())
+            .all(|(a, b)| (a.owner, a.dots) == (b.owner, b.dots));
+        if !same_board {
+            return only_board(moves);
+        }
+
+        let positions = grids
+            .into_iter()
+            .enumerate()
+            .map(|(i, grid)| Position {
+                grid,
+                turn: moves.get(i).map_or(turn, |x| x.player),
+                moves: i,
+            })
+            .collect::<Vec<_>>();
+        Self {
+            current: positions.len() - 1,
+            positions,
+            moves,
+            casual,
+            dirty: false,
+        }
+    }
+
+    /// The moves that led to the position on the board, leaving out any that have been undone.
+    pub fn played_moves(&self) -> &[RecordedMove] {
+        match self.positions.get(self.current) {
+            Some(position) if !self.dirty => &self.moves[..position.moves],
+            _ => &self.moves,
+        }
+    }
+
+    /// The latest earlier position with a human to move, skipping over bot replies.
+    fn undo_target(&self, config: &Config) -> Option<usize> {
+        (0..self.current).rev().find(|&i| is_human_turn(config, self.positions[i].turn))
+    }
+
+    /// The next position with a human to move, or the last one if only bots are left to move.
+    fn redo_target(&self, config: &Config) -> Option<usize> {
+        let last = self.positions.len().checked_sub(1)?;
+        (self.current + 1..=last).find(|&i| i == last || is_human_turn(config, self.positions[i].turn))
+    }
+
+    pub fn can_undo(&self, config: &Config) -> bool {
+        self.undo_target(config).is_some()
+    }
+
+    pub fn can_redo(&self, config: &Config) -> bool {
+        self.redo_target(config).is_some()
+    }
+}
+
+/// Plays `moves` out on an empty board, returning the position before the first move and after each one.
+///
+/// Returns `None` if a move is off the board or the moves run past the end of the game.
+pub fn positions_from_moves(width: u8, height: u8, num_players: u8, moves: &[RecordedMove]) -> Option<Vec<Grid>> {
+    let mut grid = Grid::new(width, height, num_players);
+    grid.init_capacity();
+    let mut result = vec![grid];
+    for &RecordedMove { player, x, y } in moves {
+        if x >= width as usize || y >= height as usize {
+            return None;
+        }
+        let (next, _) = result.last().unwrap().with_move(x as u8, y as u8, player as u8);
+        result.push(next?);
+    }
+    Some(result)
+}
+
+/// A played move, as shown in the move list.
+pub struct LoggedMove {
+    /// 1-indexed.
+    pub player: usize,
+    pub single: SingleMove,
+    /// How many more cells the player owned afterwards.
+    pub gained: usize,
+}
+
+/// The moves that led to the board, worked out again from an empty board so each one's effect is known.
+#[derive(Resource, Default)]
+pub struct MoveLog {
+    pub moves: Vec<LoggedMove>,
+    /// The board after each move.
+    positions: Vec<Grid>,
+    /// The moves don't play out from an empty board, e.g. for a save from an older version.
+    pub unavailable: bool,
+}
+
+/// A move from the [`MoveLog`] whose position is shown on the board instead of the real one. Nothing can be played until it's closed.
+#[derive(Resource, Default)]
+pub struct HistoryPreview(pub Option<usize>);
+
+/// Asks to show the position after a move from the [`MoveLog`], or to go back to the real board.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct PreviewMove(pub Option<usize>);
+
+fn is_human_turn(config: &Config, turn: usize) -> bool {
+    config.players.get(turn.wrapping_sub(1)).is_some_and(PlayerConfigEntry::is_human)
+}
+
+/// Online games live on the server and the tutorial isn't a real game, so undo and saving are only for local games.
+pub fn is_local_game(config: &Config, tutorial: &Tutorial) -> bool {
+    !tutorial.active && !config.players.iter().any(PlayerConfigEntry::online)
+}
+
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryStep {
+    Undo,
+    Redo,
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<GameHistory>()
+        .init_resource::<MoveLog>()
+        .init_resource::<HistoryPreview>()
+        .add_message::<HistoryStep>()
+        .add_message::<PreviewMove>()
+        .add_systems(Update, (record_moves, update_move_log.run_if(resource_changed::<GameHistory>)).chain())
+        .add_systems(Update, run_preview.after(update_move_log).run_if(in_state(MainState::Game)))
+        .add_systems(
+            Update,
+            (record_position.run_if(state_changed::<GameOperation>), undo_keys, step_history.after(undo_keys)).run_if(in_state(MainState::Game)),
+        );
+}
+
+fn record_moves(mut place_dot: MessageReader<PlaceDot>, mut history: ResMut<GameHistory>) {
+    for &PlaceDot { player, x, y } in place_dot.read() {
+        // A new move throws away anything that was undone
+        if history.current + 1 < history.positions.len() {
+            let played = history.positions[history.current].moves;
+            history.moves.truncate(played);
+            let current = history.current;
+            history.positions.truncate(current + 1);
+        }
+        history.moves.push(RecordedMove { player, x, y });
+        history.dirty = true;
+    }
+}
+
+fn record_position(
+    mut history: ResMut<GameHistory>,
+    config: Res<Config>,
+    game_op: Res<State<GameOperation>>,
+    current_turn: Res<State<CurrentTurn>>,
+    grid: Res<VisualGrid>,
+    cells: Query<(&DotCell, &CellColor)>,
+) {
+    if !matches!(**game_op, GameOperation::Human | GameOperation::Bot) || (!history.dirty && !history.positions.is_empty()) {
+        return;
+    }
+    let position = Position {
+        grid: board_snapshot(&grid, &cells, config.players.len() as u8),
+        turn: current_turn.0,
+        moves: history.moves.len(),
+    };
+    history.positions.push(position);
+    history.current = history.positions.len() - 1;
+    history.dirty = false;
+}
+
+fn undo_keys(key_input: Res<ButtonInput<KeyCode>>, mut steps: MessageWriter<HistoryStep>) {
+    let ctrl = key_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]);
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl {
+        return;
+    }
+    if key_input.just_pressed(KeyCode::KeyY) || (shift && key_input.just_pressed(KeyCode::KeyZ)) {
+        steps.write(HistoryStep::Redo);
+    } else if key_input.just_pressed(KeyCode::KeyZ) {
+        steps.write(HistoryStep::Undo);
+    }
+}
+
+fn step_history(
+    mut steps: MessageReader<HistoryStep>,
+    mut history: ResMut<GameHistory>,
+    config: Res<Config>,
+    tutorial: Res<Tutorial>,
+    game_op: Res<State<GameOperation>>,
+    mut commands: Commands,
+    grid: Res<VisualGrid>,
+    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: Res<GameAssets>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    mut next_turn: ResMut<NextState<CurrentTurn>>,
+    mut next_game_op: ResMut<NextState<GameOperation>>,
+    preview: Res<HistoryPreview>,
+) {
+    // Only one step per frame, since the board won't match the history until the next one
+    let Some(&step) = steps.read().last() else {
+        return;
+    };
+    if *game_op != GameOperation::Human || !is_local_game(&config, &tutorial) || preview.0.is_some() {
+        return;
+    }
+    let Ok(grid_tray) = grid_tray.single() else {
+        return;
+    };
+    let target = match step {
+        HistoryStep::Undo => history.undo_target(&config),
+        HistoryStep::Redo => history.redo_target(&config),
+    };
+    let Some(target) = target else {
+        return;
+    };
+    if step == HistoryStep::Undo && config.players.iter().any(PlayerConfigEntry::is_bot) {
+        history.casual = true;
+    }
+    history.current = target;
+    let position = &history.positions[target];
+    apply_grid(&mut commands, &position.grid, &grid, &mut cells, &game_assets, grid_tray);
+    // Hand the turn to the right player once the board settles
+    next_turn.set(CurrentTurn(position.turn - 1));
+    next_game_op.set(GameOperation::Animating);
+}
+
+fn update_move_log(mut log: ResMut<MoveLog>, history: Res<GameHistory>, config: Res<Config>) {
+    let (width, height) = config.grid_size;
+    let mut grid = Grid::new(width as u8, height as u8, config.players.len() as u8);
+    grid.init_capacity();
+    let owned = |grid: &Grid, player: usize| grid.iter().flatten().filter(|cell| cell.owner as usize == player).count();
+
+    *log = MoveLog::default();
+    for &RecordedMove { player, x, y } in history.played_moves() {
+        if x >= width || y >= height {
+            log.unavailable = true;
+            break;
+        }
+        let (next, did_cascade) = match grid.with_move(x as u8, y as u8, player as u8) {
+            (Some(next), did_cascade) => (next, did_cascade),
+            // The winning cascade never settles, so just hand the winner the board
+            (None, _) => {
+                let mut next = grid.clone();
+                for cell in next.iter_mut().flatten() {
+                    cell.owner = player as u8;
+                }
+                (next, true)
+            }
+        };
+        log.moves.push(LoggedMove {
+            player,
+            single: SingleMove {
+                x: x as u8,
+                y: y as u8,
+                did_cascade,
+                status_type: None,
+            },
+            gained: owned(&next, player).saturating_sub(owned(&grid, player)),
+        });
+        log.positions.push(next.clone());
+        grid = next;
+    }
+}
+
+fn run_preview(
+    mut requests: MessageReader<PreviewMove>,
+    mut preview: ResMut<HistoryPreview>,
+    log: Res<MoveLog>,
+    history: Res<GameHistory>,
+    config: Res<Config>,
+    (game_op, end_game): (Res<State<GameOperation>>, Option<Res<State<EndGame>>>),
+    mut commands: Commands,
+    grid: Res<VisualGrid>,
+    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: Res<GameAssets>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    mut real_board: Local<Option<Grid>>,
+) {
+    // A new or restored game has its own board, so there's nothing to go back to
+    if history.is_changed() && !history.is_added() {
+        preview.0 = None;
+        *real_board = None;
+    }
+    let requested = requests.read().last().map_or(preview.0, |request| request.0);
+    // The preview goes on the real board, so only while nothing else is moving on it: the bots hold off until it's closed,
+    // but an online opponent's move or a cascade wouldn't
+    let ended = end_game.is_some_and(|x| x.game_ended);
+    let target = requested.filter(|_| ended || matches!(**game_op, GameOperation::Human | GameOperation::Bot));
+    if target == preview.0 {
+        return;
+    }
+    let Ok(grid_tray) = grid_tray.single() else {
+        return;
+    };
+    if preview.0.is_none() {
+        let board = board_snapshot(&grid, &cells.transmute_lens::<(&DotCell, &CellColor)>().query(), config.players.len() as u8);
+        // A winning cascade that never settles is still playing out
+        if board.iter().flatten().any(|cell| cell.dots > cell.capacity) {
+            return;
+        }
+        *real_board = Some(board);
+    }
+    let board = match target {
+        Some(i) => log.positions.get(i),
+        None => real_board.as_ref(),
+    };
+    let Some(board) = board else {
+        return;
+    };
+    apply_grid(&mut commands, board, &grid, &mut cells, &game_assets, grid_tray);
+    preview.0 = target;
+    if target.is_none() {
+        *real_board = None;
+    }
+}
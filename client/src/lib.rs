@@ -3,18 +3,31 @@
 
 pub mod ai;
 pub mod anim;
+pub mod audio;
+pub mod camera;
+pub mod clock;
+pub mod history;
 pub mod menu;
+pub mod move_preview;
+pub mod navigation;
 pub mod net;
+pub mod palette;
 pub mod projection;
+pub mod replay;
+pub mod save;
+pub mod settings;
+pub mod storage;
+pub mod threats;
+pub mod tutorial;
 pub mod ui_menu;
 
 use std::{
+    f32::consts::FRAC_PI_2,
     iter,
     ops::{Index, IndexMut},
     time::Duration,
 };
 
-use bevy::audio::Volume;
 #[allow(unused_imports)] // WASM
 use bevy::{anti_alias::taa::TemporalAntiAliasing, light::ShadowFilteringMethod, platform::collections::HashSet, post_process::bloom::Bloom, prelude::*};
 use bevy_defer::{AsyncCommandsExtension, AsyncPlugin, AsyncWorld, fetch};
@@ -23,13 +36,26 @@ use bevy_rand::plugin::EntropyPlugin;
 use bevy_skein::SkeinPlugin;
 #[cfg(not(target_family = "wasm"))]
 use bevy_tokio_tasks::TokioTasksPlugin;
+use common::{
+    grid::{Grid, GridCell},
+    proto::DrawReason,
+};
 
 use crate::{
     ai::Ais,
-    anim::{Bouncing, SmoothingSettings, TargetMaterialColor, TargetTransform, TargetUiOpacity},
+    anim::{AnimationSettings, Bouncing, SmoothingSettings, TargetMaterialColor, TargetTransform, TargetUiOpacity},
+    audio::Sfx,
+    camera::FreeCamera,
+    clock::{GameClocks, LocalClock},
+    history::{GameHistory, HistoryPreview, is_local_game},
     menu::MenuState,
-    net::{NetManagerMessage, NetServerboundSender},
+    move_preview::HoveredCell,
+    navigation::NavInput,
+    net::{NetManagerMessage, NetServerboundSender, OnlineGame},
+    palette::PaletteSettings,
     projection::PerspectiveMinAspect,
+    save::PendingRestore,
+    tutorial::Tutorial,
     ui_menu::{GameEndText, GameEndUiTree, GameHudUiTree, support::fade_out_ui},
 };
 
@@ -42,8 +68,10 @@ pub struct GameAssets {
     dot_mesh: Handle<Mesh>,
     tile_mesh: Handle<Mesh>,
     splash_mesh: Handle<Mesh>,
+    highlight_mesh: Handle<Mesh>,
     dot_color: Handle<StandardMaterial>,
     splash_material: Handle<StandardMaterial>,
+    highlight_material: Handle<StandardMaterial>,
 }
 
 impl FromWorld for GameAssets {
@@ -63,6 +91,13 @@ impl FromWorld for GameAssets {
         let dot_mesh = meshes.add(Sphere::new(0.1).mesh().ico(2).unwrap());
         let tile_mesh = meshes.add(Cuboid::new(0.95, 0.1, 0.95));
         let splash_mesh = meshes.add(Rectangle::new(7.2, 4.0));
+        let highlight_mesh = meshes.add(
+            Annulus::new(0.4, 0.46)
+                .mesh()
+                .resolution(32)
+                .build()
+                .rotated_by(Quat::from_rotation_x(-FRAC_PI_2)),
+        );
 
         let mut materials = world.resource_mut::<Assets<_>>();
         let dot_color = materials.add(Color::srgb(1.0, 1.0, 1.0));
@@ -74,6 +109,12 @@ impl FromWorld for GameAssets {
             alpha_mode: AlphaMode::Blend,
             ..default()
         });
+        let highlight_material = materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.85, 0.2),
+            emissive: LinearRgba::rgb(4.0, 3.0, 0.5),
+            unlit: true,
+            ..default()
+        });
 
         Self {
             table_scene,
@@ -83,8 +124,10 @@ impl FromWorld for GameAssets {
             dot_mesh,
             tile_mesh,
             splash_mesh,
+            highlight_mesh,
             dot_color,
             splash_material,
+            highlight_material,
         }
     }
 }
@@ -119,6 +162,7 @@ pub enum MainState {
     Menu,
     Game,
     DimForUi,
+    Replay,
 }
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Reflect, States)]
@@ -262,37 +306,74 @@ impl PlayerConfigEntry {
         }
     }
 
+    pub fn set_name(&mut self, new_name: String) {
+        match self {
+            Self::Human { name, .. } | Self::Bot { _name: name, .. } | Self::Disabled { _name: name, .. } => *name = new_name,
+        }
+    }
+
+    pub fn set_color(&mut self, new_color: Color) {
+        match self {
+            Self::Human { color, .. } | Self::Bot { color, .. } | Self::Disabled { _color: color, .. } => *color = new_color,
+        }
+    }
+
+    /// The name to show for this player, who plays `player`th (1-indexed). Unnamed bots go by the bot's name.
+    pub fn display_name(&self, player: usize, ais: &Ais) -> String {
+        match self {
+            _ if !self.name().is_empty() => self.name().to_owned(),
+            Self::Bot { level, .. } => ais.get(*level).map_or("Bot", |ai| ai.name()).to_owned(),
+            _ => format!("Player {player}"),
+        }
+    }
+
+    /// The setup for the `player`th seat (1-indexed) before it's been changed. Players start unnamed, to go by [`Self::display_name`].
     pub fn default_for_player(player: usize) -> Self {
+        assert!((1..=MAX_PLAYERS).contains(&player), "invalid player number");
+        let color = PLAYER_COLORS[player - 1];
+        let name = String::new();
         match player {
             1 => PlayerConfigEntry::Human {
-                color: Color::srgb(0.0, 1.0, 0.0),
-                name: "Player 1".into(),
+                color,
+                name,
                 _level: 0,
                 online: false,
             },
             2 => PlayerConfigEntry::Bot {
-                color: Color::srgb(0.0, 0.0, 1.0),
-                _name: "Player 2".into(),
+                color,
+                _name: name,
                 level: 0,
                 online: false,
             },
-            3 => PlayerConfigEntry::Disabled {
-                _color: Color::srgb(1.0, 0.0, 1.0),
-                _name: "Player 3".into(),
-                _level: 0,
-                _online: false,
-            },
-            4 => PlayerConfigEntry::Disabled {
-                _color: Color::srgb(0.0, 1.0, 1.0),
-                _name: "Player 4".into(),
+            _ => PlayerConfigEntry::Disabled {
+                _color: color,
+                _name: name,
                 _level: 0,
                 _online: false,
             },
-            _ => panic!("invalid player number"),
         }
     }
 }
 
+/// How many players a local game can have.
+pub const MAX_PLAYERS: usize = 8;
+
+/// Colours players can pick from. The first [`MAX_PLAYERS`] are the defaults, in turn order.
+pub const PLAYER_COLORS: [Color; 12] = [
+    Color::srgb(0.0, 1.0, 0.0),
+    Color::srgb(0.0, 0.0, 1.0),
+    Color::srgb(1.0, 0.0, 1.0),
+    Color::srgb(0.0, 1.0, 1.0),
+    Color::srgb(1.0, 0.5, 0.0),
+    Color::srgb(1.0, 1.0, 0.0),
+    Color::srgb(1.0, 0.0, 0.0),
+    Color::srgb(1.0, 1.0, 1.0),
+    Color::srgb(0.5, 0.0, 1.0),
+    Color::srgb(1.0, 0.6, 0.8),
+    Color::srgb(0.0, 0.5, 0.25),
+    Color::srgb(0.55, 0.35, 0.15),
+];
+
 #[derive(Clone, Resource, Reflect)]
 pub struct Config {
     pub players: Vec<PlayerConfigEntry>,
@@ -345,6 +426,10 @@ impl VisualGrid {
     pub fn iter(&self) -> core::slice::ChunksExact<'_, Entity> {
         self.grid.chunks_exact(self.width)
     }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Entity> {
+        if x < self.width { self.grid.get(y * self.width + x) } else { None }
+    }
 }
 
 impl Index<usize> for VisualGrid {
@@ -374,6 +459,12 @@ impl<'a> IntoIterator for &'a VisualGrid {
 #[derive(Resource)]
 pub struct FlashIntensity(f32);
 
+impl Default for FlashIntensity {
+    fn default() -> Self {
+        Self(0.3)
+    }
+}
+
 const TABLE_BASE_COLOR: Color = Color::Srgba(Srgba::rgb(0.904, 0.943, 1.0));
 const TABLE_DARK_COLOR: Color = Color::Srgba(Srgba::rgb(0.0, 0.005, 0.008));
 
@@ -406,9 +497,23 @@ pub fn main() {
     .add_plugins(EntropyPlugin::<WyRand>::default())
     .add_plugins(SkeinPlugin::default())
     .add_plugins(anim::plugin)
+    .add_plugins(audio::plugin)
+    .add_plugins(camera::plugin)
+    .add_plugins(clock::plugin)
+    .add_plugins(palette::plugin)
+    .add_plugins(move_preview::plugin)
+    .add_plugins(threats::plugin)
     .add_plugins(menu::plugin)
+    .add_plugins(navigation::plugin)
     .add_plugins(ui_menu::plugin)
-    .add_plugins(net::plugin);
+    .add_plugins(net::plugin)
+    .add_plugins(tutorial::plugin)
+    .add_plugins(history::plugin)
+    .add_plugins(save::plugin)
+    .add_plugins(replay::plugin)
+    .add_plugins(settings::plugin)
+    .add_message::<PlaceDot>()
+    .add_message::<RequestMove>();
 
     #[cfg(not(target_family = "wasm"))]
     app.add_plugins(TokioTasksPlugin::default());
@@ -437,15 +542,17 @@ pub fn main() {
             ..default()
         })
         .insert_resource(ClearColor(Color::srgb_u8(33, 34, 37)))
-        .insert_resource(FlashIntensity(0.3))
+        .init_resource::<FlashIntensity>()
         .insert_resource(Config {
             players: vec![PlayerConfigEntry::default_for_player(1), PlayerConfigEntry::default_for_player(2)],
             grid_size: (6, 6),
         })
         .init_resource::<GameCode>()
         .add_systems(Startup, setup_scene)
-        .add_systems(OnEnter(MainState::Game), fly_in_game)
+        .add_systems(OnEnter(MainState::Game), (fly_in_game, show_game_hud))
         .add_systems(OnExit(MainState::Game), fly_out_game)
+        .add_systems(OnEnter(MainState::Replay), fly_in_game)
+        .add_systems(OnExit(MainState::Replay), fly_out_game)
         .add_systems(
             OnEnter(MainState::DimForUi),
             |lights: Query<&mut PointLight>, mut table_material: Query<&mut TargetMaterialColor, With<TableMaterial>>| {
@@ -475,16 +582,23 @@ pub fn main() {
                 end_game.set(EndGame { game_ended: false });
             }),
         )
+        .add_systems(
+            Update,
+            (ai::tick_ai, (orbit, game_ended).run_if(in_state(EndGame { game_ended: true }))).run_if(in_state(MainState::Game)),
+        )
+        .add_systems(
+            Update,
+            scatter_tick.run_if((in_state(MainState::Game).or(in_state(MainState::Replay))).and(ready_for_scatter)),
+        )
         .add_systems(
             Update,
             (
-                ai::tick_ai,
-                scatter_tick.run_if(ready_for_scatter),
-                (orbit, game_ended).run_if(in_state(EndGame { game_ended: true })),
-            )
-                .run_if(in_state(MainState::Game)),
+                run_splash,
+                esc_to_menu.after(game_ended),
+                request_moves,
+                place_dots.after(ai::tick_ai).after(request_moves),
+            ),
         )
-        .add_systems(Update, (run_splash, esc_to_menu.after(game_ended)))
         .add_systems(
             OnEnter(MainState::Splash),
             |mut commands: Commands, mut ui_opacity: ResMut<TargetUiOpacity>, ui_trees: Query<Entity, (With<Node>, Without<ChildOf>)>| {
@@ -530,9 +644,6 @@ pub const GRAY: Color = Color::Srgba(bevy::color::palettes::tailwind::GRAY_400);
 #[derive(Component)]
 pub struct Splash;
 
-#[derive(Component)]
-pub struct BumpPlayer;
-
 fn run_splash(
     mut commands: Commands,
     splash: Query<(Entity, &MeshMaterial3d<StandardMaterial>), With<Splash>>,
@@ -587,7 +698,7 @@ fn run_splash(
 
 #[allow(clippy::collapsible_if)] // TODO
 fn esc_to_menu(
-    key_input: Res<ButtonInput<KeyCode>>,
+    nav: Res<NavInput>,
     mut main_state: ResMut<NextState<MainState>>,
     mut menu_state: ResMut<NextState<MenuState>>,
     cur_state: Res<State<MainState>>,
@@ -596,7 +707,7 @@ fn esc_to_menu(
     mut commands: Commands,
     ui_tree: Query<Entity, With<GameEndUiTree>>,
 ) {
-    if key_input.just_pressed(KeyCode::Escape) {
+    if nav.back {
         if *cur_state == MainState::Game {
             if let Some(end_game) = end_game
                 && end_game.game_ended
@@ -611,7 +722,7 @@ fn esc_to_menu(
     }
 }
 
-fn fly_in_game(
+pub fn fly_in_game(
     mut commands: Commands,
     mut camera_pos: Query<&mut TargetTransform, With<Camera3d>>,
     config: Res<Config>,
@@ -624,14 +735,19 @@ fn fly_in_game(
     mut next_turn: ResMut<NextState<CurrentTurn>>,
     named_entities: Query<(Entity, &Name)>,
     mut game_operation: ResMut<NextState<GameOperation>>,
-    game_hud: Query<Entity, With<GameHudUiTree>>,
+    mut pending_restore: ResMut<PendingRestore>,
+    mut history: ResMut<GameHistory>,
+    mut free_camera: ResMut<FreeCamera>,
+    (mut clocks, local_clock, tutorial, main_state): (ResMut<GameClocks>, Res<LocalClock>, Res<Tutorial>, Res<State<MainState>>),
 ) {
     let (width, height) = config.grid_size;
     let max_dim = (width * 2 / 3).max(height);
     let true_max_dim = width.max(height);
+    let home = vec3(0.0, max_dim as f32 * 2.0, max_dim as f32);
     if let Ok(mut camera_pos) = camera_pos.single_mut() {
-        **camera_pos = Transform::from_xyz(0.0, max_dim as f32 * 2.0, max_dim as f32).looking_at(Vec3::ZERO, Vec3::Y);
+        **camera_pos = Transform::from_translation(home).looking_at(Vec3::ZERO, Vec3::Y);
     }
+    free_camera.go_home(home);
     for (table, name) in named_entities {
         if name.as_str() == "Table" {
             let scale = (true_max_dim + 2) as f32 / 8.0;
@@ -649,7 +765,13 @@ fn fly_in_game(
     };
     **target = Transform::default();
 
-    if need_new_board.0 {
+    // A saved game is only restored if nothing asked for a fresh board in the meantime. Replays leave it for later.
+    let restore = if **main_state == MainState::Replay {
+        None
+    } else {
+        pending_restore.take().filter(|_| !need_new_board.0)
+    };
+    if need_new_board.0 || restore.is_some() {
         let (width, height) = config.grid_size;
         grid.new_inplace(width, height);
         commands.entity(grid_tray).despawn_related::<Children>().with_children(|commands| {
@@ -672,6 +794,7 @@ fn fly_in_game(
                         y as f32 - height as f32 / 2.0 + 0.5,
                         (x, y),
                         capacity,
+                        restore.as_ref().map_or_else(GridCell::default, |restore| restore.grid[y][x]),
                     );
                 }
             }
@@ -679,9 +802,24 @@ fn fly_in_game(
         game_operation.set(GameOperation::Animating);
         transform.translation = vec3(0.0, 30.0, 0.0);
         next_need_new_board.set(NeedNewBoard(false));
-        next_turn.set(CurrentTurn(0));
+        if let Some(restore) = restore {
+            // Hand the turn to the saved player once the board settles
+            next_turn.set(CurrentTurn(restore.turn - 1));
+            *history = restore.history;
+        } else {
+            next_turn.set(CurrentTurn(0));
+            *history = GameHistory::default();
+        }
+        // Online, the server sends the clocks. The tutorial goes at the student's pace.
+        if is_local_game(&config, &tutorial) {
+            *clocks = GameClocks::local(local_clock.0, config.players.len());
+        } else if tutorial.active {
+            *clocks = GameClocks::default();
+        }
     }
+}
 
+fn show_game_hud(mut commands: Commands, game_hud: Query<Entity, With<GameHudUiTree>>) {
     let game_hud = game_hud.single().unwrap();
 
     commands.spawn_task(move || async move {
@@ -751,6 +889,169 @@ fn spawn_dot(x: f32, z: f32, game_assets: &GameAssets) -> impl Bundle {
     )
 }
 
+/// Adds a dot to a cell on behalf of a player and starts the cascade animation.
+///
+/// Every kind of move (clicks, bots, remote players, scripted moves) goes through this, so anything that needs to know about moves can listen for it.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct PlaceDot {
+    pub player: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+/// A move picked on this device, by clicking a cell or with the board cursor.
+///
+/// It's only played if a human here is to move and the cell is empty or already theirs.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct RequestMove {
+    pub x: usize,
+    pub y: usize,
+}
+
+fn request_moves(
+    mut requests: MessageReader<RequestMove>,
+    grid: Res<VisualGrid>,
+    colors: Query<&CellColor>,
+    state: Option<Res<State<GameOperation>>>,
+    current_turn: Option<Res<State<CurrentTurn>>>,
+    main_state: Res<State<MainState>>,
+    tutorial: Res<Tutorial>,
+    preview: Res<HistoryPreview>,
+    mut place_dot: MessageWriter<PlaceDot>,
+    (net_tx, mut online): (Res<NetServerboundSender>, ResMut<OnlineGame>),
+) {
+    for &RequestMove { x, y } in requests.read() {
+        let (Some(state), Some(current_turn)) = (state.as_ref(), current_turn.as_ref()) else {
+            continue;
+        };
+        // The board is showing an old position, which can only be looked at
+        if **state != GameOperation::Human || **main_state != MainState::Game || preview.0.is_some() || !tutorial.allows_move(current_turn.0, (x, y)) {
+            continue;
+        }
+        let Some(color) = grid.get(x, y).and_then(|&cell| colors.get(cell).ok()) else {
+            continue;
+        };
+        if color.player == 0 || color.player == current_turn.0 {
+            place_dot.write(PlaceDot { player: current_turn.0, x, y });
+            net_tx.force_send(NetManagerMessage::Move { x: x as u8, y: y as u8 }).unwrap();
+            // Wait to hear from the server whose turn is next
+            online.turn = None;
+            // Only one move per turn, even if several were asked for this frame
+            break;
+        }
+    }
+}
+
+fn place_dots(
+    mut place_dot: MessageReader<PlaceDot>,
+    mut commands: Commands,
+    grid: Res<VisualGrid>,
+    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: Res<GameAssets>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    mut next_state: ResMut<NextState<GameOperation>>,
+    (animation, config, tutorial): (Res<AnimationSettings>, Res<Config>, Res<Tutorial>),
+    mut sfx: MessageWriter<Sfx>,
+) {
+    for &PlaceDot { player, x, y } in place_dot.read() {
+        let Some(&entity) = grid.get(x, y).filter(|&&x| cells.contains(x)) else {
+            warn!("tried to place a dot outside of the board at ({x}, {y})");
+            continue;
+        };
+        // The tutorial is there to show cascades, so it always plays them out
+        let board = (&*game_assets, grid_tray.single().unwrap(), config.players.len() as u8);
+        if animation.instant && !tutorial.active && resolve_instantly(&mut commands, PlaceDot { player, x, y }, &grid, &mut cells, board, &mut sfx) {
+            next_state.set(GameOperation::Animating);
+            continue;
+        }
+        let Ok((_, mut color, Transform { translation, .. })) = cells.get_mut(entity) else {
+            continue;
+        };
+        commands
+            .entity(entity)
+            .with_related::<Dot>((spawn_dot(translation.x, translation.z, &game_assets), ChildOf(grid_tray.single().unwrap())));
+        color.player = player;
+        next_state.set(GameOperation::Animating);
+    }
+}
+
+/// Puts the board straight into the position after the move, skipping the cascade. Returns whether it did.
+///
+/// A move that wins the game by cascading forever has no final position, so that one is left to play out.
+fn resolve_instantly(
+    commands: &mut Commands,
+    PlaceDot { player, x, y }: PlaceDot,
+    grid: &VisualGrid,
+    cells: &mut Query<(&DotCell, &mut CellColor, &Transform)>,
+    (game_assets, grid_tray, num_players): (&GameAssets, Entity, u8),
+    sfx: &mut MessageWriter<Sfx>,
+) -> bool {
+    let before = board_snapshot(grid, &cells.transmute_lens::<(&DotCell, &CellColor)>().query(), num_players);
+    let (Some(after), cascaded) = before.with_move(x as u8, y as u8, player as u8) else {
+        return false;
+    };
+    apply_grid(commands, &after, grid, cells, game_assets, grid_tray);
+    if cascaded {
+        sfx.write(Sfx::Cascade { chain: 1 });
+    }
+    let owns_cells = |board: &Grid, player| board.grid_inner().iter().any(|cell| cell.owner == player);
+    let knocked_out = (1..=num_players).any(|x| owns_cells(&before, x) && !owns_cells(&after, x));
+    if knocked_out {
+        sfx.write(Sfx::Elimination);
+    }
+    true
+}
+
+/// Makes the board match `target`, adding and removing dots and recoloring cells as needed.
+///
+/// The board must already have the same dimensions as `target`.
+pub fn apply_grid(
+    commands: &mut Commands,
+    target: &Grid,
+    grid: &VisualGrid,
+    cells: &mut Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: &GameAssets,
+    grid_tray: Entity,
+) {
+    for (y, row) in target.iter().enumerate() {
+        for (x, target_cell) in row.iter().enumerate() {
+            let Some(&entity) = grid.get(x, y) else {
+                continue;
+            };
+            let Ok((cell, mut color, Transform { translation, .. })) = cells.get_mut(entity) else {
+                continue;
+            };
+            let (cur_dots, target_dots) = (cell.dots.len(), target_cell.dots.max(1) as usize);
+            if cur_dots < target_dots {
+                for _ in cur_dots..target_dots {
+                    commands
+                        .entity(entity)
+                        .with_related::<Dot>((spawn_dot(translation.x, translation.z, game_assets), ChildOf(grid_tray)));
+                }
+            } else {
+                for &dot in &cell.dots[target_dots..] {
+                    commands.entity(dot).despawn();
+                }
+            }
+            color.player = target_cell.owner as usize;
+        }
+    }
+}
+
+/// Reads the board into a [`Grid`], e.g. for the bots or for saving.
+pub fn board_snapshot(grid: &VisualGrid, cells: &Query<(&DotCell, &CellColor)>, num_players: u8) -> Grid {
+    let mut result = Grid::new(grid.width() as u8, grid.height() as u8, num_players);
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            let (cell, cell_color) = cells.get(cell).unwrap();
+            result[y][x].dots = cell.dots.len() as u8;
+            result[y][x].owner = cell_color.player as u8;
+        }
+    }
+    result.init_capacity();
+    result
+}
+
 fn spawn_cell(
     commands: &mut ChildSpawnerCommands,
     materials: &mut Assets<StandardMaterial>,
@@ -759,86 +1060,60 @@ fn spawn_cell(
     z: f32,
     pos: (usize, usize),
     capacity: usize,
+    contents: GridCell,
 ) -> Entity {
-    commands
-        .spawn((
-            Mesh3d(game_assets.tile_mesh.clone()),
-            MeshMaterial3d(materials.add(GRAY)),
-            Transform::from_xyz(x, -0.15, z),
-            TargetTransform(Transform::from_xyz(x, -0.15, z)),
-            SmoothingSettings {
-                translation_decay_rate: 5.0,
-                rotation_decay_rate: 0.0,
-                scale_decay_rate: 10.0,
-            },
-            Pickable::default(),
-            related!(DotCell[
-                (spawn_dot(x, z, game_assets), ChildOf(commands.target_entity())),
-            ]),
-            DotCellMeta { capacity },
-        ))
-        .observe(|trigger: On<Pointer<Over>>, mut targets: Query<&mut TargetTransform>| {
-            let mut target = targets.get_mut(trigger.original_event_target()).unwrap();
-            target.scale = Vec3::splat(1.05);
-        })
-        .observe(|trigger: On<Pointer<Out>>, mut targets: Query<&mut TargetTransform>| {
-            let mut target = targets.get_mut(trigger.original_event_target()).unwrap();
-            target.scale = Vec3::splat(1.0);
-        })
-        .observe(
-            move |trigger: On<Pointer<Click>>,
-                  mut commands: Commands,
-                  mut colors: Query<&mut CellColor>,
-                  game_assets: Res<GameAssets>,
-                  state: Option<Res<State<GameOperation>>>,
-                  next_state: Option<ResMut<NextState<GameOperation>>>,
-                  current_turn: Option<Res<State<CurrentTurn>>>,
-                  grid_tray: Query<Entity, With<GridTray>>,
-                  net_tx: Res<NetServerboundSender>| {
-                if let (Some(state), Some(mut next_state), Some(current_turn)) = (state, next_state, current_turn)
-                    && *state == GameOperation::Human
-                {
-                    let mut color = colors.get_mut(trigger.original_event_target()).unwrap();
-                    if color.player == 0 || color.player == current_turn.0 {
-                        color.player = current_turn.0;
-                        commands
-                            .entity(trigger.original_event_target())
-                            .with_related::<Dot>((spawn_dot(x, z, &game_assets), ChildOf(grid_tray.single().unwrap())));
-                        next_state.set(GameOperation::Animating);
-                        net_tx
-                            .force_send(NetManagerMessage::Move {
-                                x: pos.0 as u8,
-                                y: pos.1 as u8,
-                            })
-                            .unwrap();
-                    }
-                }
-            },
-        )
-        .id()
+    let grid_tray = commands.target_entity();
+    let mut cell = commands.spawn((
+        Mesh3d(game_assets.tile_mesh.clone()),
+        MeshMaterial3d(materials.add(GRAY)),
+        Transform::from_xyz(x, -0.15, z),
+        TargetTransform(Transform::from_xyz(x, -0.15, z)),
+        SmoothingSettings {
+            translation_decay_rate: 5.0,
+            rotation_decay_rate: 0.0,
+            scale_decay_rate: 10.0,
+        },
+        Pickable::default(),
+        CellColor {
+            player: contents.owner as usize,
+        },
+        DotCellMeta { capacity },
+    ));
+    for _ in 0..contents.dots.max(1) {
+        cell.with_related::<Dot>((spawn_dot(x, z, game_assets), ChildOf(grid_tray)));
+    }
+    add_hover_observers(&mut cell);
+    cell.observe(move |_: On<Pointer<Over>>, mut hovered: ResMut<HoveredCell>| {
+        hovered.0 = Some(pos);
+    })
+    .observe(move |_: On<Pointer<Out>>, mut hovered: ResMut<HoveredCell>| {
+        if hovered.0 == Some(pos) {
+            hovered.0 = None;
+        }
+    })
+    .observe(move |_: On<Pointer<Click>>, mut requests: MessageWriter<RequestMove>| {
+        requests.write(RequestMove { x: pos.0, y: pos.1 });
+    })
+    .id()
+}
+
+/// Grows something a little to show it's under the pointer or the keyboard/gamepad cursor.
+pub fn set_hovered(target: &mut TargetTransform, hovered: bool) {
+    target.scale = Vec3::splat(if hovered { 1.05 } else { 1.0 });
 }
 
 fn add_hover_observers(entity_commands: &mut EntityCommands) {
     let id = entity_commands.id();
     entity_commands
         .observe(move |_: On<Pointer<Over>>, mut targets: Query<&mut TargetTransform>| {
-            targets.get_mut(id).unwrap().scale = Vec3::splat(1.05);
+            set_hovered(&mut targets.get_mut(id).unwrap(), true);
         })
         .observe(move |_: On<Pointer<Out>>, mut targets: Query<&mut TargetTransform>| {
-            targets.get_mut(id).unwrap().scale = Vec3::splat(1.0);
+            set_hovered(&mut targets.get_mut(id).unwrap(), false);
         });
 }
 
 fn setup_scene(mut commands: Commands, game_assets: Res<GameAssets>, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        AudioPlayer(game_assets.bump_sfx.clone()),
-        BumpPlayer,
-        PlaybackSettings {
-            volume: Volume::Decibels(-6.0),
-            ..default()
-        },
-    ));
-
     commands.spawn((
         Mesh3d(game_assets.splash_mesh.clone()),
         MeshMaterial3d(game_assets.splash_material.clone()),
@@ -913,9 +1188,15 @@ fn setup_scene(mut commands: Commands, game_assets: Res<GameAssets>, asset_serve
         });
 }
 
-pub fn ready_for_scatter(mut timer: Local<Timer>, time: Res<Time>, state: Option<Res<State<GameOperation>>>) -> bool {
+pub fn ready_for_scatter(
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+    state: Option<Res<State<GameOperation>>>,
+    animation: Res<AnimationSettings>,
+    config: Res<Config>,
+) -> bool {
     timer.set_mode(TimerMode::Repeating);
-    timer.set_duration(Duration::from_millis(500));
+    timer.set_duration(Duration::from_secs_f32(0.5 / animation.speed_for(&config)));
 
     if let Some(state) = state {
         if *state == GameOperation::Animating {
@@ -937,7 +1218,7 @@ pub fn scatter_tick(
     mut next_state: ResMut<NextState<GameOperation>>,
     current_turn: Res<State<CurrentTurn>>,
     mut next_turn: ResMut<NextState<CurrentTurn>>,
-    player_config: Res<Config>,
+    (player_config, online, clocks): (Res<Config>, Res<OnlineGame>, Res<GameClocks>),
     grid: Res<VisualGrid>,
     mut cells: Query<(&mut DotCell, &DotCellMeta, &mut CellColor, &MeshMaterial3d<StandardMaterial>, &mut Transform)>,
     time: Res<Time>,
@@ -945,8 +1226,9 @@ pub fn scatter_tick(
     mut end_game: ResMut<NextState<EndGame>>,
     need_new_board: Res<State<NeedNewBoard>>,
     mut next_need_new_board: ResMut<NextState<NeedNewBoard>>,
-    bump_player: Query<Entity, With<BumpPlayer>>,
     intensity: Res<FlashIntensity>,
+    mut sfx: MessageWriter<Sfx>,
+    (mut chain, mut prev_colors): (Local<usize>, Local<HashSet<usize>>),
 ) {
     let mut scatter_temp = vec![vec![false; grid.width()]; grid.height()];
     let mut do_scatter = false;
@@ -961,13 +1243,32 @@ pub fn scatter_tick(
             }
         }
     }
-    let game_over = colors.len() == 1 && !colors.contains(&0);
+    let one_color = colors.len() == 1 && !colors.contains(&0);
+    // Online, the server says who won, or that it's a draw. That can be before the board shows it, if everyone else left.
+    let online_game = player_config.players.iter().any(PlayerConfigEntry::online);
+    // Locally, whoever's left once everyone else has run out of time wins
+    let on_time = (1..=player_config.players.len()).filter(|x| !clocks.flagged.contains(x)).collect::<Vec<_>>();
+    let game_over = if online_game {
+        (online.winner.is_some() || online.draw.is_some()) && online.snapshot.is_none() && (one_color || !do_scatter)
+    } else {
+        one_color || on_time.len() == 1 && !do_scatter
+    };
     if game_over {
+        if let Some(winner) = online.winner.filter(|_| online_game) {
+            next_turn.set(CurrentTurn(winner.into()));
+        } else if let [winner] = on_time[..] {
+            next_turn.set(CurrentTurn(winner));
+        }
         end_game.set(EndGame { game_ended: true });
         next_need_new_board.set(NeedNewBoard(true));
+    } else if *chain > 0 && prev_colors.iter().any(|x| *x != 0 && !colors.contains(x)) {
+        // Only mid-cascade, so jumping around the history doesn't count as knocking anyone out
+        sfx.write(Sfx::Elimination);
     }
+    *prev_colors = colors;
     if do_scatter {
-        commands.entity(bump_player.single().unwrap()).remove::<AudioSink>(); // Cheap restart
+        *chain += 1;
+        sfx.write(Sfx::Cascade { chain: *chain });
         for (y, row) in scatter_temp.iter().enumerate() {
             for (x, &should_scatter) in row.iter().enumerate() {
                 if should_scatter {
@@ -1008,16 +1309,30 @@ pub fn scatter_tick(
                 }
             }
         }
-    } else if !game_over && !need_new_board.0 {
+    } else {
+        *chain = 0;
+    }
+    if !do_scatter && !game_over && !need_new_board.0 {
         // Check so we keep orbiting if the game has ended and don't do stupid stuff if we need a new board
-        let next_turn_idx = current_turn.0 % player_config.players.len();
-        next_state.set(match &player_config.players[next_turn_idx] {
+        // Online, nobody moves until the server has started the game and said whose turn it is
+        let next = if online_game {
+            online.turn.filter(|_| online.seated()).map(usize::from)
+        } else {
+            // current_turn is 1-indexed, and anyone out of time is skipped
+            let len = player_config.players.len();
+            (1..=len).map(|i| (current_turn.0 + i - 1) % len + 1).find(|x| on_time.contains(x))
+        };
+        let Some((next, player)) = next.and_then(|x| Some((x, player_config.players.get(x.wrapping_sub(1))?))) else {
+            next_state.set(GameOperation::Connecting);
+            return;
+        };
+        next_state.set(match player {
             x if x.online() => GameOperation::OnlinePlayer,
             PlayerConfigEntry::Bot { .. } => GameOperation::Bot,
             PlayerConfigEntry::Human { .. } => GameOperation::Human,
             _ => unreachable!(), // Disabled should never be in the final config
         });
-        next_turn.set(CurrentTurn(next_turn_idx + 1)); // current_turn is 1-indexed
+        next_turn.set(CurrentTurn(next));
     }
 }
 
@@ -1032,9 +1347,12 @@ pub fn game_ended(
     config: Res<Config>,
     mut ui_opacity: ResMut<TargetUiOpacity>,
     mut game_end_ui: Query<&mut Visibility, With<GameEndUiTree>>,
-    mut game_end_text: Query<&mut Text, With<GameEndText>>,
+    mut game_end_text: Query<(&mut Text, &mut TargetMaterialColor), With<GameEndText>>,
     current_turn: Res<State<CurrentTurn>>,
-    // ais: Res<Ais>,
+    history: Res<GameHistory>,
+    palette: Res<PaletteSettings>,
+    ais: Res<Ais>,
+    online: Res<OnlineGame>,
 ) {
     if let Ok(mut camera_pos) = camera_pos.single_mut() {
         let (width, height) = config.grid_size;
@@ -1042,20 +1360,28 @@ pub fn game_ended(
         *camera_pos = TargetTransform(Transform::from_xyz(0.0, max_dim as f32, 2.0 * max_dim as f32).looking_at(Vec3::ZERO, Vec3::Y));
         ui_opacity.0 = 1.0;
         *game_end_ui.single_mut().unwrap() = Visibility::Visible;
-        // let player = &config.players[current_turn.0 - 1];
-        // game_end_text.single_mut().unwrap().0 = format!(
-        //     "Player {}{} wins!",
-        //     current_turn.0,
-        //     if player.is_human() {
-        //         if player.name() == &format!("Player {}", current_turn.0) {
-        //             "".into()
-        //         } else {
-        //             format!(" ({})", player.name())
-        //         }
-        //     } else {
-        //         format!(" ({})", ais[player.level()].name())
-        //     }
-        // );
-        game_end_text.single_mut().unwrap().0 = format!("Player {} wins!", current_turn.0);
+        let casual = if history.casual { " (casual)" } else { "" };
+        let (mut text, mut color) = game_end_text.single_mut().unwrap();
+        if let Some(reason) = online.draw.filter(|_| config.players.iter().any(PlayerConfigEntry::online)) {
+            text.0 = match reason {
+                DrawReason::Agreement => "Draw by agreement".into(),
+                DrawReason::Progress => "Draw, no cell changed hands for too long".into(),
+                DrawReason::Time => "Draw on time".into(),
+            };
+            color.0 = Color::WHITE;
+            return;
+        }
+        let winner = current_turn.0;
+        let Some(player) = config.players.get(winner.wrapping_sub(1)) else {
+            text.0 = format!("Player {winner} wins!{casual}");
+            return;
+        };
+        let name = player.display_name(winner, &ais);
+        text.0 = if name == format!("Player {winner}") {
+            format!("{name} wins!{casual}")
+        } else {
+            format!("Player {winner} ({name}) wins!{casual}")
+        };
+        color.0 = palette.player_color(winner, player);
     }
 }
@@ -8,7 +8,9 @@ use bevy::{
 use crate::{
     Config, GameCode, MainState, NeedNewBoard, add_hover_observers,
     anim::{SmoothingSettings, TargetMaterialColor, TargetTransform, TargetUiOpacity},
-    ui_menu::{CreditsUiTree, CustomConfig, CustomGameSetupUiTree, HostGameUiTree, InfoText, JoinGameUiTree, RulesUiTree, SettingsUiTree},
+    navigation::NavInput,
+    tutorial,
+    ui_menu::{CreditsUiTree, CustomConfig, CustomGameSetupUiTree, HostGameUiTree, InfoText, JoinGameUiTree, JoinMode, SettingsUiTree},
 };
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Reflect)]
@@ -48,7 +50,7 @@ impl MenuState {
         core::mem::discriminant(self) == core::mem::discriminant(other)
     }
 
-    fn shows_for_menu(&self, menu: &Self) -> bool {
+    pub fn shows_for_menu(&self, menu: &Self) -> bool {
         match (self, menu) {
             (Self::Main(None), Self::Main(_)) => true,
             (Self::Main(x), Self::Main(y)) if x == y => true,
@@ -222,16 +224,7 @@ fn insert_menu_element(mut world: DeferredWorld, HookContext { entity, .. }: Hoo
                 );
             }
             "rules" => {
-                entity_commands.observe(
-                    |_: On<Pointer<Click>>,
-                     mut next_state: ResMut<NextState<MainState>>,
-                     mut settings_ui_tree: Query<&mut Visibility, With<RulesUiTree>>,
-                     mut ui_opacity: ResMut<TargetUiOpacity>| {
-                        next_state.set(MainState::DimForUi);
-                        *settings_ui_tree.single_mut().unwrap() = Visibility::Visible;
-                        ui_opacity.0 = 1.0;
-                    },
-                );
+                entity_commands.observe(tutorial::start_tutorial);
             }
             "settings" => {
                 entity_commands.observe(
@@ -321,10 +314,12 @@ fn insert_menu_element(mut world: DeferredWorld, HookContext { entity, .. }: Hoo
                      mut next_state: ResMut<NextState<MainState>>,
                      mut join_game_ui_tree: Query<&mut Visibility, With<JoinGameUiTree>>,
                      mut ui_opacity: ResMut<TargetUiOpacity>,
+                     mut join_mode: ResMut<JoinMode>,
                      mut info_texts: Query<&mut Node, With<InfoText>>| {
                         next_state.set(MainState::DimForUi);
                         *join_game_ui_tree.single_mut().unwrap() = Visibility::Visible;
                         ui_opacity.0 = 1.0;
+                        *join_mode = JoinMode::Play;
                         for mut node in &mut info_texts {
                             node.display = Display::None;
                         }
@@ -471,8 +466,8 @@ fn animate_menu_radios(
 pub fn plugin(app: &mut App) {
     app.add_systems(
         Update,
-        (|key_input: Res<ButtonInput<KeyCode>>, mut main_state: ResMut<NextState<MainState>>| {
-            if key_input.just_pressed(KeyCode::Escape) {
+        (|nav: Res<NavInput>, mut main_state: ResMut<NextState<MainState>>| {
+            if nav.back {
                 main_state.set(MainState::Game);
             }
         })
@@ -482,7 +477,7 @@ pub fn plugin(app: &mut App) {
         Update,
         (switch_menus, handle_continue_button)
             .chain()
-            .run_if(state_changed::<MenuState>.or(state_changed::<MainState>)),
+            .run_if(state_changed::<MenuState>.or(state_changed::<MainState>).or(state_changed::<NeedNewBoard>)),
     )
     .add_systems(Update, animate_menu_radios)
     .add_systems(Update, cleanup_menus)
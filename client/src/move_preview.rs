@@ -0,0 +1,198 @@
+//! A ghost of what the hovered move would do: the cells it would take, and how far the chain would spread.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, GameAssets, GameOperation, GridTray, MainState, PlayerConfigEntry, VisualGrid, board_snapshot,
+    history::HistoryPreview, navigation::BoardCursor, palette::PaletteSettings,
+};
+
+/// Optional hints about the board.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssistSettings {
+    /// Show what the hovered move would do. Never shown in online games.
+    pub move_preview: bool,
+    /// Mark full, attacking and exposed cells. Never shown in online games.
+    pub threats: bool,
+}
+
+impl Default for AssistSettings {
+    fn default() -> Self {
+        Self {
+            move_preview: true,
+            threats: false,
+        }
+    }
+}
+
+/// The cell under the mouse pointer.
+#[derive(Resource, Default)]
+pub struct HoveredCell(pub Option<(usize, usize)>);
+
+#[derive(Component)]
+struct Ghost;
+
+#[derive(Component)]
+struct GhostLabel;
+
+#[derive(Resource)]
+struct GhostMesh(Handle<Mesh>);
+
+impl FromWorld for GhostMesh {
+    fn from_world(world: &mut World) -> Self {
+        // A thin slab just above the tile, under the dots
+        Self(world.resource_mut::<Assets<Mesh>>().add(Cuboid::new(0.95, 0.02, 0.95)))
+    }
+}
+
+/// What the ghost is showing: the cell and the player to move.
+#[derive(Default, PartialEq)]
+struct ShownGhost(Option<(usize, usize, usize)>);
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<AssistSettings>()
+        .init_resource::<HoveredCell>()
+        .init_resource::<GhostMesh>()
+        .add_systems(Startup, spawn_label)
+        .add_systems(Update, update_ghost);
+}
+
+fn spawn_label(mut commands: Commands, ga: Res<GameAssets>) {
+    commands.spawn((
+        GhostLabel,
+        Node {
+            position_type: PositionType::Absolute,
+            padding: UiRect::axes(Val::Px(4.0), Val::Px(2.0)),
+            border_radius: BorderRadius::all(Val::Px(3.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        Text::new(""),
+        TextFont {
+            font: ga.mono_font.clone(),
+            font_size: 15.0,
+            ..default()
+        },
+        Pickable::IGNORE,
+        Visibility::Hidden,
+    ));
+}
+
+fn update_ghost(
+    mut commands: Commands,
+    (settings, hovered, cursor, preview): (Res<AssistSettings>, Res<HoveredCell>, Res<BoardCursor>, Res<HistoryPreview>),
+    (main_state, game_op, current_turn): (Res<State<MainState>>, Option<Res<State<GameOperation>>>, Option<Res<State<CurrentTurn>>>),
+    config: Res<Config>,
+    grid: Res<VisualGrid>,
+    cells: Query<(&DotCell, &CellColor)>,
+    cell_transforms: Query<(&Transform, &GlobalTransform), With<DotCell>>,
+    ghosts: Query<Entity, With<Ghost>>,
+    mut label: Query<(&mut Node, &mut Text, &mut Visibility, &ComputedNode), With<GhostLabel>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    (palette, ghost_mesh): (Res<PaletteSettings>, Res<GhostMesh>),
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut shown: Local<ShownGhost>,
+) {
+    let Ok((mut label_node, mut label_text, mut label_visibility, label_size)) = label.single_mut() else {
+        return;
+    };
+    let active = settings.move_preview
+        && *main_state == MainState::Game
+        && game_op.is_some_and(|x| *x == GameOperation::Human)
+        && preview.0.is_none()
+        && !config.players.iter().any(PlayerConfigEntry::online);
+    let target = hovered
+        .0
+        .or(cursor.pos)
+        .zip(current_turn.map(|x| x.0))
+        .filter(|_| active)
+        .filter(|&((x, y), turn)| {
+            // Only moves that could actually be played
+            grid.get(x, y)
+                .and_then(|&cell| cells.get(cell).ok())
+                .is_some_and(|(_, color)| color.player == 0 || color.player == turn)
+        })
+        .map(|((x, y), turn)| (x, y, turn));
+
+    // The label follows the cell around as the camera moves
+    if let Some((x, y, _)) = target
+        && let Ok((camera, camera_transform)) = camera.single()
+        && let Ok((_, cell_transform)) = cell_transforms.get(grid[y][x])
+        && let Ok(position) = camera.world_to_viewport(camera_transform, cell_transform.translation())
+    {
+        let size = label_size.size() * label_size.inverse_scale_factor();
+        label_node.left = Val::Px(position.x - size.x / 2.0);
+        label_node.top = Val::Px(position.y - size.y - 40.0);
+    }
+
+    if ShownGhost(target) == *shown {
+        return;
+    }
+    shown.0 = target;
+    for ghost in &ghosts {
+        commands.entity(ghost).despawn();
+    }
+    *label_visibility = Visibility::Hidden;
+    let (Some((x, y, player)), Ok(grid_tray)) = (target, grid_tray.single()) else {
+        return;
+    };
+    let Some(player_config) = config.players.get(player - 1) else {
+        return;
+    };
+
+    let before = board_snapshot(&grid, &cells, config.players.len() as u8);
+    let (after, cascaded) = before.with_move(x as u8, y as u8, player as u8);
+    let mut taken = vec![];
+    let mut chain = 0;
+    for (cell_y, before_row) in before.iter().enumerate() {
+        for (cell_x, before_cell) in before_row.iter().enumerate() {
+            let Some(after) = &after else {
+                // A cascade that never settles ends the game, taking the whole board
+                if before_cell.owner as usize != player {
+                    taken.push((cell_x, cell_y));
+                }
+                continue;
+            };
+            let after_cell = &after[cell_y as u8][cell_x as u8];
+            if after_cell.owner as usize == player && before_cell.owner as usize != player {
+                taken.push((cell_x, cell_y));
+            }
+            if (after_cell.owner, after_cell.dots) != (before_cell.owner, before_cell.dots) {
+                chain += 1;
+            }
+        }
+    }
+
+    let material = materials.add(StandardMaterial {
+        base_color: palette.player_color(player, player_config).with_alpha(0.45),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    let hovered = (!taken.contains(&(x, y))).then_some((x, y));
+    for &(cell_x, cell_y) in taken.iter().chain(hovered.iter()) {
+        let Ok((transform, _)) = cell_transforms.get(grid[cell_y][cell_x]) else {
+            continue;
+        };
+        commands.spawn((
+            Ghost,
+            Mesh3d(ghost_mesh.0.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_xyz(transform.translation.x, -0.08, transform.translation.z),
+            Pickable::IGNORE,
+            ChildOf(grid_tray),
+        ));
+    }
+
+    label_text.0 = if after.is_none() {
+        "Wins the game".into()
+    } else if cascaded {
+        format!("+{} cells, chain of {chain}", taken.len())
+    } else {
+        format!("+{} cells", taken.len())
+    };
+    *label_visibility = Visibility::Inherited;
+}
@@ -0,0 +1,277 @@
+//! Keyboard and gamepad control: a cursor on the board, and focus that moves between menu buttons.
+
+use std::time::Duration;
+
+use bevy::{
+    camera::NormalizedRenderTarget,
+    picking::{
+        backend::HitData,
+        pointer::{Location, PointerButton, PointerId},
+    },
+    prelude::*,
+    window::{PrimaryWindow, WindowRef},
+};
+
+use crate::{
+    EndGame, MainState, RequestMove, VisualGrid,
+    anim::TargetTransform,
+    menu::{MenuElement, MenuState},
+    set_hovered,
+    ui_menu::{EditingName, ServerForm},
+};
+
+/// How far the stick has to be pushed to count as a direction.
+const STICK_THRESHOLD: f32 = 0.5;
+/// Holding the stick keeps stepping, after this long...
+const STICK_DELAY: Duration = Duration::from_millis(400);
+/// ...this often.
+const STICK_REPEAT: Duration = Duration::from_millis(150);
+const FOCUS_OUTLINE: Color = Color::srgb(1.0, 0.85, 0.2);
+
+/// This frame's input from the keyboard and every gamepad, boiled down to cursor movement.
+#[derive(Resource, Default)]
+pub struct NavInput {
+    /// One step in screen space, with +y pointing down.
+    pub step: Option<IVec2>,
+    /// Enter, Space or the south face button.
+    pub activate: bool,
+    /// Escape, or the east face button or Start.
+    pub back: bool,
+    stick: Option<IVec2>,
+    stick_repeat: Timer,
+}
+
+/// The cell the keyboard/gamepad cursor is on. It only shows up once one of them is used.
+#[derive(Resource, Default)]
+pub struct BoardCursor {
+    pub pos: Option<(usize, usize)>,
+    shown: Option<Entity>,
+}
+
+/// The menu button the keyboard/gamepad cursor is on.
+#[derive(Resource, Default)]
+struct MenuFocus(Option<Entity>);
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<NavInput>()
+        .init_resource::<BoardCursor>()
+        .init_resource::<MenuFocus>()
+        .add_systems(PreUpdate, read_nav_input.after(bevy::input::InputSystems))
+        .add_systems(
+            Update,
+            move_board_cursor.run_if(in_state(MainState::Game).and(not(in_state(EndGame { game_ended: true })))),
+        )
+        .add_systems(
+            Update,
+            move_menu_focus.run_if(not(in_state(MainState::Game)).or(in_state(EndGame { game_ended: true }))),
+        )
+        .add_systems(OnExit(MainState::Game), hide_board_cursor);
+}
+
+fn read_nav_input(
+    mut nav: ResMut<NavInput>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    (editing, server_form): (Res<EditingName>, Res<ServerForm>),
+    time: Res<Time>,
+) {
+    // The keyboard belongs to the text being typed
+    let empty = ButtonInput::default();
+    let key_input = if editing.0.is_some() || server_form.focus.is_some() {
+        &empty
+    } else {
+        &*key_input
+    };
+    let ctrl = key_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]);
+    let keys = [
+        (KeyCode::ArrowUp, KeyCode::KeyW, IVec2::NEG_Y),
+        (KeyCode::ArrowDown, KeyCode::KeyS, IVec2::Y),
+        (KeyCode::ArrowLeft, KeyCode::KeyA, IVec2::NEG_X),
+        (KeyCode::ArrowRight, KeyCode::KeyD, IVec2::X),
+    ];
+    let buttons = [
+        (GamepadButton::DPadUp, IVec2::NEG_Y),
+        (GamepadButton::DPadDown, IVec2::Y),
+        (GamepadButton::DPadLeft, IVec2::NEG_X),
+        (GamepadButton::DPadRight, IVec2::X),
+    ];
+    // Letter keys are left alone while Ctrl is held, so shortcuts like Ctrl+S don't move anything
+    nav.step = keys
+        .iter()
+        .find(|&&(arrow, letter, _)| key_input.just_pressed(arrow) || (!ctrl && key_input.just_pressed(letter)))
+        .map(|&(_, _, step)| step)
+        .or_else(|| {
+            gamepads
+                .iter()
+                .find_map(|gamepad| buttons.iter().find(|&&(button, _)| gamepad.just_pressed(button)).map(|&(_, step)| step))
+        });
+    nav.activate = key_input.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter, KeyCode::Space])
+        || gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::South));
+    nav.back = key_input.just_pressed(KeyCode::Escape)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::East) || gamepad.just_pressed(GamepadButton::Start));
+
+    // The stick steps once when pushed, then repeats while it's held
+    let stick = gamepads
+        .iter()
+        .map(Gamepad::left_stick)
+        .find(|stick| stick.length() > STICK_THRESHOLD)
+        .map(|stick| {
+            if stick.x.abs() > stick.y.abs() {
+                IVec2::new(stick.x.signum() as i32, 0)
+            } else {
+                IVec2::new(0, -stick.y.signum() as i32)
+            }
+        });
+    if stick != nav.stick {
+        nav.stick = stick;
+        nav.stick_repeat = Timer::new(STICK_DELAY, TimerMode::Once);
+        if nav.step.is_none() {
+            nav.step = stick;
+        }
+    } else if stick.is_some() && nav.stick_repeat.tick(time.delta()).is_finished() {
+        nav.stick_repeat = Timer::new(STICK_REPEAT, TimerMode::Once);
+        nav.step = nav.step.or(stick);
+    }
+}
+
+fn move_board_cursor(
+    nav: Res<NavInput>,
+    mut cursor: ResMut<BoardCursor>,
+    grid: Res<VisualGrid>,
+    mut targets: Query<&mut TargetTransform>,
+    mut requests: MessageWriter<RequestMove>,
+) {
+    if grid.width() == 0 || grid.height() == 0 {
+        return;
+    }
+    if let Some(step) = nav.step {
+        let (x, y) = match cursor.pos {
+            Some((x, y)) => (
+                (x as i32 + step.x).clamp(0, grid.width() as i32 - 1) as usize,
+                (y as i32 + step.y).clamp(0, grid.height() as i32 - 1) as usize,
+            ),
+            // The first press just shows the cursor in the middle of the board
+            None => (grid.width() / 2, grid.height() / 2),
+        };
+        cursor.pos = Some((x, y));
+    }
+    let Some((x, y)) = cursor.pos else {
+        return;
+    };
+    // The board may have been rebuilt smaller since the cursor was last moved
+    let (x, y) = (x.min(grid.width() - 1), y.min(grid.height() - 1));
+    cursor.pos = Some((x, y));
+
+    let cell = grid[y][x];
+    if cursor.shown != Some(cell) {
+        if let Some(mut target) = cursor.shown.and_then(|shown| targets.get_mut(shown).ok()) {
+            set_hovered(&mut target, false);
+        }
+        if let Ok(mut target) = targets.get_mut(cell) {
+            set_hovered(&mut target, true);
+        }
+        cursor.shown = Some(cell);
+    }
+    if nav.activate {
+        requests.write(RequestMove { x, y });
+    }
+}
+
+fn hide_board_cursor(mut cursor: ResMut<BoardCursor>, mut targets: Query<&mut TargetTransform>) {
+    if let Some(mut target) = cursor.shown.and_then(|shown| targets.get_mut(shown).ok()) {
+        set_hovered(&mut target, false);
+    }
+    *cursor = default();
+}
+
+/// Picks the closest candidate in the direction of `step`, favouring ones that are straight ahead.
+fn next_in_direction(from: Vec2, step: IVec2, candidates: &[(Entity, Vec2)]) -> Option<Entity> {
+    let dir = step.as_vec2();
+    candidates
+        .iter()
+        .filter_map(|&(entity, pos)| {
+            let offset = pos - from;
+            let along = offset.dot(dir);
+            let across = offset.perp_dot(dir).abs();
+            (along > 1.0 && across < along * 2.0).then_some((entity, along + across * 2.0))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity)
+}
+
+fn move_menu_focus(
+    mut commands: Commands,
+    nav: Res<NavInput>,
+    mut focus: ResMut<MenuFocus>,
+    menu_state: Option<Res<State<MenuState>>>,
+    buttons: Query<(Entity, &InheritedVisibility, &ComputedNode, &UiGlobalTransform), With<Button>>,
+    menu_elements: Query<(Entity, &MenuElement, &InheritedVisibility, &GlobalTransform)>,
+    camera: Query<(Entity, &Camera, &GlobalTransform), With<Camera3d>>,
+    window: Query<Entity, With<PrimaryWindow>>,
+    mut outlines: Query<&mut Outline>,
+    mut targets: Query<&mut TargetTransform>,
+) {
+    let Ok((camera_entity, camera, camera_transform)) = camera.single() else {
+        return;
+    };
+    // Everything that can be clicked right now, in logical screen coordinates
+    let mut candidates = buttons
+        .iter()
+        .filter(|(_, visibility, node, _)| visibility.get() && !node.is_empty())
+        .map(|(entity, _, node, transform)| (entity, transform.translation * node.inverse_scale_factor()))
+        .collect::<Vec<_>>();
+    if let Some(menu_state) = &menu_state {
+        candidates.extend(
+            menu_elements
+                .iter()
+                .filter(|(_, element, visibility, _)| visibility.get() && element.menu_action.is_some() && element.for_menu.shows_for_menu(menu_state))
+                .filter_map(|(entity, _, _, transform)| Some((entity, camera.world_to_viewport(camera_transform, transform.translation()).ok()?))),
+        );
+    }
+
+    let current = focus.0.and_then(|focused| candidates.iter().find(|(entity, _)| *entity == focused).copied());
+    let next = match (nav.step, current) {
+        (Some(step), Some((_, from))) => next_in_direction(from, step, &candidates).or(focus.0),
+        // Start from the top left
+        (Some(_), None) => candidates
+            .iter()
+            .min_by(|a, b| (a.1.y, a.1.x).partial_cmp(&(b.1.y, b.1.x)).unwrap())
+            .map(|(entity, _)| *entity),
+        (None, Some(_)) => focus.0,
+        (None, None) => None,
+    };
+
+    if next != focus.0 {
+        for (entity, focused) in [(focus.0, false), (next, true)] {
+            let Some(entity) = entity else {
+                continue;
+            };
+            if let Ok(mut outline) = outlines.get_mut(entity) {
+                let alpha = outline.color.alpha();
+                outline.color = if focused { FOCUS_OUTLINE } else { Color::WHITE }.with_alpha(alpha);
+            } else if let Ok(mut target) = targets.get_mut(entity) {
+                set_hovered(&mut target, focused);
+            }
+        }
+        focus.0 = next;
+    }
+
+    if nav.activate
+        && let Some((entity, position)) = next.and_then(|next| candidates.iter().find(|(entity, _)| *entity == next).copied())
+        && let Ok(window) = window.single()
+    {
+        // Click it just like the mouse would, so every button works without knowing about the cursor
+        let location = Location {
+            target: NormalizedRenderTarget::Window(WindowRef::Primary.normalize(Some(window)).unwrap()),
+            position,
+        };
+        let click = Click {
+            button: PointerButton::Primary,
+            hit: HitData::new(camera_entity, 0.0, None, None),
+            duration: Duration::ZERO,
+        };
+        commands.trigger(Pointer::new(PointerId::Mouse, location, click, entity));
+    }
+}
@@ -1,29 +1,39 @@
-use bevy::prelude::*;
+use bevy::{platform::time::Instant, prelude::*};
 use bevy_defer::{AsyncCommandsExtension as _, AsyncWorld, fetch};
 
 #[cfg(not(target_family = "wasm"))]
 use std::pin::Pin;
-use std::time::Duration;
+use std::{fmt, time::Duration};
 
 use async_channel::{Receiver, Sender};
-use async_wsocket::{ConnectionMode, Message, Url, futures_util::SinkExt};
+use async_wsocket::{ConnectionMode, Message, Url, WebSocket, futures_util::SinkExt};
+use common::{
+    grid::Grid,
+    proto::{CellState, DrawReason, PlayerStatus, ProposalType},
+};
 
 #[cfg(target_family = "wasm")]
 use bevy::tasks::IoTaskPool;
 #[cfg(not(target_family = "wasm"))]
 use bevy_tokio_tasks::TokioTasksRuntime;
-use futures::StreamExt;
-use serde::{Deserialize, Serialize};
+use futures::{
+    StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::{
-    CellColor, Config, Dot, DotCell, GameAssets, GameOperation, GridTray, MainState, NeedNewBoard, PlayerConfigEntry, VisualGrid,
+    CellColor, Config, CurrentTurn, DotCell, EndGame, GameAssets, GameOperation, GridTray, MAX_PLAYERS, MainState, NeedNewBoard, PlaceDot, PlayerConfigEntry,
+    VisualGrid,
     anim::TargetUiOpacity,
-    menu::MenuRadios,
-    spawn_dot,
-    ui_menu::{HostGameUiTree, JoinGameUiTree, support::fade_out_ui},
+    apply_grid,
+    audio::Sfx,
+    clock::GameClocks,
+    menu::{MainMenuSubState, MenuRadios, MenuState},
+    ui_menu::{HostGameUiTree, JoinGameUiTree, MAX_ONLINE_PLAYERS, support::fade_out_ui},
 };
 
-const SERVER_LIST: [(&str, &str); 3] = [
+const DEFAULT_SERVERS: [(&str, &str); 3] = [
     ("Local server", "ws://localhost:8080"),
     ("LC HQ", "wss://hopdot.lcdev.xyz"),
     ("New LC HQ", "ws://hopdot.lc"),
@@ -31,17 +41,237 @@ const SERVER_LIST: [(&str, &str); 3] = [
 
 #[derive(Clone, Debug, Default, Resource)]
 pub struct ServerUrl {
-    name: String,
-    url: String,
+    pub name: String,
+    pub url: String,
+    /// Picked by the player rather than found by pinging, so it's kept between launches.
+    pub chosen: bool,
+}
+
+/// The server the current online game is on, which may not be the one picked now.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RoomServer(pub Option<ServerUrl>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ServerStatus {
+    #[default]
+    Unknown,
+    Pinging,
+    /// Answered, taking `latency` to open a connection.
+    Online {
+        latency: Duration,
+    },
+    Offline,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerEntry {
+    pub name: String,
+    pub url: String,
+    /// Added by the player, so it can be edited or removed.
+    pub custom: bool,
+    pub status: ServerStatus,
+}
+
+/// Every server the player can pick from: the built-in ones, then the ones they added.
+#[derive(Clone, Debug, Resource)]
+pub struct ServerList(pub Vec<ServerEntry>);
+
+impl Default for ServerList {
+    fn default() -> Self {
+        Self(
+            DEFAULT_SERVERS
+                .iter()
+                .map(|&(name, url)| ServerEntry {
+                    name: name.into(),
+                    url: url.into(),
+                    custom: false,
+                    status: ServerStatus::Unknown,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ServerList {
+    pub fn get(&self, url: &str) -> Option<&ServerEntry> {
+        self.0.iter().find(|x| x.url == url)
+    }
+
+    pub fn add_custom(&mut self, name: String, url: String) {
+        if self.get(&url).is_none() {
+            self.0.push(ServerEntry {
+                name,
+                url,
+                custom: true,
+                status: ServerStatus::Unknown,
+            });
+        }
+    }
+
+    /// Measures every server again.
+    pub fn ping_all(&mut self, tx: &NetServerboundSender) {
+        for server in &mut self.0 {
+            server.status = ServerStatus::Pinging;
+            tx.force_send(NetManagerMessage::Ping { url: server.url.clone() }).unwrap();
+        }
+    }
+
+    /// The first server that answered, which is what gets used until the player picks one.
+    fn first_online(&self) -> Option<&ServerEntry> {
+        self.0.iter().find(|x| matches!(x.status, ServerStatus::Online { .. }))
+    }
+}
+
+/// Checks a server address typed in by the player, returning it cleaned up.
+pub fn parse_server_url(url: &str) -> Result<String, &'static str> {
+    let url = url.trim().trim_end_matches('/');
+    let parsed = Url::parse(url).map_err(|_| "That isn't a valid address")?;
+    if !matches!(parsed.scheme(), "ws" | "wss") {
+        return Err("The address must start with ws:// or wss://");
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err("The address needs a host name");
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err("The address can't have a query or fragment");
+    }
+    Ok(url.to_owned())
+}
+
+/// Picks the first server that answers, unless the player has picked one themselves.
+pub fn pick_server(server_url: &mut ServerUrl, servers: &ServerList) {
+    if server_url.chosen {
+        return;
+    }
+    if let Some(server) = servers.first_online() {
+        (server_url.name, server_url.url) = (server.name.clone(), server.url.clone());
+    }
+}
+
+/// How the connection to the current online game is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub enum ConnectionStatus {
+    #[default]
+    Offline,
+    /// Joining the game, or waiting for the other players to.
+    Connecting,
+    Connected,
+    /// The connection dropped, and is being opened again.
+    Reconnecting {
+        attempt: u32,
+    },
+    Lost,
+}
+
+/// Who's in the room being hosted or joined, while it fills up.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct RoomLobby {
+    pub joined: u8,
+    /// How many players the room is for, or 0 outside of a room.
+    pub capacity: u8,
+}
+
+/// Something the server said about the game that the player should hear about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnlineNotice {
+    Eliminated {
+        player: u8,
+        reason: LeaveReason,
+    },
+    /// A move was played when it wasn't this device's turn, so the server ignored it.
+    OutOfTurn,
+    /// The server ignored a move it couldn't play at all.
+    Rejected(MoveError),
+    /// A player's connection dropped, and the server is holding their seat for a while.
+    Away {
+        player: u8,
+    },
+    Back {
+        player: u8,
+    },
+    /// This device reconnected, and the board was brought up to date.
+    Rejoined,
+    /// The server turned down a move and sent its board to replace ours.
+    Resynced,
+    DrawOffered {
+        player: u8,
+    },
+    /// Someone turned down or took back a draw offer.
+    DrawCalledOff {
+        player: u8,
+    },
+}
+
+/// What the server has said about the current online game. It has the final say on whose turn it is and who won.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct OnlineGame {
+    /// This device's player, once the game has started.
+    pub me: Option<u8>,
+    /// How many players haven't joined yet.
+    pub waiting_for: u8,
+    /// Whose turn it is, unless a move has been played since the server last said.
+    pub turn: Option<u8>,
+    pub eliminated: Vec<(u8, LeaveReason)>,
+    /// Players whose connection dropped, who still have time to come back.
+    pub away: Vec<u8>,
+    pub winner: Option<u8>,
+    pub draw: Option<DrawReason>,
+    /// Players who have offered or agreed to a draw. It's a draw once everyone still playing has.
+    pub draw_offers: Vec<u8>,
+    /// The latest notice, and when it was given.
+    pub notice: Option<(OnlineNotice, f64)>,
+    /// Watching the game rather than playing in it.
+    pub spectating: bool,
+    /// The board a spectator was sent, until there's a board of the right size to show it on.
+    pub snapshot: Option<Grid>,
+}
+
+impl OnlineGame {
+    /// Whether the server has said where this device sits, as a player or watching, and the board is up to date.
+    pub fn seated(&self) -> bool {
+        (self.me.is_some() || self.spectating) && self.snapshot.is_none()
+    }
+
+    pub fn leave_reason(&self, player: usize) -> Option<LeaveReason> {
+        self.eliminated.iter().find(|x| usize::from(x.0) == player).map(|x| x.1)
+    }
+
+    /// Whether this device has a seat in a game that's still going, so can resign or offer a draw.
+    pub fn playing(&self) -> bool {
+        let still_in = self.me.is_some_and(|me| self.leave_reason(me.into()).is_none());
+        still_in && self.seated() && self.waiting_for == 0 && self.winner.is_none() && self.draw.is_none()
+    }
 }
 
+/// A game event from the server, for [`run_online_game`] to apply.
+#[derive(Message, Clone, Debug)]
+pub struct GameEvent(pub GameClientbound);
+
 pub fn plugin(app: &mut App) {
     app.init_resource::<ServerUrl>()
+        .init_resource::<ServerList>()
+        .init_resource::<RoomServer>()
+        .init_resource::<ConnectionStatus>()
         .add_systems(PreStartup, setup_channel)
         .add_systems(Startup, start_net_manager)
-        .add_systems(Update, process_net_inbound)
+        .init_resource::<NetRequest>()
+        .init_resource::<OnlineGame>()
+        .init_resource::<RoomLobby>()
+        .add_systems(
+            Update,
+            (
+                process_net_inbound,
+                run_online_game,
+                apply_online_snapshot,
+                run_net_request,
+                forget_finished_game,
+            )
+                .chain(),
+        )
+        .add_systems(OnExit(MainState::DimForUi), cancel_lobby)
         .add_systems(Last, maybe_shutdown)
-        .add_message::<NetMessage>();
+        .add_message::<NetMessage>()
+        .add_message::<GameEvent>();
 }
 
 fn maybe_shutdown(mut app_exit_reader: MessageReader<AppExit>, s_s: Res<NetServerboundSender>) {
@@ -50,15 +280,76 @@ fn maybe_shutdown(mut app_exit_reader: MessageReader<AppExit>, s_s: Res<NetServe
     }
 }
 
+/// Something that went wrong talking to a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetError {
+    /// The server's address can't be connected to at all.
+    InvalidServer,
+    Unreachable,
+    /// The server didn't answer in time.
+    TimedOut,
+    /// The server sent something that couldn't be understood.
+    BadMessage,
+    /// The connection dropped.
+    Disconnected,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidServer => "the server address isn't valid",
+            Self::Unreachable => "couldn't reach the server",
+            Self::TimedOut => "the server didn't answer in time",
+            Self::BadMessage => "the server sent something unexpected",
+            Self::Disconnected => "the connection dropped",
+        })
+    }
+}
+
 #[derive(Message)]
 pub enum NetMessage {
-    RoomCreated { code: String },
+    RoomCreated {
+        code: String,
+    },
     RoomNotFound,
+    NoPublicRooms,
+    /// The server turned the request down, such as for a room it won't host.
+    Refused {
+        reason: String,
+    },
+    /// The request failed, and will be tried again shortly.
+    Retrying {
+        attempt: u32,
+        error: NetError,
+    },
+    /// The request failed for good.
+    Failed(NetError),
+    /// Something went wrong, but nothing had to stop because of it.
+    Warning(NetError),
 }
 
 pub enum NetMessageClientbound {
-    PingFailed(usize),
-    PingSucceeded(usize),
+    PingFailed {
+        url: String,
+    },
+    PingSucceeded {
+        url: String,
+        latency: Duration,
+    },
+    /// A socket opened for the latest request.
+    Connected {
+        connection: u32,
+    },
+    /// The latest request couldn't be sent.
+    Failed {
+        error: NetError,
+    },
+    /// A socket closed, whether or not it was meant to.
+    Dropped {
+        connection: u32,
+    },
+    /// A message from the server was skipped because it couldn't be decoded.
+    BadMessage,
     RoomCreated {
         code: String,
     },
@@ -68,16 +359,17 @@ pub enum NetMessageClientbound {
         server: ServerUrl,
     },
     RoomNotFound,
-
-    GameStart {
-        me: u8,
+    NoPublicRooms,
+    Refused {
+        reason: String,
     },
-    Move {
-        player: u8,
-        x: u8,
-        y: u8,
+    LobbyPlayers {
+        joined: u8,
+        capacity: u8,
     },
 
+    Game(GameClientbound),
+
     #[cfg(not(target_family = "wasm"))]
     Spawn(Pin<Box<dyn Future<Output = ()> + Send + Sync>>),
 }
@@ -88,14 +380,39 @@ pub struct NetClientboundSender(Sender<NetMessageClientbound>);
 #[derive(Deref, Resource)]
 pub struct NetClientboundReceiver(Receiver<NetMessageClientbound>);
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum NetManagerMessage {
-    Ping(usize),
-    HostGame { settings: GameSettings, server: ServerUrl },
-    JoinLobby { code: String, server: ServerUrl },
+    Ping {
+        url: String,
+    },
+    HostGame {
+        settings: GameSettings,
+        server: ServerUrl,
+    },
+    JoinLobby {
+        code: String,
+        server: ServerUrl,
+    },
+    JoinPublic {
+        server: ServerUrl,
+    },
     CancelLobby,
-    JoinGame { code: String, server: ServerUrl },
-    Move { x: u8, y: u8 },
+    /// Takes a seat in a game, or takes back the seat given `token` if the connection dropped.
+    JoinGame {
+        code: String,
+        server: ServerUrl,
+        token: Option<String>,
+    },
+    WatchGame {
+        code: String,
+        server: ServerUrl,
+    },
+    Move {
+        x: u8,
+        y: u8,
+    },
+    /// Anything else said in a game, like resigning or offering a draw.
+    Game(GameServerbound),
     Shutdown,
 }
 
@@ -105,49 +422,180 @@ pub struct NetServerboundSender(Sender<NetManagerMessage>);
 #[derive(Deref, Resource)]
 pub struct NetServerboundReceiver(Receiver<NetManagerMessage>);
 
+/// The lobby or game request being worked on, kept so it can be sent again if it fails.
+#[derive(Debug, Default, Resource)]
+pub struct NetRequest {
+    request: Option<NetManagerMessage>,
+    /// The socket opened for the request. Older sockets closing doesn't matter any more.
+    connection: Option<u32>,
+    /// While waiting for the server to answer, when to give up on it.
+    deadline: Option<f64>,
+    /// After a failure, when to try again.
+    retry_at: Option<f64>,
+    /// How many times in a row the request has failed.
+    failures: u32,
+}
+
+impl NetRequest {
+    /// Sends a new request, replacing any earlier one.
+    pub fn send(&mut self, request: NetManagerMessage, tx: &NetServerboundSender, time: &Time) {
+        *self = Self {
+            request: Some(request),
+            ..default()
+        };
+        self.resend(tx, time.elapsed_secs_f64());
+    }
+
+    /// Gives up on the request, closing its lobby if it has one.
+    pub fn cancel(&mut self, tx: &NetServerboundSender) {
+        if self.request.is_some() && !self.is_game() {
+            tx.force_send(NetManagerMessage::CancelLobby).unwrap();
+        }
+        *self = default();
+    }
+
+    /// Hosting or joining a room that hasn't started yet.
+    pub fn in_lobby(&self) -> bool {
+        matches!(
+            self.request,
+            Some(NetManagerMessage::HostGame { .. } | NetManagerMessage::JoinLobby { .. } | NetManagerMessage::JoinPublic { .. })
+        )
+    }
+
+    /// Sends the session token along if the game has to be joined again, to get the same seat back.
+    fn set_token(&mut self, new_token: String) {
+        if let Some(NetManagerMessage::JoinGame { token, .. }) = &mut self.request {
+            *token = Some(new_token);
+        }
+    }
+
+    fn is_game(&self) -> bool {
+        matches!(self.request, Some(NetManagerMessage::JoinGame { .. } | NetManagerMessage::WatchGame { .. }))
+    }
+
+    fn resend(&mut self, tx: &NetServerboundSender, now: f64) {
+        if let Some(request) = &self.request {
+            tx.force_send(request.clone()).unwrap();
+            self.connection = None;
+            self.deadline = Some(now + REQUEST_TIMEOUT);
+            self.retry_at = None;
+        }
+    }
+
+    /// The server answered, so there's nothing more to wait for.
+    fn answered(&mut self) {
+        self.deadline = None;
+        self.failures = 0;
+    }
+
+    /// Schedules another try, or gives up if there have been too many. Returns whether it gave up.
+    fn failed(&mut self, error: NetError, now: f64, net_messages: &mut MessageWriter<NetMessage>) -> bool {
+        warn!("request failed: {error} ({:?})", self.request);
+        self.connection = None;
+        self.deadline = None;
+        self.failures += 1;
+        if self.failures > MAX_RETRIES {
+            self.request = None;
+            self.retry_at = None;
+            net_messages.write(NetMessage::Failed(error));
+            return true;
+        }
+        self.retry_at = Some(now + RETRY_BACKOFF * 2f64.powi(self.failures as i32 - 1));
+        net_messages.write(NetMessage::Retrying { attempt: self.failures, error });
+        false
+    }
+}
+
+/// How long the server has to answer a request before it's tried again.
+const REQUEST_TIMEOUT: f64 = 10.0;
+/// How long to wait before the first retry, doubling each time after.
+const RETRY_BACKOFF: f64 = 1.0;
+pub const MAX_RETRIES: u32 = 4;
+/// How long to show that the connection was lost before leaving the game.
+const LOST_GAME_DELAY: f32 = 3.0;
+
 fn process_net_inbound(
-    (r_c, s_s): (Res<NetClientboundReceiver>, Res<NetServerboundSender>),
-    mut server_url: ResMut<ServerUrl>,
+    r_c: Res<NetClientboundReceiver>,
+    (mut server_url, mut servers, mut room_server): (ResMut<ServerUrl>, ResMut<ServerList>, ResMut<RoomServer>),
     #[cfg(not(target_family = "wasm"))] runtime: Res<TokioTasksRuntime>,
-    (mut config, mut radios): (ResMut<Config>, ResMut<MenuRadios>),
+    (mut config, mut radios, mut room_lobby): (ResMut<Config>, ResMut<MenuRadios>, ResMut<RoomLobby>),
     mut message_writer: MessageWriter<NetMessage>,
     mut commands: Commands,
     mut ui_opacity: ResMut<TargetUiOpacity>,
     (host_ui_tree, join_ui_tree): (Query<Entity, With<HostGameUiTree>>, Query<Entity, With<JoinGameUiTree>>),
-    grid: Res<VisualGrid>,
-    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
-    game_assets: Res<GameAssets>,
-    grid_tray: Query<Entity, With<GridTray>>,
-    (mut next_game_state, mut need_new_board): (ResMut<NextState<GameOperation>>, ResMut<NextState<NeedNewBoard>>),
-    mut local_me: Local<u8>,
+    (mut game_events, mut online, mut clocks): (MessageWriter<GameEvent>, ResMut<OnlineGame>, ResMut<GameClocks>),
+    (mut need_new_board, main_state): (ResMut<NextState<NeedNewBoard>>, Res<State<MainState>>),
+    (mut connection_status, mut request, s_s, time): (ResMut<ConnectionStatus>, ResMut<NetRequest>, Res<NetServerboundSender>, Res<Time>),
 ) {
+    let now = time.elapsed_secs_f64();
     while let Ok(message) = r_c.try_recv() {
         match message {
-            NetMessageClientbound::PingFailed(x) => {
-                if x + 1 < SERVER_LIST.len() {
-                    s_s.force_send(NetManagerMessage::Ping(x + 1)).unwrap();
+            NetMessageClientbound::PingFailed { url } => {
+                if let Some(server) = servers.0.iter_mut().find(|x| x.url == url) {
+                    server.status = ServerStatus::Offline;
+                }
+            }
+            NetMessageClientbound::PingSucceeded { url, latency } => {
+                if let Some(server) = servers.0.iter_mut().find(|x| x.url == url) {
+                    server.status = ServerStatus::Online { latency };
+                }
+                pick_server(&mut server_url, &servers);
+            }
+            NetMessageClientbound::Connected { connection } => {
+                request.connection = Some(connection);
+            }
+            NetMessageClientbound::Failed { error } => {
+                fail_request(&mut request, error, now, &mut message_writer, &mut commands, &mut connection_status);
+            }
+            NetMessageClientbound::Dropped { connection } => {
+                if request.connection != Some(connection) {
+                    continue; // A socket that was closed on purpose, or already given up on
                 }
+                fail_request(
+                    &mut request,
+                    NetError::Disconnected,
+                    now,
+                    &mut message_writer,
+                    &mut commands,
+                    &mut connection_status,
+                );
             }
-            NetMessageClientbound::PingSucceeded(x) => {
-                (server_url.name, server_url.url) = (SERVER_LIST[x].0.into(), SERVER_LIST[x].1.into());
+            NetMessageClientbound::BadMessage => {
+                message_writer.write(NetMessage::Warning(NetError::BadMessage));
             }
             NetMessageClientbound::RoomCreated { code } => {
+                request.answered();
                 message_writer.write(NetMessage::RoomCreated { code });
             }
+            NetMessageClientbound::LobbyPlayers { joined, capacity } => {
+                *room_lobby = RoomLobby { joined, capacity };
+            }
             NetMessageClientbound::RoomReady { code, settings, server } => {
                 info!("RoomReady {{ ... }}");
+                *room_lobby = RoomLobby::default();
+                let capacity = usize::from(settings.capacity);
+                if !(2..=MAX_ONLINE_PLAYERS).contains(&settings.capacity) {
+                    error!("can't play a room for {capacity} players");
+                    request.cancel(&s_s);
+                    message_writer.write(NetMessage::Failed(NetError::BadMessage));
+                    continue;
+                }
                 let Some(play_mode) = radios.radios.get_mut("game-type") else {
                     return;
                 };
                 play_mode.disable();
                 config.grid_size = (settings.width.into(), settings.height.into());
-                config.players = vec![
-                    PlayerConfigEntry::default_for_player(1).as_human().as_online(),
-                    PlayerConfigEntry::default_for_player(2).as_human().as_online(),
-                ];
+                // Everyone starts out as someone else, until the server says which seat is ours
+                config.players = (1..=capacity)
+                    .map(|player| PlayerConfigEntry::default_for_player(player).as_human().as_online())
+                    .collect();
                 need_new_board.set(NeedNewBoard(true));
+                *connection_status = ConnectionStatus::Connecting;
+                *online = OnlineGame::default();
+                *clocks = GameClocks::default();
+                room_server.0 = Some(server.clone());
+                request.send(NetManagerMessage::JoinGame { code, server, token: None }, &s_s, &time);
                 commands.spawn_task(|| async move {
-                    fetch!(NetServerboundSender).with(|s_s| s_s.force_send(NetManagerMessage::JoinGame { code, server }).unwrap());
                     AsyncWorld.sleep(1.5).await;
                     fetch!(NextState<MainState>).with(|x| x.set(MainState::Game));
                     Ok(())
@@ -157,33 +605,69 @@ fn process_net_inbound(
                 info!("{:?}", config.players);
             }
             NetMessageClientbound::RoomNotFound => {
+                request.cancel(&s_s);
                 message_writer.write(NetMessage::RoomNotFound);
             }
+            NetMessageClientbound::NoPublicRooms => {
+                request.cancel(&s_s);
+                message_writer.write(NetMessage::NoPublicRooms);
+            }
+            NetMessageClientbound::Refused { reason } => {
+                request.cancel(&s_s);
+                message_writer.write(NetMessage::Refused { reason });
+            }
 
-            NetMessageClientbound::GameStart { me } => {
-                info!("GameStart {{ me: {me} }}");
-                *local_me = me;
-                config.players[me as usize - 1].set_online(false);
-                info!("{:?}", config.players);
+            NetMessageClientbound::Game(GameClientbound::Spectating {
+                width,
+                height,
+                players,
+                grid: board,
+            }) => {
+                request.answered();
+                let fits = (2..=MAX_PLAYERS).contains(&usize::from(players)) && width > 0 && height > 0;
+                let Some(board) = decode_grid(&board, width.into(), height.into(), players).filter(|_| fits) else {
+                    error!("can't show a {width}x{height} game for {players} players");
+                    request.cancel(&s_s);
+                    message_writer.write(NetMessage::Failed(NetError::BadMessage));
+                    continue;
+                };
+                *online = OnlineGame {
+                    spectating: true,
+                    snapshot: Some(board),
+                    ..default()
+                };
+                *clocks = GameClocks::default();
+                *connection_status = ConnectionStatus::Connected;
+                let grid_size = (width.into(), height.into());
+                if **main_state == MainState::Game && config.grid_size == grid_size {
+                    continue; // Back after reconnecting, so the board already up just needs catching up
+                }
+                if let Some(play_mode) = radios.radios.get_mut("game-type") {
+                    play_mode.disable();
+                }
+                config.grid_size = grid_size;
+                // Nobody here is playing, so every move comes from the server
+                config.players = (1..=usize::from(players))
+                    .map(|player| PlayerConfigEntry::default_for_player(player).as_human().as_online())
+                    .collect();
+                need_new_board.set(NeedNewBoard(true));
+                commands.spawn_task(|| async move {
+                    AsyncWorld.sleep(1.5).await;
+                    fetch!(NextState<MainState>).with(|x| x.set(MainState::Game));
+                    Ok(())
+                });
+                fade_out_ui(&mut commands, &mut ui_opacity, &join_ui_tree);
             }
-            NetMessageClientbound::Move { player, x, y } => {
-                if player == *local_me {
-                    continue; // Skip
-                }
-                let entity = grid[y as usize][x as usize];
-                let (
-                    _,
-                    mut color,
-                    Transform {
-                        translation: Vec3 { x, z, .. },
-                        ..
-                    },
-                ) = cells.get_mut(entity).unwrap();
-                commands
-                    .entity(entity)
-                    .with_related::<Dot>((spawn_dot(*x, *z, &game_assets), ChildOf(grid_tray.single().unwrap())));
-                color.player = player as usize;
-                next_game_state.set(GameOperation::Animating);
+            NetMessageClientbound::Game(event) => {
+                match event {
+                    GameClientbound::GameStart { ref token, .. } => {
+                        request.answered();
+                        request.set_token(token.clone());
+                    }
+                    GameClientbound::Rejoined { .. } => request.answered(),
+                    _ => {}
+                }
+                game_events.write(GameEvent(event));
             }
 
             #[cfg(not(target_family = "wasm"))]
@@ -194,6 +678,298 @@ fn process_net_inbound(
     }
 }
 
+/// Applies what the server says happened in the game.
+fn run_online_game(
+    mut events: MessageReader<GameEvent>,
+    mut online: ResMut<OnlineGame>,
+    mut config: ResMut<Config>,
+    mut connection_status: ResMut<ConnectionStatus>,
+    mut place_dot: MessageWriter<PlaceDot>,
+    (game_op, mut next_game_op, current_turn): (
+        Option<Res<State<GameOperation>>>,
+        ResMut<NextState<GameOperation>>,
+        Option<Res<State<CurrentTurn>>>,
+    ),
+    (mut commands, grid, mut cells, game_assets, grid_tray): (
+        Commands,
+        Res<VisualGrid>,
+        Query<(&DotCell, &mut CellColor, &Transform)>,
+        Res<GameAssets>,
+        Query<Entity, With<GridTray>>,
+    ),
+    (mut sfx, mut clocks): (MessageWriter<Sfx>, ResMut<GameClocks>),
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs_f64();
+    // Once the board has settled, whoever's turn it is has to be worked out again to take in the change
+    let settled = game_op.as_ref().is_some_and(|x| **x != GameOperation::Animating);
+    for GameEvent(event) in events.read() {
+        match *event {
+            GameClientbound::WaitingFor { players } => {
+                online.waiting_for = players;
+            }
+            GameClientbound::GameStart { me, .. } => {
+                info!("GameStart {{ me: {me} }}");
+                online.me = Some(me);
+                online.waiting_for = 0;
+                if let Some(player) = config.players.get_mut(usize::from(me).wrapping_sub(1)) {
+                    player.set_online(false);
+                }
+                *connection_status = ConnectionStatus::Connected;
+                info!("{:?}", config.players);
+            }
+            GameClientbound::Clocks { ref remaining_ms } => {
+                clocks.set_remaining(remaining_ms);
+            }
+            GameClientbound::Turn { player } => {
+                // Turns only start once everyone's in
+                online.waiting_for = 0;
+                online.turn = Some(player);
+                clocks.running = Some(player.into());
+                if settled
+                    && (current_turn.as_ref().is_none_or(|x| x.0 != usize::from(player)) || game_op.as_ref().is_some_and(|x| **x == GameOperation::Connecting))
+                {
+                    next_game_op.set(GameOperation::Animating);
+                }
+            }
+            GameClientbound::Move { player, x, y } => {
+                // Whose turn is next will come after the move
+                online.turn = None;
+                clocks.running = None;
+                if Some(player) == online.me {
+                    continue; // Already played here
+                }
+                if let Some(snapshot) = &mut online.snapshot {
+                    // Not on screen yet, so play it on the board that will be
+                    if let (Some(next), _) = snapshot.with_move(x, y, player) {
+                        *snapshot = next;
+                    }
+                    continue;
+                }
+                place_dot.write(PlaceDot {
+                    player: player as usize,
+                    x: x as usize,
+                    y: y as usize,
+                });
+            }
+            GameClientbound::OutOfTurn => {
+                online.notice = Some((OnlineNotice::OutOfTurn, now));
+            }
+            GameClientbound::Rejected { error } => {
+                warn!("the server rejected a move: {error:?}");
+                online.notice = Some((OnlineNotice::Rejected(error), now));
+            }
+            GameClientbound::InvalidMove { grid: ref board } => {
+                let Some(board) = decode_grid(board, grid.width(), grid.height(), config.players.len() as u8) else {
+                    warn!("the server's board doesn't fit this one");
+                    continue;
+                };
+                if let Ok(grid_tray) = grid_tray.single() {
+                    apply_grid(&mut commands, &board, &grid, &mut cells, &game_assets, grid_tray);
+                }
+                // The move didn't count, so it's still this device's turn
+                online.turn = online.me;
+                online.notice = Some((OnlineNotice::Resynced, now));
+                next_game_op.set(GameOperation::Animating);
+            }
+            GameClientbound::Spectating { .. } => {} // Handled as it arrives, since it sets up the game
+            GameClientbound::Rejoined { me, grid: ref board } => {
+                info!("Rejoined {{ me: {me} }}");
+                let Some(board) = decode_grid(board, grid.width(), grid.height(), config.players.len() as u8) else {
+                    warn!("the server's board doesn't fit this one");
+                    continue;
+                };
+                if let Ok(grid_tray) = grid_tray.single() {
+                    apply_grid(&mut commands, &board, &grid, &mut cells, &game_assets, grid_tray);
+                }
+                online.me = Some(me);
+                // Whose turn it is, and who's away, follow
+                online.turn = None;
+                online.away.clear();
+                online.notice = Some((OnlineNotice::Rejoined, now));
+                *connection_status = ConnectionStatus::Connected;
+                next_game_op.set(GameOperation::Animating);
+            }
+            GameClientbound::PlayerStatus { player, status } => match status {
+                PlayerStatus::Disconnected if !online.away.contains(&player) => {
+                    online.away.push(player);
+                    online.notice = Some((OnlineNotice::Away { player }, now));
+                }
+                PlayerStatus::Normal if online.away.contains(&player) => {
+                    online.away.retain(|&x| x != player);
+                    online.notice = Some((OnlineNotice::Back { player }, now));
+                }
+                _ => {}
+            },
+            GameClientbound::PlayerEliminated { player, reason } => {
+                online.away.retain(|&x| x != player);
+                online.draw_offers.retain(|&x| x != player);
+                if online.leave_reason(player.into()).is_some() {
+                    continue;
+                }
+                online.eliminated.push((player, reason));
+                online.notice = Some((OnlineNotice::Eliminated { player, reason }, now));
+                // Knock-outs on the board already have their sound from the cascade
+                if !matches!(reason, LeaveReason::NoLegalMoves) {
+                    sfx.write(Sfx::Elimination);
+                }
+            }
+            GameClientbound::GameWin { player } => {
+                online.winner = Some(player);
+                clocks.running = None;
+                if settled {
+                    next_game_op.set(GameOperation::Animating);
+                }
+            }
+            GameClientbound::RemoteProposal {
+                player,
+                kind: ProposalType::Draw,
+            } => {
+                if !online.draw_offers.contains(&player) {
+                    online.draw_offers.push(player);
+                }
+                if Some(player) != online.me {
+                    online.notice = Some((OnlineNotice::DrawOffered { player }, now));
+                }
+            }
+            GameClientbound::ProposalRefused {
+                player,
+                kind: ProposalType::Draw,
+            } => {
+                online.draw_offers.clear();
+                online.notice = Some((OnlineNotice::DrawCalledOff { player }, now));
+            }
+            // Resigning is never put to the other players
+            GameClientbound::RemoteProposal {
+                kind: ProposalType::Resign, ..
+            }
+            | GameClientbound::ProposalRefused {
+                kind: ProposalType::Resign, ..
+            } => {}
+            // What it led to comes right after
+            GameClientbound::ProposalAccepted { .. } => online.draw_offers.clear(),
+            GameClientbound::GameDraw { reason } => {
+                online.draw = Some(reason);
+                online.draw_offers.clear();
+                clocks.running = None;
+                if settled {
+                    next_game_op.set(GameOperation::Animating);
+                }
+            }
+        }
+    }
+}
+
+/// Shows a spectator the board they were sent, once the board on screen is ready for it.
+fn apply_online_snapshot(
+    mut commands: Commands,
+    mut online: ResMut<OnlineGame>,
+    (main_state, need_new_board, mut next_game_op): (Res<State<MainState>>, Res<State<NeedNewBoard>>, ResMut<NextState<GameOperation>>),
+    (grid, mut cells, game_assets, grid_tray): (
+        Res<VisualGrid>,
+        Query<(&DotCell, &mut CellColor, &Transform)>,
+        Res<GameAssets>,
+        Query<Entity, With<GridTray>>,
+    ),
+) {
+    let Some(board) = &online.snapshot else {
+        return;
+    };
+    // The board is rebuilt on the way into the game, so wait for the new one
+    let built = grid.width() == usize::from(board.width()) && grid.height() == usize::from(board.height()) && cells.get(grid[0][0]).is_ok();
+    if **main_state != MainState::Game || need_new_board.0 || !built {
+        return;
+    }
+    let Ok(grid_tray) = grid_tray.single() else {
+        return;
+    };
+    apply_grid(&mut commands, board, &grid, &mut cells, &game_assets, grid_tray);
+    online.snapshot = None;
+    next_game_op.set(GameOperation::Animating);
+}
+
+/// Reads the boards the server sends, such as with [`GameClientbound::InvalidMove`], one byte per cell.
+fn decode_grid(cells: &[u8], width: usize, height: usize, num_players: u8) -> Option<Grid> {
+    if cells.len() != width * height {
+        return None;
+    }
+    let mut grid = Grid::new(width as u8, height as u8, num_players);
+    grid.init_capacity();
+    for (i, &cell) in cells.iter().enumerate() {
+        let state = CellState::from_inner(cell);
+        let target = &mut grid[i / width][i % width];
+        target.owner = state.owner().map_or(0, u8::from);
+        target.dots = state.count().get();
+    }
+    Some(grid)
+}
+
+/// Tries requests again once their backoff is up, and gives up on ones the server hasn't answered.
+fn run_net_request(
+    time: Res<Time>,
+    mut request: ResMut<NetRequest>,
+    tx: Res<NetServerboundSender>,
+    mut net_messages: MessageWriter<NetMessage>,
+    mut connection_status: ResMut<ConnectionStatus>,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_secs_f64();
+    if request.deadline.is_some_and(|x| now >= x) {
+        fail_request(&mut request, NetError::TimedOut, now, &mut net_messages, &mut commands, &mut connection_status);
+    }
+    if request.retry_at.is_some_and(|x| now >= x) {
+        request.resend(&tx, now);
+    }
+}
+
+/// Stops reconnecting to a game that has finished, or that the player has left for another.
+fn forget_finished_game(mut request: ResMut<NetRequest>, tx: Res<NetServerboundSender>, config: Res<Config>, end_game: Option<Res<State<EndGame>>>) {
+    if request.is_game() && (end_game.is_some_and(|x| x.game_ended) || !config.players.iter().any(PlayerConfigEntry::online)) {
+        request.cancel(&tx);
+    }
+}
+
+/// Leaving the host and join menus (other than into the game) closes the lobby.
+fn cancel_lobby(mut request: ResMut<NetRequest>, tx: Res<NetServerboundSender>, mut room_lobby: ResMut<RoomLobby>) {
+    if !request.is_game() {
+        request.cancel(&tx);
+        *room_lobby = RoomLobby::default();
+    }
+}
+
+/// In a game, a failure means reconnecting, or leaving the game once there's no hope of that.
+fn fail_request(
+    request: &mut NetRequest,
+    error: NetError,
+    now: f64,
+    net_messages: &mut MessageWriter<NetMessage>,
+    commands: &mut Commands,
+    connection_status: &mut ConnectionStatus,
+) {
+    if request.request.is_none() {
+        return; // Cancelled while it was being sent
+    }
+    let in_game = request.is_game();
+    let gave_up = request.failed(error, now, net_messages);
+    if in_game && gave_up {
+        lose_game(commands, connection_status);
+    } else if in_game {
+        *connection_status = ConnectionStatus::Reconnecting { attempt: request.failures };
+    }
+}
+
+/// Gives up on the game and goes back to the online menu, after a moment to show why.
+fn lose_game(commands: &mut Commands, connection_status: &mut ConnectionStatus) {
+    *connection_status = ConnectionStatus::Lost;
+    commands.spawn_task(|| async move {
+        AsyncWorld.sleep(LOST_GAME_DELAY).await;
+        fetch!(NextState<MainState>).with(|x| x.set(MainState::Menu));
+        fetch!(NextState<MenuState>).with(|x| x.set(MenuState::Main(Some(MainMenuSubState::Online))));
+        fetch!(NextState<NeedNewBoard>).with(|x| x.set(NeedNewBoard(true)));
+        Ok(())
+    });
+}
+
 fn setup_channel(mut commands: Commands) {
     let (s_c, r_c) = async_channel::unbounded();
     let (s_s, r_s) = async_channel::unbounded();
@@ -208,6 +984,7 @@ fn start_net_manager(
     s_s: Res<NetServerboundSender>,
     r_s: Res<NetServerboundReceiver>,
     #[cfg(not(target_family = "wasm"))] runtime: Res<TokioTasksRuntime>,
+    mut servers: ResMut<ServerList>,
 ) {
     let future = net_manager_main(r_s.0.clone(), s_c.0.clone());
 
@@ -216,7 +993,7 @@ fn start_net_manager(
     #[cfg(target_family = "wasm")]
     IoTaskPool::get().spawn(future).detach();
 
-    s_s.force_send(NetManagerMessage::Ping(0)).unwrap();
+    servers.ping_all(&s_s);
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -224,6 +1001,88 @@ pub struct GameSettings {
     pub capacity: u8,
     pub width: u8,
     pub height: u8,
+    #[serde(default)]
+    pub clock: TimeControl,
+    #[serde(default)]
+    pub rules: Rules,
+    /// Listed for anyone to join without the code.
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default = "default_spectators")]
+    pub spectators: bool,
+}
+
+const fn default_spectators() -> bool {
+    true
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            capacity: 2,
+            width: 6,
+            height: 6,
+            clock: TimeControl::default(),
+            rules: Rules::default(),
+            public: false,
+            spectators: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum TimeControl {
+    #[default]
+    Unlimited,
+    /// Everyone starts with `base` seconds, and gains `increment` after each of their moves.
+    Fischer { base: u32, increment: u32 },
+    /// Every move has to be made within `secs` seconds. Time left over isn't kept.
+    PerMove { secs: u32 },
+    /// As [`TimeControl::PerMove`], but with `days` for each move.
+    Correspondence { days: u32 },
+}
+
+impl TimeControl {
+    pub fn name(self) -> String {
+        match self {
+            Self::Unlimited => "Unlimited".into(),
+            Self::Fischer { base, increment } => format!("{}+{increment}", base / 60),
+            Self::PerMove { secs } => format!("{secs}s a move"),
+            Self::Correspondence { days: 1 } => "1 day a move".into(),
+            Self::Correspondence { days } => format!("{days} days a move"),
+        }
+    }
+
+    /// What a clock starts at, or is reset to each move, in seconds. `None` without a clock.
+    pub fn allowance(self) -> Option<f64> {
+        match self {
+            Self::Unlimited => None,
+            Self::Fischer { base, .. } => Some(base.into()),
+            Self::PerMove { secs } => Some(secs.into()),
+            Self::Correspondence { days } => Some(f64::from(days) * 24.0 * 60.0 * 60.0),
+        }
+    }
+}
+
+/// When a player is knocked out of an online game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rules {
+    /// Once there's nowhere left for them to play: no empty cells, and none of their own.
+    #[default]
+    LastStand,
+    /// As soon as they've lost every cell, once they've had a turn.
+    Classic,
+}
+
+impl Rules {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LastStand => "Last stand",
+            Self::Classic => "Classic",
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -231,6 +1090,7 @@ pub struct GameSettings {
 pub enum LobbyServerbound {
     New(GameSettings),
     Join { code: String },
+    JoinPublic,
 }
 
 #[derive(Debug, Deserialize)]
@@ -239,26 +1099,81 @@ pub enum LobbyClientbound {
     Created { code: String },
     Ready { code: String, settings: GameSettings },
     RoomNotFound { code: String },
+    NoPublicRooms,
+    InvalidSettings { reason: String },
+    Rejected { error: LobbyError },
+    Players { joined: u8, capacity: u8 },
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum LobbyError {
+    MalformedCode,
+    AlreadyInRoom,
+}
+
+impl fmt::Display for LobbyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedCode => f.write_str("that isn't a game code"),
+            Self::AlreadyInRoom => f.write_str("already waiting in a room"),
+        }
+    }
+}
+
+/// Why the server turned down a move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum MoveError {
+    OutOfBounds { x: u8, y: u8, width: u8, height: u8 },
+    NotPlaying,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum GameServerbound {
-    Move { x: u8, y: u8 },
+    Move {
+        x: u8,
+        y: u8,
+    },
     Resign,
+    /// Offers a draw, or agrees to one that's been offered.
+    Propose {
+        kind: ProposalType,
+    },
+    /// Takes back a draw offer, or turns one down.
+    CancelProposal {
+        kind: ProposalType,
+    },
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
 pub enum LeaveReason {
     Disconnected,
     NoLegalMoves,
     Resigned,
+    TimedOut,
+}
+
+impl LeaveReason {
+    /// What happened to the player, to follow their name.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Disconnected => "left the game",
+            Self::NoLegalMoves => "was knocked out",
+            Self::Resigned => "resigned",
+            Self::TimedOut => "ran out of time",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum GameClientbound {
     OutOfTurn,
+    Rejected {
+        error: MoveError,
+    },
     InvalidMove {
         grid: Vec<u8>, // Vec<CellState>
     },
@@ -269,240 +1184,264 @@ pub enum GameClientbound {
     },
     GameStart {
         me: u8,
+        token: String,
+    },
+    Rejoined {
+        me: u8,
+        grid: Vec<u8>, // Vec<CellState>
+    },
+    PlayerStatus {
+        player: u8,
+        status: PlayerStatus,
+    },
+    Spectating {
+        width: u8,
+        height: u8,
+        players: u8,
+        grid: Vec<u8>, // Vec<CellState>
     },
     PlayerEliminated {
         player: u8,
         reason: LeaveReason,
     },
+    RemoteProposal {
+        player: u8,
+        kind: ProposalType,
+    },
+    ProposalRefused {
+        player: u8,
+        kind: ProposalType,
+    },
+    ProposalAccepted {
+        kind: ProposalType,
+    },
     WaitingFor {
         players: u8,
     },
+    Clocks {
+        remaining_ms: Vec<u64>,
+    },
     Turn {
         player: u8,
     },
     GameWin {
         player: u8,
     },
+    GameDraw {
+        reason: DrawReason,
+    },
+}
+
+/// How long to wait for a socket to open.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn connect(url: &str) -> Result<WebSocket, NetError> {
+    let url = Url::parse(url).map_err(|_| NetError::InvalidServer)?;
+    async_wsocket::connect(&url, &ConnectionMode::Direct, CONNECT_TIMEOUT).await.map_err(|e| {
+        error!("{e:?}");
+        #[cfg(not(target_family = "wasm"))]
+        if matches!(e, async_wsocket::Error::Timeout) {
+            return NetError::TimedOut;
+        }
+        NetError::Unreachable
+    })
+}
+
+/// Waits for the next message from the server, skipping (and reporting) any that can't be decoded. `None` once the socket closes.
+async fn next_message<T: DeserializeOwned + fmt::Debug>(ws_rx: &mut SplitStream<WebSocket>, tx: &Sender<NetMessageClientbound>) -> Option<T> {
+    while let Some(x) = ws_rx.next().await {
+        match x {
+            Err(e) => {
+                error!("{e:?}");
+            }
+            Ok(Message::Binary(x)) => match bson::deserialize_from_slice(&x) {
+                Ok(message) => {
+                    info!("{message:?}");
+                    return Some(message);
+                }
+                Err(e) => {
+                    error!("error handling unrecognized server message: {e:?} (message: {x:?})");
+                    let _ = tx.send(NetMessageClientbound::BadMessage).await;
+                }
+            },
+            #[cfg(not(target_family = "wasm"))]
+            Ok(Message::Close(_)) => {}
+            Ok(x) => info!("unhandled websocket message: {x:?}"),
+        }
+    }
+    None
+}
+
+async fn send_message(ws_tx: &mut SplitSink<WebSocket, Message>, message: &impl Serialize) -> Result<(), NetError> {
+    let message = bson::serialize_to_vec(message).map_err(|_| NetError::BadMessage)?;
+    ws_tx.send(Message::Binary(message)).await.map_err(|e| {
+        error!("{e:?}");
+        NetError::Disconnected
+    })
+}
+
+fn spawn_reader(tx: &Sender<NetMessageClientbound>, future: impl Future<Output = ()> + Send + Sync + 'static) {
+    #[cfg(not(target_family = "wasm"))]
+    let _ = tx.force_send(NetMessageClientbound::Spawn(Box::pin(future)));
+    #[cfg(target_family = "wasm")]
+    {
+        let _ = tx;
+        IoTaskPool::get().spawn(future).detach();
+    }
+}
+
+/// Opens a lobby socket and sends `message` down it, passing on what the lobby says back.
+async fn open_lobby(
+    server: ServerUrl,
+    message: LobbyServerbound,
+    connection: u32,
+    tx: &Sender<NetMessageClientbound>,
+) -> Result<SplitSink<WebSocket, Message>, NetError> {
+    let ws = connect(&(server.url.clone() + "/ws/lobby")).await?;
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    send_message(&mut ws_tx, &message).await?;
+    let _ = tx.send(NetMessageClientbound::Connected { connection }).await;
+
+    let reading_future = {
+        let tx = tx.clone(); // so we can move this into the future
+        async move {
+            while let Some(message) = next_message(&mut ws_rx, &tx).await {
+                let message = match message {
+                    LobbyClientbound::Created { code } => NetMessageClientbound::RoomCreated { code },
+                    LobbyClientbound::Ready { code, settings } => NetMessageClientbound::RoomReady {
+                        code,
+                        settings,
+                        server: server.clone(),
+                    },
+                    LobbyClientbound::RoomNotFound { .. } => NetMessageClientbound::RoomNotFound,
+                    LobbyClientbound::NoPublicRooms => NetMessageClientbound::NoPublicRooms,
+                    LobbyClientbound::InvalidSettings { reason } => NetMessageClientbound::Refused { reason },
+                    LobbyClientbound::Rejected { error } => NetMessageClientbound::Refused { reason: error.to_string() },
+                    LobbyClientbound::Players { joined, capacity } => NetMessageClientbound::LobbyPlayers { joined, capacity },
+                };
+                let _ = tx.send(message).await;
+            }
+            let _ = tx.send(NetMessageClientbound::Dropped { connection }).await;
+        }
+    };
+    spawn_reader(tx, reading_future);
+    Ok(ws_tx)
+}
+
+/// Replaces the lobby socket with a fresh one, reporting if it can't be opened.
+async fn reopen_lobby(
+    old: Option<SplitSink<WebSocket, Message>>,
+    server: ServerUrl,
+    message: LobbyServerbound,
+    next_connection: &mut u32,
+    tx: &Sender<NetMessageClientbound>,
+) -> Option<SplitSink<WebSocket, Message>> {
+    if let Some(mut ws) = old {
+        let _ = ws.close().await;
+    }
+    *next_connection += 1;
+    match open_lobby(server, message, *next_connection, tx).await {
+        Ok(ws) => Some(ws),
+        Err(error) => {
+            let _ = tx.send(NetMessageClientbound::Failed { error }).await;
+            None
+        }
+    }
+}
+
+async fn open_game(
+    code: &str,
+    server: &ServerUrl,
+    (watch, token): (bool, Option<&str>),
+    connection: u32,
+    tx: &Sender<NetMessageClientbound>,
+) -> Result<SplitSink<WebSocket, Message>, NetError> {
+    let path = if watch { "watch" } else { "game" };
+    let token = token.map(|x| format!("&token={x}")).unwrap_or_default();
+    let ws = connect(&format!("{}/ws/{path}?id={code}{token}", server.url)).await?;
+    let (ws_tx, mut ws_rx) = ws.split();
+    let _ = tx.send(NetMessageClientbound::Connected { connection }).await;
+
+    let reading_future = {
+        let tx = tx.clone(); // so we can move this into the future
+        async move {
+            while let Some(message) = next_message(&mut ws_rx, &tx).await {
+                let _ = tx.send(NetMessageClientbound::Game(message)).await;
+            }
+            let _ = tx.send(NetMessageClientbound::Dropped { connection }).await;
+        }
+    };
+    spawn_reader(tx, reading_future);
+    Ok(ws_tx)
 }
 
 async fn net_manager_main(rx: Receiver<NetManagerMessage>, tx: Sender<NetMessageClientbound>) {
     let mut lobby_connection = None;
-    let mut game_connection = None;
-    loop {
-        let message = rx.recv().await.unwrap();
+    let mut game_connection: Option<SplitSink<WebSocket, Message>> = None;
+    // Numbers each socket, so the ones closed on purpose can be told apart from the current one dropping
+    let mut next_connection = 0u32;
+    while let Ok(message) = rx.recv().await {
         info!("{message:?}");
         match message {
-            NetManagerMessage::Ping(x) => {
-                let (_, url) = SERVER_LIST[x];
-                let connection = async_wsocket::connect(
-                    &Url::parse(&(String::from(url) + "/ws/lobby")).unwrap(),
-                    &ConnectionMode::Direct,
-                    Duration::from_secs(2),
-                )
-                .await;
-                match connection {
+            NetManagerMessage::Ping { url } => {
+                let start = Instant::now();
+                match connect(&(url.clone() + "/ws/lobby")).await {
                     Ok(mut ws) => {
-                        ws.close().await.unwrap();
-                        tx.send(NetMessageClientbound::PingSucceeded(x)).await.unwrap();
+                        let latency = start.elapsed();
+                        let _ = ws.close().await;
+                        let _ = tx.send(NetMessageClientbound::PingSucceeded { url, latency }).await;
                     }
-                    Err(e) => {
-                        error!("{e:?}");
-                        tx.send(NetMessageClientbound::PingFailed(x)).await.unwrap();
+                    Err(_) => {
+                        let _ = tx.send(NetMessageClientbound::PingFailed { url }).await;
                     }
                 }
             }
             NetManagerMessage::HostGame { settings, server } => {
-                let ws = async_wsocket::connect(
-                    &Url::parse(&(server.url.clone() + "/ws/lobby")).unwrap(),
-                    &ConnectionMode::Direct,
-                    Duration::from_secs(2),
-                )
-                .await
-                .unwrap();
-                let (mut ws_tx, mut ws_rx) = ws.split();
-                ws_tx
-                    .send(Message::Binary(bson::serialize_to_vec(&LobbyServerbound::New(settings)).unwrap()))
-                    .await
-                    .unwrap();
-                lobby_connection = Some(ws_tx);
-
-                let reading_future = {
-                    let tx = tx.clone(); // so we can move this into the future
-                    async move {
-                        let mut the_code = String::new();
-                        while let Some(x) = ws_rx.next().await {
-                            match x {
-                                Err(e) => {
-                                    error!("{e:?}");
-                                }
-                                Ok(message) => {
-                                    match message {
-                                        Message::Binary(x) => {
-                                            let message = bson::deserialize_from_slice(&x).unwrap();
-                                            info!("{message:?}");
-                                            match message {
-                                                LobbyClientbound::Created { code } => {
-                                                    the_code = code.clone();
-                                                    tx.send(NetMessageClientbound::RoomCreated { code }).await.unwrap();
-                                                }
-                                                LobbyClientbound::Ready { settings, .. } => {
-                                                    tx.send(NetMessageClientbound::RoomReady {
-                                                        code: the_code.clone(),
-                                                        settings,
-                                                        server: server.clone(),
-                                                    })
-                                                    .await
-                                                    .unwrap();
-                                                }
-                                                LobbyClientbound::RoomNotFound { .. } => {
-                                                    // shouldn't happen
-                                                }
-                                            }
-                                        }
-                                        #[cfg(not(target_family = "wasm"))]
-                                        Message::Close(_) => {}
-                                        x => info!("unhandled message: {x:?}"),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                };
-                #[cfg(not(target_family = "wasm"))]
-                tx.send(NetMessageClientbound::Spawn(Box::pin(reading_future))).await.unwrap();
-                #[cfg(target_family = "wasm")]
-                IoTaskPool::get().spawn(reading_future).detach();
+                lobby_connection = reopen_lobby(lobby_connection, server, LobbyServerbound::New(settings), &mut next_connection, &tx).await;
             }
             NetManagerMessage::JoinLobby { code, server } => {
-                let ws = async_wsocket::connect(
-                    &Url::parse(&(server.url.clone() + "/ws/lobby")).unwrap(),
-                    &ConnectionMode::Direct,
-                    Duration::from_secs(2),
-                )
-                .await
-                .unwrap();
-                let (mut ws_tx, mut ws_rx) = ws.split();
-                ws_tx
-                    .send(Message::Binary(bson::serialize_to_vec(&LobbyServerbound::Join { code: code.clone() }).unwrap()))
-                    .await
-                    .unwrap();
-                lobby_connection = Some(ws_tx);
-
-                let reading_future = {
-                    let tx = tx.clone(); // so we can move this into the future
-                    async move {
-                        while let Some(x) = ws_rx.next().await {
-                            match x {
-                                Err(e) => {
-                                    error!("{e:?}");
-                                }
-                                Ok(message) => {
-                                    match message {
-                                        Message::Binary(x) => {
-                                            let message = bson::deserialize_from_slice(&x).unwrap();
-                                            info!("{message:?}");
-                                            match message {
-                                                LobbyClientbound::Created { .. } => {
-                                                    // shouldn't happen
-                                                }
-                                                LobbyClientbound::Ready { settings, .. } => {
-                                                    tx.send(NetMessageClientbound::RoomReady {
-                                                        code: code.clone(),
-                                                        settings,
-                                                        server: server.clone(),
-                                                    })
-                                                    .await
-                                                    .unwrap();
-                                                }
-                                                LobbyClientbound::RoomNotFound { .. } => {
-                                                    tx.send(NetMessageClientbound::RoomNotFound).await.unwrap();
-                                                }
-                                            }
-                                        }
-                                        #[cfg(not(target_family = "wasm"))]
-                                        Message::Close(_) => {}
-                                        x => info!("unhandled message: {x:?}"),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                };
-                #[cfg(not(target_family = "wasm"))]
-                tx.send(NetMessageClientbound::Spawn(Box::pin(reading_future))).await.unwrap();
-                #[cfg(target_family = "wasm")]
-                IoTaskPool::get().spawn(reading_future).detach();
+                lobby_connection = reopen_lobby(lobby_connection, server, LobbyServerbound::Join { code }, &mut next_connection, &tx).await;
+            }
+            NetManagerMessage::JoinPublic { server } => {
+                lobby_connection = reopen_lobby(lobby_connection, server, LobbyServerbound::JoinPublic, &mut next_connection, &tx).await;
             }
             NetManagerMessage::CancelLobby => {
                 if let Some(mut ws) = lobby_connection.take() {
-                    ws.close().await.unwrap();
+                    let _ = ws.close().await;
                 }
             }
-            NetManagerMessage::JoinGame { code, server } => {
+            NetManagerMessage::JoinGame { ref code, ref server, .. } | NetManagerMessage::WatchGame { ref code, ref server } => {
+                let (watch, token) = match &message {
+                    NetManagerMessage::JoinGame { token, .. } => (false, token.as_deref()),
+                    _ => (true, None),
+                };
                 if let Some(mut ws) = lobby_connection.take() {
-                    ws.close().await.unwrap();
-                }
-                let ws = async_wsocket::connect(
-                    &Url::parse(&(format!("{}/ws/game?id={code}", server.url))).unwrap(),
-                    &ConnectionMode::Direct,
-                    Duration::from_secs(2),
-                )
-                .await
-                .unwrap();
-                let (ws_tx, mut ws_rx) = ws.split();
-                game_connection = Some(ws_tx);
-
-                let reading_future = {
-                    let tx = tx.clone(); // so we can move this into the future
-                    async move {
-                        while let Some(x) = ws_rx.next().await {
-                            match x {
-                                Err(e) => {
-                                    error!("{e:?}");
-                                }
-                                Ok(message) => match message {
-                                    Message::Binary(x) => {
-                                        let message = match bson::deserialize_from_slice(&x) {
-                                            Ok(x) => x,
-                                            Err(e) => {
-                                                error!("error handling unrecognized server message: {e:?} (message: {x:?})");
-                                                continue;
-                                            }
-                                        };
-                                        info!("{message:?}");
-                                        match message {
-                                            GameClientbound::WaitingFor { .. } | GameClientbound::Turn { .. } => {
-                                                // May use this later, but don't need to yet
-                                            }
-                                            GameClientbound::GameStart { me } => {
-                                                tx.send(NetMessageClientbound::GameStart { me }).await.unwrap();
-                                            }
-                                            GameClientbound::Move { player, x, y } => {
-                                                tx.send(NetMessageClientbound::Move { player, x, y }).await.unwrap();
-                                            }
-                                            x => {
-                                                error!("unhandled server message: {x:?}");
-                                            }
-                                        }
-                                    }
-                                    #[cfg(not(target_family = "wasm"))]
-                                    Message::Close(_) => {}
-                                    x => info!("unhandled websocket message: {x:?}"),
-                                },
-                            }
-                        }
+                    let _ = ws.close().await;
+                }
+                if let Some(mut ws) = game_connection.take() {
+                    let _ = ws.close().await;
+                }
+                next_connection += 1;
+                match open_game(code, server, (watch, token), next_connection, &tx).await {
+                    Ok(ws) => game_connection = Some(ws),
+                    Err(error) => {
+                        let _ = tx.send(NetMessageClientbound::Failed { error }).await;
                     }
-                };
-                #[cfg(not(target_family = "wasm"))]
-                tx.send(NetMessageClientbound::Spawn(Box::pin(reading_future))).await.unwrap();
-                #[cfg(target_family = "wasm")]
-                IoTaskPool::get().spawn(reading_future).detach();
+                }
             }
             NetManagerMessage::Move { x, y } => {
-                if let Some(ws) = &mut game_connection {
-                    ws.send(Message::Binary(bson::serialize_to_vec(&GameServerbound::Move { x, y }).unwrap()))
-                        .await
-                        .unwrap();
+                if let Some(ws) = &mut game_connection
+                    && send_message(ws, &GameServerbound::Move { x, y }).await.is_err()
+                {
+                    // The reading side will notice the socket is gone and report it
+                    warn!("couldn't send move ({x}, {y})");
+                }
+            }
+            NetManagerMessage::Game(message) => {
+                if let Some(ws) = &mut game_connection
+                    && send_message(ws, &message).await.is_err()
+                {
+                    warn!("couldn't send {message:?}");
                 }
             }
             NetManagerMessage::Shutdown => {
@@ -510,7 +1449,7 @@ async fn net_manager_main(rx: Receiver<NetManagerMessage>, tx: Sender<NetMessage
                     let _ = x.close().await;
                 }
                 if let Some(mut x) = game_connection.take() {
-                    let _ = x.send(Message::Binary(bson::serialize_to_vec(&GameServerbound::Resign).unwrap())).await;
+                    let _ = send_message(&mut x, &GameServerbound::Resign).await;
                     let _ = x.close().await;
                 }
             }
@@ -0,0 +1,110 @@
+//! Alternative player colours for colour-blind players, and per-player dot shapes so colour isn't the only way to tell players apart.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{CellColor, Dot, DotCell, GameAssets, PlayerConfigEntry};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    /// Whatever colours the players were set up with.
+    #[default]
+    Standard,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Self; 5] = [Self::Standard, Self::Deuteranopia, Self::Protanopia, Self::Tritanopia, Self::HighContrast];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Standard => "Standard",
+            Self::Deuteranopia => "Deuteranopia",
+            Self::Protanopia => "Protanopia",
+            Self::Tritanopia => "Tritanopia",
+            Self::HighContrast => "High contrast",
+        }
+    }
+
+    /// Player colours in turn order, or `None` to keep the configured ones.
+    fn colors(self) -> Option<[Color; 8]> {
+        let hex = |x: u32| Color::srgb_u8((x >> 16) as u8, (x >> 8) as u8, x as u8);
+        // Picked from the Okabe-Ito and Tol palettes, ordered so the first few players are the furthest apart
+        Some(
+            match self {
+                Self::Standard => return None,
+                Self::Deuteranopia => [0x0072b2, 0xe69f00, 0xf0e442, 0xcc79a7, 0x56b4e9, 0xd55e00, 0xffffff, 0x000000],
+                Self::Protanopia => [0x0072b2, 0xf0e442, 0x56b4e9, 0xe69f00, 0xcc79a7, 0x009e73, 0xffffff, 0x000000],
+                Self::Tritanopia => [0xdc3220, 0x009e9e, 0xff9fcf, 0xffffff, 0x882255, 0x88ccee, 0x000000, 0x999933],
+                Self::HighContrast => [0xffff00, 0x0044ff, 0xff0000, 0xffffff, 0x00ff00, 0xff00ff, 0x000000, 0x00ffff],
+            }
+            .map(hex),
+        )
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteSettings {
+    pub palette: Palette,
+    /// Give each player's dots their own mesh.
+    pub dot_shapes: bool,
+}
+
+impl PaletteSettings {
+    /// The colour to show for `player` (1-indexed), who was set up as `entry`.
+    pub fn player_color(&self, player: usize, entry: &PlayerConfigEntry) -> Color {
+        match self.palette.colors() {
+            Some(colors) => colors[player.saturating_sub(1) % colors.len()],
+            None => entry.color(),
+        }
+    }
+}
+
+/// Sphere, cube, tetrahedron and ring, in turn order.
+#[derive(Resource)]
+struct DotShapes([Handle<Mesh>; 4]);
+
+impl FromWorld for DotShapes {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        Self([
+            meshes.add(Sphere::new(0.1).mesh().ico(2).unwrap()),
+            meshes.add(Cuboid::from_length(0.16)),
+            meshes.add(Tetrahedron::default().mesh().build().scaled_by(Vec3::splat(0.28))),
+            meshes.add(Torus::new(0.05, 0.11)),
+        ])
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<PaletteSettings>()
+        .init_resource::<DotShapes>()
+        .add_systems(Update, update_dot_shapes);
+}
+
+fn update_dot_shapes(
+    settings: Res<PaletteSettings>,
+    shapes: Res<DotShapes>,
+    game_assets: Res<GameAssets>,
+    cells: Query<(&DotCell, &CellColor)>,
+    mut dots: Query<&mut Mesh3d, With<Dot>>,
+) {
+    for (cell, color) in &cells {
+        let mesh = if settings.dot_shapes && color.player != 0 {
+            &shapes.0[(color.player - 1) % shapes.0.len()]
+        } else {
+            &game_assets.dot_mesh
+        };
+        let mut dots = dots.iter_many_mut(&cell.dots);
+        while let Some(mut dot) = dots.fetch_next() {
+            if dot.0 != *mesh {
+                dot.0 = mesh.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Recording finished games and playing them back on the board.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::grid::Grid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, EndGame, GameAssets, GameOperation, GridTray, MainState, NeedNewBoard, PlaceDot, PlayerConfigEntry, VisualGrid,
+    apply_grid,
+    history::{GameHistory, RecordedMove, is_local_game, positions_from_moves},
+    menu::{MainMenuSubState, MenuRadios, MenuState},
+    navigation::NavInput,
+    save::{PendingRestore, RestoredGame},
+    storage,
+    tutorial::Tutorial,
+};
+
+const REPLAYS_KEY: &str = "replays";
+/// Bump this when the format changes. Replays from other versions are thrown away.
+const REPLAYS_VERSION: u32 = 1;
+/// Only this many of the most recent games are kept.
+pub const MAX_REPLAYS: usize = 10;
+/// Playback speeds, in moves per second.
+pub const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedPlayer {
+    pub name: String,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedGame {
+    pub width: u8,
+    pub height: u8,
+    pub players: Vec<RecordedPlayer>,
+    pub moves: Vec<RecordedMove>,
+    pub online: bool,
+}
+
+impl RecordedGame {
+    /// Every position of the game, from the empty board to the end.
+    fn positions(&self) -> Option<Vec<Grid>> {
+        let num_players = self.players.len() as u8;
+        let Some((last, rest)) = self.moves.split_last() else {
+            return positions_from_moves(self.width, self.height, num_players, &[]);
+        };
+        let mut positions = positions_from_moves(self.width, self.height, num_players, rest)?;
+        if last.x >= self.width as usize || last.y >= self.height as usize {
+            return None;
+        }
+        let before = positions.last().unwrap();
+        let after = match before.with_move(last.x as u8, last.y as u8, last.player as u8) {
+            (Some(after), _) => after,
+            // The winning cascade never settles, so just hand the winner the board
+            (None, _) => {
+                let mut after = before.clone();
+                for cell in after.iter_mut().flatten() {
+                    cell.owner = last.player as u8;
+                }
+                after
+            }
+        };
+        positions.push(after);
+        Some(positions)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReplayLibrary {
+    version: u32,
+    games: Vec<RecordedGame>,
+}
+
+/// Recently finished games, newest first.
+#[derive(Resource, Default, Deref)]
+pub struct Replays(Vec<RecordedGame>);
+
+#[derive(Resource)]
+pub struct ReplayViewer {
+    pub game: RecordedGame,
+    positions: Vec<Grid>,
+    /// How many moves have been played onto the board.
+    pub shown: usize,
+    pub playing: bool,
+    /// Index into [`REPLAY_SPEEDS`].
+    pub speed: usize,
+    /// A position to snap to once the board is ready.
+    jump: Option<usize>,
+    wait: Timer,
+    /// The players and board size from before the replay, put back once it's closed.
+    returns_to: Config,
+    /// There was a game to continue when the replay was opened.
+    resumable: bool,
+}
+
+impl ReplayViewer {
+    pub fn len(&self) -> usize {
+        self.game.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.game.moves.is_empty()
+    }
+
+    /// Steps forward one move, playing out its cascade.
+    pub fn step_forward(&mut self) {
+        self.playing = false;
+        self.wait.tick(self.wait.duration());
+    }
+
+    /// Snaps to the position after `moves` moves.
+    pub fn jump_to(&mut self, moves: usize) {
+        self.jump = Some(moves.min(self.len()));
+    }
+
+    pub fn toggle_playing(&mut self) {
+        self.playing = !self.playing;
+        if self.playing && self.shown == self.len() {
+            self.jump_to(0);
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: usize) {
+        self.speed = speed.min(REPLAY_SPEEDS.len() - 1);
+        self.wait.set_duration(Duration::from_secs_f32(1.0 / REPLAY_SPEEDS[self.speed]));
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Replays>()
+        .add_systems(Startup, load_replays)
+        .add_systems(OnEnter(EndGame { game_ended: true }), record_game.run_if(in_state(MainState::Game)))
+        .add_systems(OnEnter(MainState::Replay), keep_game.before(crate::fly_in_game))
+        .add_systems(
+            Update,
+            (run_replay.run_if(resource_exists::<ReplayViewer>), esc_to_menu).run_if(in_state(MainState::Replay)),
+        )
+        .add_systems(OnExit(MainState::Replay), stop_replay);
+}
+
+fn load_replays(mut replays: ResMut<Replays>) {
+    let Some(library) = storage::load(REPLAYS_KEY) else {
+        return;
+    };
+    match serde_json::from_str::<ReplayLibrary>(&library) {
+        Ok(library) if library.version == REPLAYS_VERSION => replays.0 = library.games,
+        Ok(library) => warn!("discarding replays from version {}", library.version),
+        Err(e) => warn!("discarding unreadable replays: {e}"),
+    }
+}
+
+fn record_game(mut replays: ResMut<Replays>, config: Res<Config>, tutorial: Res<Tutorial>, history: Res<GameHistory>) {
+    if tutorial.active {
+        return;
+    }
+    let (width, height) = config.grid_size;
+    let game = RecordedGame {
+        width: width as u8,
+        height: height as u8,
+        players: config
+            .players
+            .iter()
+            .map(|player| RecordedPlayer {
+                name: player.name().to_owned(),
+                color: player.color().to_srgba().to_f32_array(),
+            })
+            .collect(),
+        moves: history.played_moves().to_vec(),
+        online: config.players.iter().any(PlayerConfigEntry::online),
+    };
+    replays.0.insert(0, game);
+    replays.0.truncate(MAX_REPLAYS);
+    let library = ReplayLibrary {
+        version: REPLAYS_VERSION,
+        games: replays.0.clone(),
+    };
+    match serde_json::to_string(&library) {
+        Ok(library) => storage::store(REPLAYS_KEY, &library),
+        Err(e) => warn!("failed to serialize replays: {e}"),
+    }
+}
+
+/// Sets up the board for a recorded game and switches to the replay view.
+pub fn start_replay(
+    commands: &mut Commands,
+    game: &RecordedGame,
+    resumable: bool,
+    config: &mut Config,
+    radios: &mut MenuRadios,
+    next_state: &mut NextState<MainState>,
+    new_board: &mut NextState<NeedNewBoard>,
+) {
+    let Some(positions) = game.positions() else {
+        warn!("replay doesn't play out, skipping it");
+        return;
+    };
+    // Keep the game type buttons from overwriting our players
+    if let Some(play_mode) = radios.radios.get_mut("game-type") {
+        play_mode.disable();
+    }
+    let returns_to = config.clone();
+    config.players = game
+        .players
+        .iter()
+        .map(|player| PlayerConfigEntry::Human {
+            color: Color::Srgba(Srgba::from_f32_array(player.color)),
+            name: player.name.clone(),
+            _level: 0,
+            online: false,
+        })
+        .collect();
+    config.grid_size = (game.width.into(), game.height.into());
+    let mut viewer = ReplayViewer {
+        game: game.clone(),
+        positions,
+        shown: 0,
+        playing: true,
+        speed: 0,
+        jump: None,
+        wait: Timer::default(),
+        returns_to,
+        resumable,
+    };
+    viewer.set_speed(2);
+    commands.insert_resource(viewer);
+    new_board.set(NeedNewBoard(true));
+    next_state.set(MainState::Replay);
+}
+
+/// Holds on to the game that was being played, so it can still be continued once the replay is closed.
+fn keep_game(viewer: Res<ReplayViewer>, history: Res<GameHistory>, tutorial: Res<Tutorial>, mut pending_restore: ResMut<PendingRestore>) {
+    if !viewer.resumable {
+        **pending_restore = None;
+    } else if pending_restore.is_none() && is_local_game(&viewer.returns_to, &tutorial) {
+        **pending_restore = RestoredGame::from_history(&history);
+    }
+}
+
+fn stop_replay(
+    mut commands: Commands,
+    viewer: Option<Res<ReplayViewer>>,
+    mut config: ResMut<Config>,
+    pending_restore: Res<PendingRestore>,
+    mut radios: ResMut<MenuRadios>,
+    mut new_board: ResMut<NextState<NeedNewBoard>>,
+) {
+    commands.remove_resource::<ReplayViewer>();
+    if let Some(viewer) = viewer {
+        config.clone_from(&viewer.returns_to);
+    }
+    // The replay board isn't a game that can be continued, but the one from before it might be
+    if pending_restore.is_none()
+        && let Some(play_mode) = radios.radios.get_mut("game-type")
+    {
+        play_mode.enable();
+    }
+    new_board.set(NeedNewBoard(pending_restore.is_none()));
+}
+
+fn esc_to_menu(nav: Res<NavInput>, mut next_state: ResMut<NextState<MainState>>, mut next_menu_state: ResMut<NextState<MenuState>>) {
+    if nav.back {
+        next_state.set(MainState::Menu);
+        next_menu_state.set(MenuState::Main(Some(MainMenuSubState::Main)));
+    }
+}
+
+fn run_replay(
+    mut commands: Commands,
+    mut viewer: ResMut<ReplayViewer>,
+    game_op: Res<State<GameOperation>>,
+    end_game: Option<Res<State<EndGame>>>,
+    (mut next_game_op, mut next_turn, mut next_end_game): (ResMut<NextState<GameOperation>>, ResMut<NextState<CurrentTurn>>, ResMut<NextState<EndGame>>),
+    grid: Res<VisualGrid>,
+    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: Res<GameAssets>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    mut place_dot: MessageWriter<PlaceDot>,
+    time: Res<Time>,
+) {
+    // Wait for the board to be built
+    if grid.width() != viewer.game.width as usize || cells.get(grid[0][0]).is_err() {
+        return;
+    }
+    let Ok(grid_tray) = grid_tray.single() else {
+        return;
+    };
+    // The final cascade never settles, so the end of the game counts as settled too
+    let settled = *game_op != GameOperation::Animating || end_game.is_some_and(|x| x.game_ended);
+    if !settled {
+        return;
+    }
+
+    if let Some(target) = viewer.jump.take() {
+        apply_grid(&mut commands, &viewer.positions[target], &grid, &mut cells, &game_assets, grid_tray);
+        viewer.shown = target;
+        viewer.wait.reset();
+        next_turn.set(CurrentTurn(0));
+        next_game_op.set(GameOperation::Animating);
+        next_end_game.set(EndGame { game_ended: false });
+        return;
+    }
+
+    if viewer.playing {
+        viewer.wait.tick(time.delta());
+    }
+    if viewer.wait.is_finished() {
+        viewer.wait.reset();
+        if let Some(&RecordedMove { player, x, y }) = viewer.game.moves.get(viewer.shown) {
+            place_dot.write(PlaceDot { player, x, y });
+            viewer.shown += 1;
+        }
+        if viewer.shown == viewer.len() {
+            viewer.playing = false;
+        }
+    }
+}
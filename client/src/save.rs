@@ -0,0 +1,188 @@
+//! Keeps the current local game on disk so it can be resumed after the app is closed.
+
+use bevy::prelude::*;
+use common::grid::Grid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, EndGame, GameOperation, MainState, NeedNewBoard, PlayerConfigEntry, VisualGrid, board_snapshot,
+    history::{GameHistory, RecordedMove, is_local_game},
+    menu::{MenuRadios, RadioState},
+    storage,
+    tutorial::Tutorial,
+};
+
+const SAVE_KEY: &str = "savegame";
+/// Bump this when the format changes. Saves from other versions are thrown away.
+const SAVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    bot: bool,
+    name: String,
+    color: [f32; 4],
+    level: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedGame {
+    version: u32,
+    width: u8,
+    height: u8,
+    players: Vec<SavedPlayer>,
+    /// The player to move, 1-indexed.
+    turn: usize,
+    /// `(owner, dots)` for every cell, row by row.
+    cells: Vec<(u8, u8)>,
+    moves: Vec<RecordedMove>,
+    #[serde(default)]
+    casual: bool,
+}
+
+pub struct RestoredGame {
+    pub grid: Grid,
+    /// The player to move, 1-indexed.
+    pub turn: usize,
+    pub history: GameHistory,
+}
+
+impl RestoredGame {
+    /// The position on the board, to be put back later. A move that's still playing out is left off.
+    pub fn from_history(history: &GameHistory) -> Option<Self> {
+        let position = history.positions.get(history.current).filter(|x| x.turn > 0)?;
+        Some(Self {
+            grid: position.grid.clone(),
+            turn: position.turn,
+            history: GameHistory::replay(&position.grid, history.moves[..position.moves].to_vec(), position.turn, history.casual),
+        })
+    }
+}
+
+/// A saved game found at startup, waiting for the board to be built.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct PendingRestore(pub Option<RestoredGame>);
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<PendingRestore>()
+        .add_systems(Startup, load_game)
+        .add_systems(Update, save_game.run_if(in_state(MainState::Game).and(state_changed::<GameOperation>)))
+        .add_systems(OnEnter(EndGame { game_ended: true }), remove_save.run_if(in_state(MainState::Game)));
+}
+
+fn save_game(
+    config: Res<Config>,
+    tutorial: Res<Tutorial>,
+    game_op: Res<State<GameOperation>>,
+    current_turn: Res<State<CurrentTurn>>,
+    grid: Res<VisualGrid>,
+    cells: Query<(&DotCell, &CellColor)>,
+    history: Res<GameHistory>,
+) {
+    // Only save between moves, once the board has settled
+    if !matches!(**game_op, GameOperation::Human | GameOperation::Bot) || !is_local_game(&config, &tutorial) {
+        return;
+    }
+    let board = board_snapshot(&grid, &cells, config.players.len() as u8);
+    let save = SavedGame {
+        version: SAVE_VERSION,
+        width: board.width(),
+        height: board.height(),
+        players: config
+            .players
+            .iter()
+            .map(|player| SavedPlayer {
+                bot: player.is_bot(),
+                name: player.name().to_owned(),
+                color: player.color().to_srgba().to_f32_array(),
+                level: player.level(),
+            })
+            .collect(),
+        turn: current_turn.0,
+        cells: board.iter().flat_map(|row| row.iter().map(|cell| (cell.owner, cell.dots))).collect(),
+        moves: history.played_moves().to_vec(),
+        casual: history.casual,
+    };
+    match serde_json::to_string(&save) {
+        Ok(save) => storage::store(SAVE_KEY, &save),
+        Err(e) => warn!("failed to serialize save: {e}"),
+    }
+}
+
+fn remove_save(config: Res<Config>, tutorial: Res<Tutorial>) {
+    if is_local_game(&config, &tutorial) {
+        storage::remove(SAVE_KEY);
+    }
+}
+
+fn load_game(
+    mut config: ResMut<Config>,
+    mut radios: ResMut<MenuRadios>,
+    mut pending_restore: ResMut<PendingRestore>,
+    mut next_need_new_board: ResMut<NextState<NeedNewBoard>>,
+) {
+    let Some(save) = storage::load(SAVE_KEY) else {
+        return;
+    };
+    let save = match serde_json::from_str::<SavedGame>(&save) {
+        Ok(save) if save.version == SAVE_VERSION => save,
+        Ok(save) => {
+            warn!("discarding save from version {}", save.version);
+            storage::remove(SAVE_KEY);
+            return;
+        }
+        Err(e) => {
+            warn!("discarding unreadable save: {e}");
+            storage::remove(SAVE_KEY);
+            return;
+        }
+    };
+    let valid = save.width > 0
+        && save.height > 0
+        && save.cells.len() == save.width as usize * save.height as usize
+        && !save.players.is_empty()
+        && (1..=save.players.len()).contains(&save.turn)
+        && save.cells.iter().all(|&(owner, _)| owner as usize <= save.players.len());
+    if !valid {
+        warn!("discarding corrupt save");
+        storage::remove(SAVE_KEY);
+        return;
+    }
+
+    let mut grid = Grid::new(save.width, save.height, save.players.len() as u8);
+    grid.init_capacity();
+    for (cell, &(owner, dots)) in grid.iter_mut().flatten().zip(&save.cells) {
+        cell.owner = owner;
+        cell.dots = dots;
+    }
+    config.players = save
+        .players
+        .into_iter()
+        .map(|player| {
+            let color = Color::Srgba(Srgba::from_f32_array(player.color));
+            if player.bot {
+                PlayerConfigEntry::Bot {
+                    color,
+                    _name: player.name,
+                    level: player.level,
+                    online: false,
+                }
+            } else {
+                PlayerConfigEntry::Human {
+                    color,
+                    name: player.name,
+                    _level: player.level,
+                    online: false,
+                }
+            }
+        })
+        .collect();
+    config.grid_size = (save.width.into(), save.height.into());
+    // Keep the game type buttons from overwriting the saved players until a new game is started
+    radios.radios.entry("game-type".into()).or_insert(RadioState::Enabled(0)).disable();
+    **pending_restore = Some(RestoredGame {
+        history: GameHistory::replay(&grid, save.moves, save.turn, save.casual),
+        grid,
+        turn: save.turn,
+    });
+    next_need_new_board.set(NeedNewBoard(false));
+}
@@ -0,0 +1,328 @@
+//! Player preferences that are kept between launches.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    FlashIntensity, MAX_PLAYERS, PlayerConfigEntry,
+    anim::{ANIMATION_SPEEDS, AnimationSettings},
+    audio::AudioSettings,
+    clock::{CLOCK_PRESETS, LocalClock},
+    menu::{MenuRadios, RadioState},
+    move_preview::AssistSettings,
+    net::{ServerList, ServerUrl, TimeControl},
+    palette::PaletteSettings,
+    storage,
+    ui_menu::CustomConfig,
+};
+
+const SETTINGS_KEY: &str = "settings";
+/// Bump this when a setting is renamed or changes meaning, and add a step to [`MIGRATIONS`].
+///
+/// Settings that are only added don't need a bump, since missing fields fall back to their defaults.
+const SETTINGS_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PlayerKind {
+    Human,
+    Bot,
+    Disabled,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct PlayerSettings {
+    kind: PlayerKind,
+    name: String,
+    color: [f32; 4],
+    level: usize,
+}
+
+impl From<&PlayerConfigEntry> for PlayerSettings {
+    fn from(player: &PlayerConfigEntry) -> Self {
+        Self {
+            kind: match player {
+                PlayerConfigEntry::Human { .. } => PlayerKind::Human,
+                PlayerConfigEntry::Bot { .. } => PlayerKind::Bot,
+                PlayerConfigEntry::Disabled { .. } => PlayerKind::Disabled,
+            },
+            name: player.name().to_owned(),
+            color: player.color().to_srgba().to_f32_array(),
+            level: player.level(),
+        }
+    }
+}
+
+impl From<&PlayerSettings> for PlayerConfigEntry {
+    fn from(player: &PlayerSettings) -> Self {
+        let entry = Self::Human {
+            color: Color::Srgba(Srgba::from_f32_array(player.color)),
+            name: player.name.clone(),
+            _level: player.level,
+            online: false,
+        };
+        match player.kind {
+            PlayerKind::Human => entry,
+            PlayerKind::Bot => entry.as_bot(),
+            PlayerKind::Disabled => entry.as_disabled(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct ServerSettings {
+    name: String,
+    url: String,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    version: u32,
+    flash_intensity: f32,
+    game_type: usize,
+    game_difficulty: usize,
+    /// The custom game setup, if it's ever been opened.
+    custom_players: Vec<PlayerSettings>,
+    custom_grid_size: (usize, usize),
+    /// Only set once the player picks a server themselves.
+    server: Option<ServerSettings>,
+    /// Servers the player added, on top of the built-in ones.
+    custom_servers: Vec<ServerSettings>,
+    audio: AudioSettings,
+    palette: PaletteSettings,
+    assist: AssistSettings,
+    animation: AnimationSettings,
+    /// The clock for games on this device.
+    clock: TimeControl,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            flash_intensity: FlashIntensity::default().0,
+            game_type: 0,
+            game_difficulty: 0,
+            custom_players: vec![],
+            custom_grid_size: (6, 6),
+            server: None,
+            custom_servers: vec![],
+            audio: AudioSettings::default(),
+            palette: PaletteSettings::default(),
+            assist: AssistSettings::default(),
+            animation: AnimationSettings::default(),
+            clock: TimeControl::default(),
+        }
+    }
+}
+
+/// The settings as they were last loaded or stored, so they're only written when something changes.
+#[derive(Resource, Default)]
+struct StoredSettings(Settings);
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<StoredSettings>()
+        // Before the menus are spawned, so their radios pick up the stored values
+        .add_systems(PreStartup, load_settings)
+        .add_systems(
+            Last,
+            store_settings.run_if(
+                resource_changed::<FlashIntensity>
+                    .or(resource_changed::<MenuRadios>)
+                    .or(resource_changed::<CustomConfig>)
+                    .or(resource_changed::<ServerUrl>)
+                    .or(resource_changed::<ServerList>)
+                    .or(resource_changed::<AudioSettings>)
+                    .or(resource_changed::<PaletteSettings>)
+                    .or(resource_changed::<AssistSettings>)
+                    .or(resource_changed::<AnimationSettings>)
+                    .or(resource_changed::<LocalClock>),
+            ),
+        );
+}
+
+/// Upgrades settings from one version to the next: entry `i` takes version `i + 1` to `i + 2`.
+const MIGRATIONS: [fn(&mut Value); SETTINGS_VERSION as usize - 1] = [clear_default_names];
+
+/// Version 1 named every player "Player N" until they were renamed, which hid the bots' own names.
+fn clear_default_names(settings: &mut Value) {
+    let Some(players) = settings.get_mut("custom_players").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for (i, player) in players.iter_mut().enumerate() {
+        if player.get("name").and_then(Value::as_str) == Some(&format!("Player {}", i + 1)) {
+            player["name"] = Value::from("");
+        }
+    }
+}
+
+/// Brings settings written by an older version up to date.
+fn migrate(settings: &mut Value) {
+    let version = settings.get("version").and_then(Value::as_u64).unwrap_or(1).max(1) as usize;
+    if version > SETTINGS_VERSION as usize {
+        // Probably written by a newer build; take what we understand
+        warn!("settings are from a newer version ({version})");
+    }
+    for migration in MIGRATIONS.iter().skip(version - 1) {
+        migration(settings);
+    }
+}
+
+fn load_settings(
+    mut stored: ResMut<StoredSettings>,
+    mut flash_intensity: ResMut<FlashIntensity>,
+    mut radios: ResMut<MenuRadios>,
+    mut custom_config: ResMut<CustomConfig>,
+    (mut server_url, mut servers): (ResMut<ServerUrl>, ResMut<ServerList>),
+    mut audio: ResMut<AudioSettings>,
+    mut palette: ResMut<PaletteSettings>,
+    mut assist: ResMut<AssistSettings>,
+    mut animation: ResMut<AnimationSettings>,
+    mut local_clock: ResMut<LocalClock>,
+) {
+    let Some(settings) = storage::load(SETTINGS_KEY) else {
+        return;
+    };
+    let mut settings = match serde_json::from_str::<Value>(&settings) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("ignoring unreadable settings: {e}");
+            return;
+        }
+    };
+    migrate(&mut settings);
+    let settings = match serde_json::from_value::<Settings>(settings) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("ignoring unreadable settings: {e}");
+            return;
+        }
+    };
+
+    flash_intensity.0 = settings.flash_intensity.clamp(0.0, 1.0);
+    radios.radios.insert("game-type".into(), RadioState::Enabled(settings.game_type));
+    radios.radios.insert("game-difficulty".into(), RadioState::Enabled(settings.game_difficulty));
+    if (2..=MAX_PLAYERS).contains(&settings.custom_players.len()) {
+        custom_config.players = settings.custom_players.iter().map(PlayerConfigEntry::from).collect();
+    }
+    let (width, height) = settings.custom_grid_size;
+    custom_config.grid_size = (width.clamp(1, 20), height.clamp(1, 20));
+    for server in &settings.custom_servers {
+        servers.add_custom(server.name.clone(), server.url.clone());
+    }
+    if let Some(server) = &settings.server {
+        // Keep it listed, even if it somehow went missing from the custom servers
+        servers.add_custom(server.name.clone(), server.url.clone());
+        *server_url = ServerUrl {
+            name: server.name.clone(),
+            url: server.url.clone(),
+            chosen: true,
+        };
+    }
+    *audio = AudioSettings {
+        master: settings.audio.master.clamp(0.0, 1.0),
+        sfx: settings.audio.sfx.clamp(0.0, 1.0),
+        music: settings.audio.music.clamp(0.0, 1.0),
+        ..settings.audio
+    };
+    *palette = settings.palette;
+    *assist = settings.assist;
+    *animation = AnimationSettings {
+        speed: settings
+            .animation
+            .speed
+            .clamp(ANIMATION_SPEEDS[0], ANIMATION_SPEEDS[ANIMATION_SPEEDS.len() - 1]),
+        ..settings.animation
+    };
+    // Only the clocks the menu can step through
+    if CLOCK_PRESETS.contains(&settings.clock) {
+        local_clock.0 = settings.clock;
+    }
+    stored.0 = Settings {
+        version: SETTINGS_VERSION,
+        ..settings
+    };
+}
+
+fn store_settings(
+    mut stored: ResMut<StoredSettings>,
+    flash_intensity: Res<FlashIntensity>,
+    radios: Res<MenuRadios>,
+    custom_config: Res<CustomConfig>,
+    (server_url, servers): (Res<ServerUrl>, Res<ServerList>),
+    audio: Res<AudioSettings>,
+    palette: Res<PaletteSettings>,
+    assist: Res<AssistSettings>,
+    animation: Res<AnimationSettings>,
+    local_clock: Res<LocalClock>,
+) {
+    let radio = |name, stored| radios.radios.get(name).map_or(stored, RadioState::value);
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        flash_intensity: flash_intensity.0,
+        game_type: radio("game-type", stored.0.game_type),
+        game_difficulty: radio("game-difficulty", stored.0.game_difficulty),
+        custom_players: custom_config.players.iter().map(PlayerSettings::from).collect(),
+        custom_grid_size: custom_config.grid_size,
+        server: server_url.chosen.then(|| ServerSettings {
+            name: server_url.name.clone(),
+            url: server_url.url.clone(),
+        }),
+        custom_servers: servers
+            .0
+            .iter()
+            .filter(|x| x.custom)
+            .map(|x| ServerSettings {
+                name: x.name.clone(),
+                url: x.url.clone(),
+            })
+            .collect(),
+        audio: *audio,
+        palette: *palette,
+        assist: *assist,
+        animation: *animation,
+        clock: local_clock.0,
+    };
+    if settings == stored.0 {
+        return;
+    }
+    match serde_json::to_string(&settings) {
+        Ok(json) => storage::store(SETTINGS_KEY, &json),
+        Err(e) => warn!("failed to serialize settings: {e}"),
+    }
+    stored.0 = settings;
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn players(names: [&str; 3]) -> Value {
+        names
+            .iter()
+            .map(|name| json!({ "kind": "bot", "name": name, "color": [1.0, 1.0, 1.0, 1.0], "level": 0 }))
+            .collect()
+    }
+
+    #[test]
+    fn default_names_are_cleared() {
+        let mut settings = json!({ "version": 1, "custom_players": players(["Player 1", "Ann", "Player 2"]) });
+        migrate(&mut settings);
+        let settings = serde_json::from_value::<Settings>(settings).unwrap();
+        let names = settings.custom_players.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        // Only a name that matches the seat was a default one
+        assert_eq!(names, ["", "Ann", "Player 2"]);
+    }
+
+    #[test]
+    fn current_settings_are_left_alone() {
+        let mut settings = json!({ "version": SETTINGS_VERSION, "custom_players": players(["Player 1", "Ann", "Player 3"]) });
+        let before = settings.clone();
+        migrate(&mut settings);
+        assert_eq!(settings, before);
+    }
+}
@@ -0,0 +1,103 @@
+//! Small key-value store for things that need to survive a restart.
+//!
+//! On native platforms each key is a file in the user's data directory; on the web it's an entry in `localStorage`.
+
+#[cfg(not(target_family = "wasm"))]
+mod imp {
+    use std::{fs, io, path::PathBuf};
+
+    use bevy::log::warn;
+
+    fn data_dir() -> Option<PathBuf> {
+        #[cfg(target_os = "android")]
+        return bevy::android::ANDROID_APP.get().and_then(|app| app.internal_data_path());
+
+        #[cfg(not(target_os = "android"))]
+        {
+            let env = |name| std::env::var_os(name).filter(|x| !x.is_empty()).map(PathBuf::from);
+            let base = if cfg!(windows) {
+                env("APPDATA")
+            } else if cfg!(target_os = "macos") {
+                env("HOME").map(|x| x.join("Library/Application Support"))
+            } else {
+                env("XDG_DATA_HOME").or_else(|| env("HOME").map(|x| x.join(".local/share")))
+            };
+            base.map(|x| x.join("hopdot"))
+        }
+    }
+
+    fn path(key: &str) -> Option<PathBuf> {
+        data_dir().map(|x| x.join(format!("{key}.json")))
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        match fs::read_to_string(path(key)?) {
+            Ok(x) => Some(x),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("failed to read {key}: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn store(key: &str, value: &str) {
+        let Some(path) = path(key) else {
+            warn!("nowhere to save {key}");
+            return;
+        };
+        // Write then rename so a crash mid-write doesn't leave a truncated file behind
+        let tmp = path.with_extension("json.tmp");
+        let result = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&tmp, value))
+            .and_then(|_| fs::rename(&tmp, &path));
+        if let Err(e) = result {
+            warn!("failed to save {key}: {e}");
+        }
+    }
+
+    pub fn remove(key: &str) {
+        let Some(path) = path(key) else {
+            return;
+        };
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("failed to remove {key}: {e}"),
+        }
+    }
+}
+
+#[cfg(target_family = "wasm")]
+mod imp {
+    use bevy::log::warn;
+    use web_sys::Storage;
+
+    const PREFIX: &str = "hopdot.";
+
+    fn local_storage() -> Option<Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        local_storage()?.get_item(&format!("{PREFIX}{key}")).ok().flatten()
+    }
+
+    pub fn store(key: &str, value: &str) {
+        let Some(storage) = local_storage() else {
+            warn!("nowhere to save {key}");
+            return;
+        };
+        if storage.set_item(&format!("{PREFIX}{key}"), value).is_err() {
+            warn!("failed to save {key}");
+        }
+    }
+
+    pub fn remove(key: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(&format!("{PREFIX}{key}"));
+        }
+    }
+}
+
+pub use imp::{load, remove, store};
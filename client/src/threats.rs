@@ -0,0 +1,168 @@
+//! Marks the tactical state of the board for the player to move: full cells, cells ready to cascade into an opponent, and cells an opponent could take next.
+
+use bevy::prelude::*;
+use common::grid::Grid;
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, GameOperation, GridTray, MainState, PlayerConfigEntry, VisualGrid, board_snapshot, history::HistoryPreview,
+    move_preview::AssistSettings,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Threat {
+    /// One more dot and it cascades.
+    Full,
+    /// Full, and next to an opponent it would cascade into.
+    Attack,
+    /// Next to an opponent's full cell, so they could take it with their next move.
+    Exposed,
+}
+
+impl Threat {
+    fn color(self) -> Color {
+        match self {
+            Self::Full => Color::srgba(1.0, 1.0, 1.0, 0.7),
+            Self::Attack => Color::srgba(1.0, 0.8, 0.0, 0.9),
+            Self::Exposed => Color::srgba(1.0, 0.1, 0.1, 0.9),
+        }
+    }
+}
+
+#[derive(Component)]
+struct ThreatMarker;
+
+#[derive(Resource)]
+struct ThreatAssets {
+    mesh: Handle<Mesh>,
+    materials: [Handle<StandardMaterial>; 3],
+}
+
+impl FromWorld for ThreatAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(Cuboid::new(0.18, 0.02, 0.18));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let materials = [Threat::Full, Threat::Attack, Threat::Exposed].map(|threat| {
+            materials.add(StandardMaterial {
+                base_color: threat.color(),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })
+        });
+        Self { mesh, materials }
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ThreatAssets>().add_systems(Update, update_threats);
+}
+
+/// What each of `player`'s cells and every full cell is up against.
+fn find_threats(grid: &Grid, player: u8) -> Vec<((usize, usize), Threat)> {
+    let mut threats = vec![];
+    for (y, row) in grid.iter().enumerate_u8() {
+        for (x, cell) in row.iter().enumerate_u8() {
+            let opponents_next_door = || {
+                [(-1, 0), (1, 0), (0, -1), (0, 1)].into_iter().filter_map(|(dx, dy): (i16, i16)| {
+                    let (nx, ny) = (x as i16 + dx, y as i16 + dy);
+                    if !(0..grid.width() as i16).contains(&nx) || !(0..grid.height() as i16).contains(&ny) {
+                        return None;
+                    }
+                    let neighbour = grid[ny as u8][nx as u8];
+                    (neighbour.owner != 0 && neighbour.owner != player).then_some(neighbour)
+                })
+            };
+            let opponent_next_door = opponents_next_door().next().is_some();
+            let threat = if cell.owner == player && opponents_next_door().any(|x| x.is_full()) {
+                Threat::Exposed
+            } else if cell.owner == player && cell.is_full() && opponent_next_door {
+                Threat::Attack
+            } else if cell.owner != 0 && cell.is_full() {
+                Threat::Full
+            } else {
+                continue;
+            };
+            threats.push(((x as usize, y as usize), threat));
+        }
+    }
+    threats
+}
+
+fn update_threats(
+    mut commands: Commands,
+    (settings, preview): (Res<AssistSettings>, Res<HistoryPreview>),
+    (main_state, game_op, current_turn): (Res<State<MainState>>, Option<Res<State<GameOperation>>>, Option<Res<State<CurrentTurn>>>),
+    config: Res<Config>,
+    grid: Res<VisualGrid>,
+    cells: Query<(&DotCell, &CellColor)>,
+    transforms: Query<&Transform, With<DotCell>>,
+    markers: Query<Entity, With<ThreatMarker>>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    assets: Res<ThreatAssets>,
+    mut shown: Local<Option<usize>>,
+) {
+    // Only for a human thinking about their move, and not online, where it would be an unfair advantage
+    let player = current_turn.map(|x| x.0).filter(|_| {
+        settings.threats
+            && *main_state == MainState::Game
+            && game_op.as_ref().is_some_and(|x| **x == GameOperation::Human)
+            && preview.0.is_none()
+            && !config.players.iter().any(PlayerConfigEntry::online)
+    });
+    // The board only changes between turns, so there's nothing to redo until the turn or the settings do
+    let changed = settings.is_changed() || preview.is_changed() || game_op.is_some_and(|x| x.is_changed());
+    if player == *shown && !changed {
+        return;
+    }
+    *shown = player;
+    for marker in &markers {
+        commands.entity(marker).despawn();
+    }
+    let (Some(player), Ok(grid_tray)) = (player, grid_tray.single()) else {
+        return;
+    };
+
+    let board = board_snapshot(&grid, &cells, config.players.len() as u8);
+    for ((x, y), threat) in find_threats(&board, player as u8) {
+        let Ok(transform) = transforms.get(grid[y][x]) else {
+            continue;
+        };
+        let material = match threat {
+            Threat::Full => &assets.materials[0],
+            Threat::Attack => &assets.materials[1],
+            Threat::Exposed => &assets.materials[2],
+        };
+        // In the corner of the tile, out of the way of the dots
+        commands.spawn((
+            ThreatMarker,
+            Mesh3d(assets.mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_xyz(transform.translation.x - 0.35, -0.085, transform.translation.z - 0.35),
+            Pickable::IGNORE,
+            ChildOf(grid_tray),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exposed_only_next_to_a_full_opponent() {
+        let mut grid = Grid::new(3, 3, 2);
+        grid.init_capacity();
+        grid[1_usize][1_usize].owner = 1;
+        grid[1_usize][1_usize].dots = 1;
+        // Nobody around to take it
+        assert!(find_threats(&grid, 1).is_empty());
+
+        grid[1_usize][0_usize].owner = 2;
+        grid[1_usize][0_usize].dots = 2;
+        // Two more dots before it cascades
+        assert!(find_threats(&grid, 1).is_empty());
+
+        grid[1_usize][0_usize].dots = 3;
+        assert_eq!(find_threats(&grid, 1), [((0, 1), Threat::Full), ((1, 1), Threat::Exposed)]);
+    }
+}
@@ -0,0 +1,317 @@
+use bevy::{
+    asset::{AssetLoadFailedEvent, AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use common::grid::Grid;
+use serde::Deserialize;
+
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, GameAssets, GameOperation, GridTray, MainState, NeedNewBoard, PLAYER_COLORS, PlaceDot, PlayerConfigEntry,
+    VisualGrid, apply_grid,
+    menu::{MainMenuSubState, MenuRadios, MenuState},
+};
+
+/// The script the tutorial plays when there's none in the system's language. Translations live next to it as `<lang>.tutorial.json`.
+const TUTORIAL_SCRIPT: &str = "tutorial/en.tutorial.json";
+
+/// The script in the system's language, like `tutorial/fr.tutorial.json` for `fr-CA`.
+fn localized_script() -> Option<String> {
+    let locale = sys_locale::get_locale()?;
+    let lang = locale.split(['-', '_', '.']).next()?.to_ascii_lowercase();
+    (!lang.is_empty() && lang.chars().all(|x| x.is_ascii_alphabetic())).then(|| format!("tutorial/{lang}.tutorial.json"))
+}
+
+/// A scripted walkthrough of the rules, loaded from a `.tutorial.json` file.
+#[derive(Asset, TypePath, Deserialize)]
+pub struct TutorialScript {
+    pub width: u8,
+    pub height: u8,
+    pub steps: Vec<TutorialStep>,
+}
+
+#[derive(Deserialize)]
+pub struct TutorialStep {
+    /// Shown while the step is running.
+    pub text: String,
+    /// Shown once the forced move (and the tutor's reply) has played out. Falls back to `text`.
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Position to set up when the step starts, one string per row. Cells are separated by spaces: `.` is an unowned cell, and a letter
+    /// followed by a digit is a cell owned by that player (`A` is the student, `B` is the tutor) holding that many dots.
+    #[serde(default)]
+    pub board: Option<Vec<String>>,
+    /// Cells to draw attention to.
+    #[serde(default)]
+    pub highlight: Vec<(u8, u8)>,
+    /// The only move the student is allowed to make. If this is missing, the student reads the text and clicks "Next" instead.
+    #[serde(default)]
+    pub force: Option<(u8, u8)>,
+    /// The tutor's answer to the forced move.
+    #[serde(default)]
+    pub reply: Option<(u8, u8)>,
+}
+
+impl TutorialStep {
+    fn board(&self, width: u8, height: u8) -> Option<Grid> {
+        let rows = self.board.as_ref()?;
+        let mut grid = Grid::new(width, height, 2);
+        grid.init_capacity();
+        for (y, row) in rows.iter().enumerate().take(height as usize) {
+            for (x, token) in row.split_whitespace().enumerate().take(width as usize) {
+                let cell = &mut grid[y][x];
+                let mut chars = token.chars();
+                match (chars.next(), chars.next().and_then(|x| x.to_digit(10))) {
+                    (Some(player @ 'A'..='B'), Some(dots)) => {
+                        cell.owner = player as u8 - b'A' + 1;
+                        cell.dots = dots as u8;
+                    }
+                    (Some('.'), None) => {}
+                    _ => warn!("invalid tutorial board token {token:?}"),
+                }
+            }
+        }
+        Some(grid)
+    }
+}
+
+#[derive(Default, TypePath)]
+struct TutorialScriptLoader;
+
+impl AssetLoader for TutorialScriptLoader {
+    type Asset = TutorialScript;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(&self, reader: &mut dyn Reader, _: &(), _: &mut LoadContext<'_>) -> Result<TutorialScript, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tutorial.json"]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TutorialPhase {
+    /// The step's position still needs to be set up.
+    #[default]
+    Setup,
+    /// Waiting for the student to make the forced move.
+    AwaitMove,
+    /// Waiting for the tutor's reply to play out.
+    AwaitReply,
+    /// Waiting for the student to click "Next".
+    Reading,
+}
+
+#[derive(Resource)]
+pub struct Tutorial {
+    pub script: Handle<TutorialScript>,
+    pub active: bool,
+    pub step: usize,
+    pub phase: TutorialPhase,
+    /// The move the student is allowed to make, if any. Copied out of the current step so move validation doesn't need the asset.
+    forced: Option<(usize, usize)>,
+}
+
+impl FromWorld for Tutorial {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            script: world
+                .resource::<AssetServer>()
+                .load(localized_script().unwrap_or_else(|| TUTORIAL_SCRIPT.into())),
+            active: false,
+            step: 0,
+            phase: TutorialPhase::Setup,
+            forced: None,
+        }
+    }
+}
+
+impl Tutorial {
+    /// Whether `player` may play at `pos`. Outside of the tutorial, any move is allowed.
+    pub fn allows_move(&self, player: usize, pos: (usize, usize)) -> bool {
+        !self.active || (player == 1 && self.phase == TutorialPhase::AwaitMove && self.forced == Some(pos))
+    }
+
+    pub fn advance(&mut self) {
+        self.step += 1;
+        self.phase = TutorialPhase::Setup;
+    }
+}
+
+#[derive(Component)]
+pub struct TutorialHighlight;
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<TutorialScript>()
+        .init_asset_loader::<TutorialScriptLoader>()
+        .init_resource::<Tutorial>()
+        .add_systems(Update, fall_back_to_english)
+        .add_systems(Update, run_tutorial.run_if(in_state(MainState::Game)))
+        .add_systems(OnEnter(MenuState::Main(Some(MainMenuSubState::Main))), stop_tutorial);
+}
+
+/// Swaps in the English script if there's no translation for the system's language.
+fn fall_back_to_english(mut failed: MessageReader<AssetLoadFailedEvent<TutorialScript>>, mut tutorial: ResMut<Tutorial>, asset_server: Res<AssetServer>) {
+    for event in failed.read() {
+        if event.id == tutorial.script.id() && event.path.path().to_str() != Some(TUTORIAL_SCRIPT) {
+            tutorial.script = asset_server.load(TUTORIAL_SCRIPT);
+        }
+    }
+}
+
+/// Starts the tutorial on a fresh board. Used as the click handler for the "Rules" menu entry.
+pub fn start_tutorial(
+    _: On<Pointer<Click>>,
+    mut tutorial: ResMut<Tutorial>,
+    scripts: Res<Assets<TutorialScript>>,
+    mut config: ResMut<Config>,
+    mut radios: ResMut<MenuRadios>,
+    mut next_state: ResMut<NextState<MainState>>,
+    mut new_board: ResMut<NextState<NeedNewBoard>>,
+) {
+    let Some(script) = scripts.get(&tutorial.script) else {
+        warn!("tutorial script isn't loaded yet");
+        return;
+    };
+    // Keep the game type buttons from overwriting our players
+    if let Some(play_mode) = radios.radios.get_mut("game-type") {
+        play_mode.disable();
+    }
+    config.players = vec![
+        PlayerConfigEntry::Human {
+            color: PLAYER_COLORS[0],
+            name: "You".into(),
+            _level: 0,
+            online: false,
+        },
+        PlayerConfigEntry::Human {
+            color: PLAYER_COLORS[1],
+            name: "Tutor".into(),
+            _level: 0,
+            online: false,
+        },
+    ];
+    config.grid_size = (script.width.into(), script.height.into());
+    *tutorial = Tutorial {
+        script: tutorial.script.clone(),
+        active: true,
+        step: 0,
+        phase: TutorialPhase::Setup,
+        forced: None,
+    };
+    new_board.set(NeedNewBoard(true));
+    next_state.set(MainState::Game);
+}
+
+fn stop_tutorial(
+    mut commands: Commands,
+    mut tutorial: ResMut<Tutorial>,
+    mut radios: ResMut<MenuRadios>,
+    mut new_board: ResMut<NextState<NeedNewBoard>>,
+    highlights: Query<Entity, With<TutorialHighlight>>,
+) {
+    if !tutorial.active {
+        return;
+    }
+    tutorial.active = false;
+    tutorial.forced = None;
+    if let Some(play_mode) = radios.radios.get_mut("game-type") {
+        play_mode.enable();
+    }
+    // The tutorial board isn't a real game, so don't offer to continue it
+    new_board.set(NeedNewBoard(true));
+    for highlight in &highlights {
+        commands.entity(highlight).despawn();
+    }
+}
+
+fn run_tutorial(
+    mut commands: Commands,
+    mut tutorial: ResMut<Tutorial>,
+    scripts: Res<Assets<TutorialScript>>,
+    need_new_board: Res<State<NeedNewBoard>>,
+    (game_op, current_turn): (Res<State<GameOperation>>, Res<State<CurrentTurn>>),
+    (mut next_game_op, mut next_turn): (ResMut<NextState<GameOperation>>, ResMut<NextState<CurrentTurn>>),
+    grid: Res<VisualGrid>,
+    mut cells: Query<(&DotCell, &mut CellColor, &Transform)>,
+    game_assets: Res<GameAssets>,
+    grid_tray: Query<Entity, With<GridTray>>,
+    highlights: Query<Entity, With<TutorialHighlight>>,
+    mut place_dot: MessageWriter<PlaceDot>,
+) {
+    if !tutorial.active || need_new_board.0 {
+        return;
+    }
+    let Some(script) = scripts.get(&tutorial.script) else {
+        return;
+    };
+    let Some(step) = script.steps.get(tutorial.step) else {
+        return;
+    };
+    let Ok(grid_tray) = grid_tray.single() else {
+        return;
+    };
+    match tutorial.phase {
+        TutorialPhase::Setup => {
+            // Wait for the board to be built
+            if grid.width() != script.width as usize || cells.get(grid[0][0]).is_err() {
+                return;
+            }
+            if let Some(board) = step.board(script.width, script.height) {
+                apply_grid(&mut commands, &board, &grid, &mut cells, &game_assets, grid_tray);
+            }
+            for highlight in &highlights {
+                commands.entity(highlight).despawn();
+            }
+            for &(x, y) in &step.highlight {
+                let Some(&cell) = grid.get(x as usize, y as usize) else {
+                    continue;
+                };
+                let Ok((_, _, transform)) = cells.get(cell) else {
+                    continue;
+                };
+                commands.spawn((
+                    TutorialHighlight,
+                    Mesh3d(game_assets.highlight_mesh.clone()),
+                    MeshMaterial3d(game_assets.highlight_material.clone()),
+                    Transform::from_xyz(transform.translation.x, -0.09, transform.translation.z),
+                    Pickable::IGNORE,
+                    ChildOf(grid_tray),
+                ));
+            }
+            // Hand the turn back to the student
+            next_turn.set(CurrentTurn(0));
+            next_game_op.set(GameOperation::Animating);
+            tutorial.forced = step.force.map(|(x, y)| (x as usize, y as usize));
+            tutorial.phase = if tutorial.forced.is_some() {
+                TutorialPhase::AwaitMove
+            } else {
+                TutorialPhase::Reading
+            };
+        }
+        TutorialPhase::AwaitMove => {
+            if *game_op == GameOperation::Human && current_turn.0 == 2 {
+                if let Some((x, y)) = step.reply {
+                    place_dot.write(PlaceDot {
+                        player: 2,
+                        x: x as usize,
+                        y: y as usize,
+                    });
+                    tutorial.phase = TutorialPhase::AwaitReply;
+                } else {
+                    tutorial.phase = TutorialPhase::Reading;
+                }
+            }
+        }
+        TutorialPhase::AwaitReply => {
+            if *game_op == GameOperation::Human && current_turn.0 == 1 {
+                tutorial.phase = TutorialPhase::Reading;
+            }
+        }
+        TutorialPhase::Reading => {}
+    }
+}
@@ -6,16 +6,21 @@ mod game_end;
 mod game_hud;
 mod host_game;
 mod join_game;
-mod rules;
+mod lobby;
+mod replays;
+mod servers;
 mod settings;
+mod tutorial;
+
+pub use host_game::MAX_ONLINE_PLAYERS;
 
 use bevy::{prelude::*, window::PrimaryWindow};
 
 use crate::{
-    Config, GameAssets, PlayerConfigEntry,
+    Config, GameAssets, MainState, PlayerConfigEntry,
     menu::MenuRadios,
-    net::NetMessage,
-    ui_menu::{custom_game_setup::render_player_config, rules::RulesPageNumber},
+    net::{MAX_RETRIES, NetMessage},
+    ui_menu::custom_game_setup::render_player_config,
 };
 
 #[derive(Component)]
@@ -37,40 +42,95 @@ pub struct HostGameUiTree;
 pub struct JoinGameUiTree;
 
 #[derive(Component)]
-pub struct RulesUiTree;
+pub struct WatchEntryUiTree;
+
+/// Whether the join screen is for taking a seat in a room, or watching a game that's already going.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JoinMode {
+    #[default]
+    Play,
+    Watch,
+}
+
+#[derive(Component)]
+pub struct ReplayListUiTree;
+
+#[derive(Component)]
+pub struct ResignUiTree;
+
+#[derive(Component)]
+pub struct ReplayUiTree;
+
+#[derive(Component)]
+pub struct ReplaysEntryUiTree;
+
+#[derive(Component)]
+pub struct ServersUiTree;
 
 #[derive(Component)]
 pub struct SettingsUiTree;
 
+#[derive(Component)]
+pub struct TutorialUiTree;
+
 #[derive(Component)]
 pub struct GameHudUiTree;
 
 pub fn plugin(app: &mut App) {
-    app.insert_resource(RulesPageNumber(1))
-        .insert_resource(CustomConfig(Config {
-            players: vec![],
-            grid_size: (6, 6),
-        }))
-        .add_systems(
-            Update,
-            (
-                update_config_from_buttons,
-                update_ui_scale,
-                render_player_config,
-                update_net_menus,
-                game_hud::run_menu,
-            ),
-        )
-        .add_systems(Startup, |mut commands: Commands, ga: Res<GameAssets>| {
-            commands.spawn(custom_game_setup::menu(&ga));
-            commands.spawn(game_end::menu(&ga));
-            commands.spawn(rules::menu(&ga));
-            commands.spawn(settings::menu(&ga));
-            commands.spawn(credits::menu(&ga));
-            commands.spawn(host_game::menu(&ga));
-            commands.spawn(join_game::menu(&ga));
-            commands.spawn(game_hud::menu(&ga));
-        });
+    app.insert_resource(CustomConfig(Config {
+        players: vec![],
+        grid_size: (6, 6),
+    }))
+    .init_resource::<EditingName>()
+    .init_resource::<custom_game_setup::PickingColor>()
+    .init_resource::<ServerForm>()
+    .init_resource::<servers::ServersReturnTo>()
+    .init_resource::<host_game::HostSettings>()
+    .init_resource::<JoinMode>()
+    .init_resource::<game_hud::ResignPrompt>()
+    .add_systems(
+        Update,
+        (
+            update_config_from_buttons,
+            update_ui_scale,
+            (custom_game_setup::edit_player_name, render_player_config).chain(),
+            custom_game_setup::render_grid_size,
+            settings::run_menu,
+            (update_net_menus, host_game::run_menu, lobby::run_list),
+            (join_game::run_menu, join_game::run_watch_entry),
+            game_hud::run_menu,
+            game_hud::run_scoreboard,
+            game_hud::run_connection_text,
+            game_hud::run_online_notice,
+            game_hud::run_move_list,
+            game_hud::scroll_move_list,
+            (game_hud::run_history_buttons, game_hud::run_draw_buttons, game_hud::run_resign_entry),
+            game_hud::run_reset_view_buttons,
+            tutorial::run_menu,
+            replays::run_entry,
+            replays::run_list,
+            replays::run_controls,
+            (servers::edit_server_form, servers::run_menu).chain(),
+        ),
+    )
+    .add_systems(OnEnter(MainState::Replay), replays::show_controls)
+    .add_systems(OnExit(MainState::Replay), replays::hide_controls)
+    .add_systems(Startup, |mut commands: Commands, ga: Res<GameAssets>| {
+        commands.spawn(custom_game_setup::menu(&ga));
+        commands.spawn(game_end::menu(&ga));
+        commands.spawn(tutorial::menu(&ga));
+        commands.spawn(settings::menu(&ga));
+        commands.spawn(credits::menu(&ga));
+        commands.spawn(host_game::menu(&ga));
+        commands.spawn(join_game::menu(&ga));
+        commands.spawn(join_game::watch_entry(&ga));
+        commands.spawn(servers::menu(&ga));
+        commands.spawn(game_hud::menu(&ga));
+        commands.spawn(game_hud::resign_entry(&ga));
+        commands.spawn(replays::entry(&ga));
+        commands.spawn(replays::list(&ga));
+        commands.spawn(replays::controls(&ga));
+    });
 }
 
 fn update_ui_scale(mut ui_scale: ResMut<UiScale>, windows: Query<&Window, With<PrimaryWindow>>) {
@@ -91,6 +151,28 @@ fn update_ui_scale(mut ui_scale: ResMut<UiScale>, windows: Query<&Window, With<P
 #[derive(Deref, DerefMut, Resource)]
 pub struct CustomConfig(pub Config);
 
+/// The custom setup player whose name is being typed in, if any.
+#[derive(Default, Resource)]
+pub struct EditingName(pub Option<usize>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerField {
+    Name,
+    Url,
+}
+
+/// The server being added or edited in the server list.
+#[derive(Default, Resource)]
+pub struct ServerForm {
+    pub name: String,
+    pub url: String,
+    /// The field being typed in, if any.
+    pub focus: Option<ServerField>,
+    /// The address of the server being edited, or `None` when adding a new one.
+    pub editing: Option<String>,
+    pub error: Option<&'static str>,
+}
+
 #[derive(Component)]
 pub struct InfoText;
 
@@ -192,6 +274,31 @@ pub fn update_net_menus(mut net_events: MessageReader<NetMessage>, mut info_text
                     text.0 = "Error: room not found".into();
                 }
             }
+            NetMessage::NoPublicRooms => {
+                for (mut node, mut text) in &mut info_texts {
+                    node.display = Display::Flex;
+                    text.0 = "No public games to join right now".into();
+                }
+            }
+            NetMessage::Refused { reason } => {
+                for (mut node, mut text) in &mut info_texts {
+                    node.display = Display::Flex;
+                    text.0 = format!("Error: {reason}");
+                }
+            }
+            NetMessage::Retrying { attempt, error } => {
+                for (mut node, mut text) in &mut info_texts {
+                    node.display = Display::Flex;
+                    text.0 = format!("Error: {error}, trying again ({attempt}/{MAX_RETRIES})...");
+                }
+            }
+            NetMessage::Failed(error) => {
+                for (mut node, mut text) in &mut info_texts {
+                    node.display = Display::Flex;
+                    text.0 = format!("Error: {error}");
+                }
+            }
+            NetMessage::Warning(error) => warn!("{error}"),
         }
     }
 }
@@ -1,9 +1,15 @@
-use bevy::prelude::*;
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
 
 use crate::{
-    PlayerConfigEntry,
+    Ais, MAX_PLAYERS, PLAYER_COLORS, PlayerConfigEntry,
     menu::{MainMenuSubState, MenuState},
-    ui_menu::CustomConfig,
+    ui_menu::{CustomConfig, EditingName},
 };
 
 use super::{CustomGameSetupUiTree, support::*};
@@ -14,11 +20,13 @@ pub struct PlayerConfigPlusLabel;
 #[derive(Component)]
 pub struct PlayerConfigMinusLabel;
 
+#[derive(Component)]
+pub struct WidthText;
+
+#[derive(Component)]
+pub struct HeightText;
+
 pub fn menu(ga: &GameAssets) -> impl Bundle {
-    #[derive(Component)]
-    struct WidthText;
-    #[derive(Component)]
-    struct HeightText;
     (
         CustomGameSetupUiTree,
         Node {
@@ -39,13 +47,10 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                     display: Display::Block,
                     ..default()
                 },
-                children![
-                    h2(ga, "Players"),
-                    player_config(ga, 0),
-                    player_config(ga, 1),
-                    player_config(ga, 2),
-                    player_config(ga, 3),
-                    (
+                Children::spawn((
+                    Spawn(h2(ga, "Players")),
+                    SpawnIter((0..MAX_PLAYERS).map(|player_idx| player_config(ga, player_idx)).collect::<Vec<_>>().into_iter()),
+                    Spawn((
                         Node {
                             margin: UiRect::vertical(Val::Px(5.0)),
                             ..default()
@@ -55,11 +60,10 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                                 PlayerConfigPlusLabel,
                                 button_default_bg(ga, "+"),
                                 observe(move |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>| {
-                                    for player in config.players.iter_mut() {
-                                        if player.is_disabled() {
-                                            player.to_human();
-                                            break;
-                                        }
+                                    if let Some(player_idx) = config.players.iter().position(PlayerConfigEntry::is_disabled) {
+                                        config.players[player_idx].to_human();
+                                        let color = free_color(&config.players, player_idx);
+                                        config.players[player_idx].set_color(color);
                                     }
                                 })
                             ),
@@ -76,8 +80,8 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                                 })
                             ),
                         ]
-                    )
-                ],
+                    )),
+                )),
             ),
             (
                 Node {
@@ -99,28 +103,22 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                             p(ga, "width: "),
                             (
                                 left_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>, mut width_text: Query<&mut Text, With<WidthText>>| {
-                                        config.grid_size.0 -= 1;
-                                        if config.grid_size.0 < 1 {
-                                            config.grid_size.0 = 1;
-                                        }
-                                        width_text.single_mut().unwrap().0 = format!("{:>2}", config.grid_size.0);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>| {
+                                    config.grid_size.0 -= 1;
+                                    if config.grid_size.0 < 1 {
+                                        config.grid_size.0 = 1;
+                                    }
+                                })
                             ),
                             (p(ga, " 6"), WidthText),
                             (
                                 right_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>, mut width_text: Query<&mut Text, With<WidthText>>| {
-                                        config.grid_size.0 += 1;
-                                        if config.grid_size.0 > 20 {
-                                            config.grid_size.0 = 20;
-                                        }
-                                        width_text.single_mut().unwrap().0 = format!("{:>2}", config.grid_size.0);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>| {
+                                    config.grid_size.0 += 1;
+                                    if config.grid_size.0 > 20 {
+                                        config.grid_size.0 = 20;
+                                    }
+                                })
                             )
                         ]
                     ),
@@ -136,28 +134,22 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                             p(ga, "height: "),
                             (
                                 left_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>, mut height_text: Query<&mut Text, With<HeightText>>| {
-                                        config.grid_size.1 -= 1;
-                                        if config.grid_size.1 < 1 {
-                                            config.grid_size.1 = 1;
-                                        }
-                                        height_text.single_mut().unwrap().0 = format!("{:>2}", config.grid_size.1);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>| {
+                                    config.grid_size.1 -= 1;
+                                    if config.grid_size.1 < 1 {
+                                        config.grid_size.1 = 1;
+                                    }
+                                })
                             ),
                             (p(ga, " 6"), HeightText),
                             (
                                 right_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>, mut height_text: Query<&mut Text, With<HeightText>>| {
-                                        config.grid_size.1 += 1;
-                                        if config.grid_size.1 > 20 {
-                                            config.grid_size.1 = 20;
-                                        }
-                                        height_text.single_mut().unwrap().0 = format!("{:>2}", config.grid_size.1);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>| {
+                                    config.grid_size.1 += 1;
+                                    if config.grid_size.1 > 20 {
+                                        config.grid_size.1 = 20;
+                                    }
+                                })
                             )
                         ]
                     )
@@ -177,6 +169,23 @@ pub struct PlayerConfigHumanLabel(usize);
 #[derive(Component)]
 pub struct PlayerConfigBotLabel(usize);
 
+#[derive(Component)]
+pub struct PlayerNameButton(usize);
+
+#[derive(Component)]
+pub struct PlayerColorButton(usize);
+
+/// One of the colours shown for a player to pick from.
+#[derive(Component)]
+pub struct PlayerColorChoice {
+    player_idx: usize,
+    color_idx: usize,
+}
+
+/// The player whose colour choices are open, if any.
+#[derive(Default, Resource)]
+pub struct PickingColor(Option<usize>);
+
 #[derive(Component)]
 pub struct PlayerConfigBotLevelLabel {
     player_idx: usize,
@@ -192,6 +201,36 @@ fn player_config(ga: &GameAssets, player_idx: usize) -> impl Bundle {
         },
         PlayerConfigLabel(player_idx),
         children![
+            (
+                PlayerColorButton(player_idx),
+                Node {
+                    width: Val::Px(22.0),
+                    margin: UiRect::horizontal(Val::Px(5.0)),
+                    border_radius: BorderRadius::all(Val::Px(5.0)),
+                    ..default()
+                },
+                Button,
+                AnimateBackgroundColor,
+                BackgroundColor(PLAYER_COLORS[player_idx]),
+                Outline::new(Val::Px(3.0), Val::Px(0.0), Color::WHITE),
+                observe(move |_: On<Pointer<Click>>, mut picking: ResMut<PickingColor>| {
+                    picking.0 = if picking.0 == Some(player_idx) { None } else { Some(player_idx) };
+                }),
+            ),
+            (
+                Node {
+                    display: Display::Flex,
+                    ..default()
+                },
+                Children::spawn(SpawnIter((0..PLAYER_COLORS.len()).map(move |color_idx| color_choice(player_idx, color_idx)))),
+            ),
+            (
+                PlayerNameButton(player_idx),
+                button_default_bg(ga, ""),
+                observe(move |_: On<Pointer<Click>>, mut editing: ResMut<EditingName>| {
+                    editing.0 = if editing.0 == Some(player_idx) { None } else { Some(player_idx) };
+                }),
+            ),
             (
                 PlayerConfigHumanLabel(player_idx),
                 Node {
@@ -252,6 +291,62 @@ fn player_config(ga: &GameAssets, player_idx: usize) -> impl Bundle {
     )
 }
 
+/// A colour `player_idx` can switch to. Only shown while their choices are open and nobody else has it.
+fn color_choice(player_idx: usize, color_idx: usize) -> impl Bundle {
+    (
+        PlayerColorChoice { player_idx, color_idx },
+        Node {
+            display: Display::None,
+            width: Val::Px(16.0),
+            margin: UiRect::right(Val::Px(3.0)),
+            border_radius: BorderRadius::all(Val::Px(4.0)),
+            ..default()
+        },
+        Button,
+        AnimateBackgroundColor,
+        BackgroundColor(PLAYER_COLORS[color_idx]),
+        observe(
+            move |_: On<Pointer<Click>>, mut config: ResMut<CustomConfig>, mut picking: ResMut<PickingColor>| {
+                let color = PLAYER_COLORS[color_idx];
+                if !color_taken(&config.players, player_idx, color) {
+                    config.players[player_idx].set_color(color);
+                }
+                picking.0 = None;
+            },
+        ),
+    )
+}
+
+/// The longest name that can be typed in, in characters.
+const MAX_NAME_LEN: usize = 16;
+
+fn color_taken(players: &[PlayerConfigEntry], player_idx: usize, color: Color) -> bool {
+    players
+        .iter()
+        .enumerate()
+        .any(|(i, player)| i != player_idx && !player.is_disabled() && player.color() == color)
+}
+
+/// The first colour after this player's current one that no other player has.
+fn next_free_color(players: &[PlayerConfigEntry], player_idx: usize) -> Color {
+    let current = players[player_idx].color();
+    let start = PLAYER_COLORS.iter().position(|&color| color == current).map_or(0, |i| i + 1);
+    (0..PLAYER_COLORS.len())
+        .map(|i| PLAYER_COLORS[(start + i) % PLAYER_COLORS.len()])
+        .find(|&color| !color_taken(players, player_idx, color))
+        .unwrap_or(current)
+}
+
+/// This player's current colour, unless someone else already has it.
+fn free_color(players: &[PlayerConfigEntry], player_idx: usize) -> Color {
+    let current = players[player_idx].color();
+    if color_taken(players, player_idx, current) {
+        next_free_color(players, player_idx)
+    } else {
+        current
+    }
+}
+
 pub fn render_player_config(
     mut config: ResMut<CustomConfig>,
     mut node: ParamSet<(
@@ -260,18 +355,33 @@ pub fn render_player_config(
         Query<(&mut Node, &PlayerConfigLabel)>,
         Query<(&mut Node, &PlayerConfigHumanLabel)>,
         Query<(&mut Node, &PlayerConfigBotLabel)>,
+        Query<(&mut Node, &PlayerColorChoice)>,
     )>,
-    mut button_colors: Query<(&mut BackgroundColor, &PlayerConfigBotLevelLabel)>,
+    mut button_colors: Query<(&mut BackgroundColor, &PlayerConfigBotLevelLabel), Without<PlayerColorButton>>,
+    mut swatches: Query<(&mut BackgroundColor, &PlayerColorButton)>,
+    name_buttons: Query<(&Children, &PlayerNameButton)>,
+    mut texts: Query<&mut Text>,
+    (editing, picking): (Res<EditingName>, Res<PickingColor>),
+    ais: Res<Ais>,
 ) {
     if config.players.is_empty() {
         return;
-    } else if config.players.len() < 4 {
-        assert!(config.players.len() == 2);
-        config.players.push(PlayerConfigEntry::default_for_player(3));
-        config.players.push(PlayerConfigEntry::default_for_player(4));
     }
-    node.p0().single_mut().unwrap().display = if config.players[2].is_disabled() { Display::None } else { Display::Block };
-    node.p1().single_mut().unwrap().display = if config.players[3].is_disabled() { Display::Block } else { Display::None };
+    while config.players.len() < MAX_PLAYERS {
+        let player = PlayerConfigEntry::default_for_player(config.players.len() + 1);
+        config.players.push(player);
+    }
+    // Two players can't share a colour; whoever comes later gives way
+    for player_idx in 0..config.players.len() {
+        let color = free_color(&config.players, player_idx);
+        if color != config.players[player_idx].color() {
+            config.players[player_idx].set_color(color);
+        }
+    }
+
+    let enabled = config.players.iter().filter(|player| !player.is_disabled()).count();
+    node.p0().single_mut().unwrap().display = if enabled > 2 { Display::Block } else { Display::None };
+    node.p1().single_mut().unwrap().display = if enabled < MAX_PLAYERS { Display::Block } else { Display::None };
     for (mut node, player) in &mut node.p2() {
         node.display = if config.players[player.0].is_disabled() {
             Display::None
@@ -285,6 +395,10 @@ pub fn render_player_config(
     for (mut node, player) in &mut node.p4() {
         node.display = if config.players[player.0].is_bot() { Display::Flex } else { Display::None };
     }
+    for (mut node, PlayerColorChoice { player_idx, color_idx }) in &mut node.p5() {
+        let shown = picking.0 == Some(*player_idx) && !color_taken(&config.players, *player_idx, PLAYER_COLORS[*color_idx]);
+        node.display = if shown { Display::Flex } else { Display::None };
+    }
     for (mut color, PlayerConfigBotLevelLabel { player_idx, level }) in &mut button_colors {
         color.0 = if config.players[*player_idx].level() == *level {
             Color::srgba(0.4, 0.4, 0.4, color.0.alpha())
@@ -292,4 +406,86 @@ pub fn render_player_config(
             Color::srgba(0.2, 0.2, 0.2, color.0.alpha())
         };
     }
+    for (mut color, player) in &mut swatches {
+        color.0 = config.players[player.0].color().with_alpha(color.0.alpha());
+    }
+    for (children, player) in &name_buttons {
+        let name = if editing.0 == Some(player.0) {
+            format!("{}_", config.players[player.0].name())
+        } else {
+            config.players[player.0].display_name(player.0 + 1, &ais)
+        };
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            let name = format!("{name:<width$}", width = MAX_NAME_LEN + 1);
+            if text.0 != name {
+                text.0 = name;
+            }
+        }
+    }
+}
+
+/// Types into the name of the player being edited, until Enter or Escape.
+pub fn edit_player_name(
+    mut editing: ResMut<EditingName>,
+    mut keys: MessageReader<KeyboardInput>,
+    mut config: ResMut<CustomConfig>,
+    setup_ui_tree: Query<&Visibility, With<CustomGameSetupUiTree>>,
+) {
+    let Some(player_idx) = editing.0 else {
+        keys.clear();
+        return;
+    };
+    if setup_ui_tree.single().is_ok_and(|visibility| *visibility == Visibility::Hidden)
+        || config.players.get(player_idx).is_none_or(PlayerConfigEntry::is_disabled)
+    {
+        editing.0 = None;
+        return;
+    }
+    // Whatever started the editing (like Enter on a focused button) shouldn't also be typed
+    if editing.is_changed() {
+        keys.clear();
+        return;
+    }
+
+    let mut name = config.players[player_idx].name().to_owned();
+    for key in keys.read().filter(|key| key.state == ButtonState::Pressed) {
+        match &key.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LEN {
+                        name.push(c);
+                    }
+                }
+            }
+            Key::Space if name.chars().count() < MAX_NAME_LEN => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter | Key::Escape => {
+                name = name.trim().to_owned();
+                editing.0 = None;
+            }
+            _ => {}
+        }
+    }
+    if name != config.players[player_idx].name() {
+        config.players[player_idx].set_name(name);
+    }
+}
+
+pub fn render_grid_size(
+    config: Res<CustomConfig>,
+    mut width_text: Query<&mut Text, (With<WidthText>, Without<HeightText>)>,
+    mut height_text: Query<&mut Text, With<HeightText>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for mut text in &mut width_text {
+        text.0 = format!("{:>2}", config.grid_size.0);
+    }
+    for mut text in &mut height_text {
+        text.0 = format!("{:>2}", config.grid_size.1);
+    }
 }
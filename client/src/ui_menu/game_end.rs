@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::anim::TargetMaterialColor;
+
 use super::{GameEndText, GameEndUiTree, support::*};
 
 pub fn menu(ga: &GameAssets) -> impl Bundle {
@@ -22,6 +24,7 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                 },
                 h1(ga, "Player 1 wins!"),
                 GameEndText,
+                TargetMaterialColor(Color::WHITE),
             ),
             back_to_main_menu::<GameEndUiTree>(ga)
         ],
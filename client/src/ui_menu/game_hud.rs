@@ -1,11 +1,98 @@
-use crate::{Config, CurrentTurn, GameOperation, MainState, ai::Ais, anim::TargetMaterialColor, menu::MenuState, ui_menu::GameHudUiTree};
+use crate::{
+    CellColor, Config, CurrentTurn, DotCell, EndGame, GameOperation, MainState, PlayerConfigEntry,
+    ai::Ais,
+    anim::{CurrentUiOpacity, TargetMaterialColor},
+    camera::FreeCamera,
+    clock::{GameClocks, format_clock},
+    history::{GameHistory, HistoryPreview, HistoryStep, MoveLog, PreviewMove, is_local_game},
+    menu::MenuState,
+    net::{
+        ConnectionStatus, GameServerbound, LeaveReason, MAX_RETRIES, MoveError, NetManagerMessage, NetServerboundSender, OnlineGame, OnlineNotice, RoomServer,
+    },
+    palette::PaletteSettings,
+    tutorial::Tutorial,
+    ui_menu::{GameHudUiTree, ResignUiTree},
+};
+use common::proto::ProposalType;
 
 use super::support::*;
-use bevy::prelude::*;
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    ui::RelativeCursorPosition,
+};
 
 #[derive(Component)]
 pub struct HudInfoText;
 
+#[derive(Component)]
+pub struct HistoryButton(HistoryStep);
+
+/// Holds one [`ScoreRow`] per player, rebuilt whenever the players change.
+#[derive(Component)]
+pub struct Scoreboard;
+
+/// A player's line on the scoreboard. The player is 1-indexed.
+#[derive(Component)]
+pub struct ScoreRow(usize);
+
+#[derive(Component)]
+pub struct ScoreSwatch;
+
+#[derive(Component)]
+pub struct ConnectionText;
+
+/// Briefly shows what the server said, like a player leaving.
+#[derive(Component)]
+pub struct OnlineNoticeText;
+
+/// How long an [`OnlineNotice`] stays up.
+const NOTICE_SECS: f64 = 4.0;
+
+/// The side panel listing every move. Its children are rebuilt as moves are made.
+#[derive(Component)]
+pub struct MoveList;
+
+/// An entry in the [`MoveList`], by index into the [`MoveLog`].
+#[derive(Component)]
+pub struct MoveEntry(usize);
+
+#[derive(Component)]
+pub struct ClosePreviewButton;
+
+/// Offers a draw, or accepts one that's been offered.
+#[derive(Component)]
+pub struct DrawButton;
+
+/// Turns down a draw offer, or takes back this device's.
+#[derive(Component)]
+pub struct DeclineDrawButton;
+
+#[derive(Component)]
+pub struct ResignButton;
+
+/// Part of the question asked before resigning.
+#[derive(Component)]
+pub struct ConfirmResign;
+
+/// The resign button has been pressed, and is waiting to be confirmed.
+#[derive(Resource, Default)]
+pub struct ResignPrompt(bool);
+
+/// Puts the camera back where the game had it. Only shown once the player has moved it.
+#[derive(Component)]
+pub struct ResetViewButton;
+
+pub fn reset_view_button(ga: &GameAssets) -> impl Bundle {
+    (
+        ResetViewButton,
+        button_default_bg(ga, "Reset view"),
+        observe(|_: On<Pointer<Click>>, mut free_camera: ResMut<FreeCamera>| {
+            free_camera.reset();
+        }),
+    )
+}
+
 pub fn menu(ga: &GameAssets) -> impl Bundle {
     (
         Node {
@@ -19,6 +106,28 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
         GameHudUiTree,
         Visibility::Hidden,
         children![
+            (
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: px(15.0),
+                    top: px(15.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                Pickable::IGNORE,
+                children![
+                    (
+                        Scoreboard,
+                        Node {
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        },
+                        Pickable::IGNORE,
+                    ),
+                    (ConnectionText, p(ga, ""), Pickable::IGNORE),
+                    (OnlineNoticeText, p(ga, ""), Pickable::IGNORE),
+                ],
+            ),
             (
                 Node {
                     display: Display::Block,
@@ -27,15 +136,91 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                     padding: UiRect::all(px(15.0)),
                     ..default()
                 },
-                children![(
-                    button_default_bg(ga, "Pause"),
-                    observe(
-                        |_: On<Pointer<Click>>, mut main_state: ResMut<NextState<MainState>>, mut menu_state: ResMut<NextState<MenuState>>| {
-                            main_state.set(MainState::Menu);
-                            menu_state.set(MenuState::Pause);
-                        }
+                children![
+                    (
+                        button_default_bg(ga, "Undo"),
+                        HistoryButton(HistoryStep::Undo),
+                        observe(|_: On<Pointer<Click>>, mut steps: MessageWriter<HistoryStep>| {
+                            steps.write(HistoryStep::Undo);
+                        }),
+                    ),
+                    (
+                        button_default_bg(ga, "Redo"),
+                        HistoryButton(HistoryStep::Redo),
+                        observe(|_: On<Pointer<Click>>, mut steps: MessageWriter<HistoryStep>| {
+                            steps.write(HistoryStep::Redo);
+                        }),
                     ),
-                )]
+                    (
+                        ClosePreviewButton,
+                        button_default_bg(ga, "Back to game"),
+                        observe(|_: On<Pointer<Click>>, mut preview: MessageWriter<PreviewMove>| {
+                            preview.write(PreviewMove(None));
+                        }),
+                    ),
+                    (
+                        button_default_bg(ga, "Moves"),
+                        observe(
+                            |_: On<Pointer<Click>>, mut move_list: Query<&mut Node, With<MoveList>>, mut preview: MessageWriter<PreviewMove>| {
+                                for mut node in &mut move_list {
+                                    node.display = if node.display == Display::None {
+                                        Display::Flex
+                                    } else {
+                                        // Closing the list goes back to the game too
+                                        preview.write(PreviewMove(None));
+                                        Display::None
+                                    };
+                                }
+                            }
+                        ),
+                    ),
+                    reset_view_button(ga),
+                    (
+                        DrawButton,
+                        button_default_bg(ga, "Offer draw"),
+                        observe(|_: On<Pointer<Click>>, net_tx: Res<NetServerboundSender>| {
+                            net_tx
+                                .force_send(NetManagerMessage::Game(GameServerbound::Propose { kind: ProposalType::Draw }))
+                                .unwrap();
+                        }),
+                    ),
+                    (
+                        DeclineDrawButton,
+                        button_default_bg(ga, "Decline draw"),
+                        observe(|_: On<Pointer<Click>>, net_tx: Res<NetServerboundSender>| {
+                            net_tx
+                                .force_send(NetManagerMessage::Game(GameServerbound::CancelProposal { kind: ProposalType::Draw }))
+                                .unwrap();
+                        }),
+                    ),
+                    (
+                        button_default_bg(ga, "Pause"),
+                        observe(
+                            |_: On<Pointer<Click>>, mut main_state: ResMut<NextState<MainState>>, mut menu_state: ResMut<NextState<MenuState>>| {
+                                main_state.set(MainState::Menu);
+                                menu_state.set(MenuState::Pause);
+                            }
+                        ),
+                    ),
+                ]
+            ),
+            (
+                MoveList,
+                Node {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    right: px(15.0),
+                    top: px(60.0),
+                    width: px(240.0),
+                    max_height: percent(60),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(px(5.0)),
+                    border_radius: BorderRadius::all(px(5.0)),
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+                BackgroundColor(Color::NONE),
+                RelativeCursorPosition::default(),
             ),
             (Node { flex_grow: 1.0, ..default() }, Pickable::IGNORE),
             (
@@ -60,6 +245,10 @@ pub fn run_menu(
     current_turn: Res<State<CurrentTurn>>,
     config: Res<Config>,
     ais: Res<Ais>,
+    palette: Res<PaletteSettings>,
+    preview: Res<HistoryPreview>,
+    time: Res<Time>,
+    online: Res<OnlineGame>,
 ) {
     for (mut node, mut text, mut target_color) in hud_info_text {
         match game_op.get() {
@@ -67,38 +256,463 @@ pub fn run_menu(
                 node.align_self = AlignSelf::FlexEnd;
                 target_color.0 = Color::srgba(1.0, 1.0, 1.0, 0.0);
             }
+            GameOperation::Human | GameOperation::Bot if let Some(shown) = preview.0 => {
+                node.align_self = AlignSelf::FlexEnd;
+                target_color.0 = Color::WHITE;
+                text.0 = format!("Showing the board after move {}", shown + 1);
+            }
             GameOperation::Human => {
                 node.align_self = AlignSelf::FlexEnd;
                 let Some(player) = config.players.get(current_turn.0 - 1) else {
-                    dbg!("invalid player, bailing out");
+                    debug!("invalid player, bailing out");
                     continue;
                 };
-                target_color.0 = player.color();
-                text.0 = format!("{}, your turn", player.name());
+                target_color.0 = palette.player_color(current_turn.0, player);
+                text.0 = format!("{}, your turn", player.display_name(current_turn.0, &ais));
             }
             GameOperation::Bot => {
                 node.align_self = AlignSelf::FlexEnd;
                 let Some(player) = config.players.get(current_turn.0 - 1) else {
-                    dbg!("invalid player, bailing out");
+                    debug!("invalid player, bailing out");
                     continue;
                 };
-                target_color.0 = player.color();
-                text.0 = format!("{} is thinking...", ais[player.level()].name());
+                target_color.0 = palette.player_color(current_turn.0, player);
+                // The dots tick along so it's clear the bot hasn't hung
+                let dots = ".".repeat((time.elapsed_secs() * 3.0) as usize % 3 + 1);
+                text.0 = format!("{} is thinking{dots:<3}", player.display_name(current_turn.0, &ais));
             }
             GameOperation::OnlinePlayer => {
                 node.align_self = AlignSelf::FlexEnd;
                 let Some(player) = config.players.get(current_turn.0 - 1) else {
-                    dbg!("invalid player, bailing out");
+                    debug!("invalid player, bailing out");
                     continue;
                 };
-                target_color.0 = player.color();
-                text.0 = format!("Waiting for {}...", player.name());
+                target_color.0 = palette.player_color(current_turn.0, player);
+                text.0 = format!("Waiting for {}...", player.display_name(current_turn.0, &ais));
             }
             GameOperation::Connecting => {
                 node.align_self = AlignSelf::Center;
                 target_color.0 = Color::srgba(1.0, 1.0, 1.0, 1.0);
-                text.0 = "Connecting...".into();
+                text.0 = match online.waiting_for {
+                    n if online.me.is_some() || (online.spectating && n == 0) => "Waiting for the server...".into(),
+                    0 => "Connecting...".into(),
+                    1 => "Waiting for 1 more player to join...".into(),
+                    n => format!("Waiting for {n} more players to join..."),
+                };
+            }
+        }
+    }
+}
+
+pub fn run_history_buttons(
+    buttons: Query<(&mut Node, &HistoryButton)>,
+    history: Res<GameHistory>,
+    config: Res<Config>,
+    tutorial: Res<Tutorial>,
+    game_op: Res<State<GameOperation>>,
+    preview: Res<HistoryPreview>,
+) {
+    let local = is_local_game(&config, &tutorial);
+    for (mut node, button) in buttons {
+        let available = match button.0 {
+            HistoryStep::Undo => history.can_undo(&config),
+            HistoryStep::Redo => history.can_redo(&config),
+        };
+        node.display = if local && available && *game_op == GameOperation::Human && preview.0.is_none() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+/// Offering, accepting and turning down draws, for players still in an online game.
+pub fn run_draw_buttons(
+    mut buttons: Query<(&mut Node, &Children, Has<DeclineDrawButton>), Or<(With<DrawButton>, With<DeclineDrawButton>)>>,
+    mut texts: Query<&mut Text>,
+    online: Res<OnlineGame>,
+    config: Res<Config>,
+) {
+    let playing = config.players.iter().any(PlayerConfigEntry::online) && online.playing();
+    let offered = online.me.is_some_and(|me| online.draw_offers.contains(&me));
+    for (mut node, children, decline) in &mut buttons {
+        let (shown, label) = match (decline, offered) {
+            (true, true) => (true, "Withdraw offer"),
+            (true, false) => (!online.draw_offers.is_empty(), "Decline draw"),
+            (false, true) => (false, ""),
+            (false, false) if online.draw_offers.is_empty() => (true, "Offer draw"),
+            (false, false) => (true, "Accept draw"),
+        };
+        node.display = if playing && shown { Display::Flex } else { Display::None };
+        let mut labels = texts.iter_many_mut(children);
+        while let Some(mut text) = labels.fetch_next() {
+            if !label.is_empty() && text.0 != label {
+                text.0 = label.into();
+            }
+        }
+    }
+}
+
+/// The "Resign" button shown with the pause menu, in online games. It asks again before going through with it.
+pub fn resign_entry(ga: &GameAssets) -> impl Bundle {
+    (
+        ResignUiTree,
+        Node {
+            position_type: PositionType::Absolute,
+            left: px(15.0),
+            bottom: px(15.0),
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: px(10.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        children![
+            (
+                ResignButton,
+                button_default_bg(ga, "Resign"),
+                observe(|_: On<Pointer<Click>>, mut prompt: ResMut<ResignPrompt>| {
+                    prompt.0 = true;
+                }),
+            ),
+            (ConfirmResign, p(ga, "Resign this game?")),
+            (
+                ConfirmResign,
+                button_default_bg(ga, "Yes, resign"),
+                observe(
+                    |_: On<Pointer<Click>>,
+                     mut prompt: ResMut<ResignPrompt>,
+                     net_tx: Res<NetServerboundSender>,
+                     mut main_state: ResMut<NextState<MainState>>| {
+                        prompt.0 = false;
+                        net_tx.force_send(NetManagerMessage::Game(GameServerbound::Resign)).unwrap();
+                        // Back to the board, to watch the rest of the game or see who won
+                        main_state.set(MainState::Game);
+                    }
+                ),
+            ),
+            (
+                ConfirmResign,
+                button_default_bg(ga, "Keep playing"),
+                observe(|_: On<Pointer<Click>>, mut prompt: ResMut<ResignPrompt>| {
+                    prompt.0 = false;
+                }),
+            ),
+        ],
+    )
+}
+
+pub fn run_resign_entry(
+    menu_state: Option<Res<State<MenuState>>>,
+    mut entry: Query<&mut Visibility, With<ResignUiTree>>,
+    mut parts: Query<(&mut Node, Has<ConfirmResign>), Or<(With<ResignButton>, With<ConfirmResign>)>>,
+    mut ui_opacity: ResMut<TargetUiOpacity>,
+    mut prompt: ResMut<ResignPrompt>,
+    (online, config): (Res<OnlineGame>, Res<Config>),
+    mut shown_for: Local<f32>,
+    time: Res<Time>,
+) {
+    let Ok(mut visibility) = entry.single_mut() else {
+        return;
+    };
+    let playing = config.players.iter().any(PlayerConfigEntry::online) && online.playing();
+    if menu_state.map(|x| **x) != Some(MenuState::Pause) || !playing {
+        *shown_for = 0.0;
+        *visibility = Visibility::Hidden;
+        if prompt.0 {
+            prompt.0 = false;
+        }
+        return;
+    }
+    for (mut node, confirm) in &mut parts {
+        node.display = if confirm == prompt.0 { Display::Flex } else { Display::None };
+    }
+    *shown_for += time.delta_secs();
+    // Give the game HUD time to fade out first
+    if *shown_for > 0.8 && *visibility == Visibility::Hidden {
+        *visibility = Visibility::Inherited;
+        ui_opacity.0 = 1.0;
+    }
+}
+
+pub fn run_reset_view_buttons(buttons: Query<&mut Node, With<ResetViewButton>>, free_camera: Res<FreeCamera>) {
+    for mut node in buttons {
+        node.display = if free_camera.moved() { Display::Flex } else { Display::None };
+    }
+}
+
+fn score_row(ga: &GameAssets, player: usize) -> impl Bundle {
+    (
+        ScoreRow(player),
+        Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            margin: UiRect::bottom(px(4.0)),
+            ..default()
+        },
+        Pickable::IGNORE,
+        children![
+            (p(ga, " "), Pickable::IGNORE),
+            (
+                ScoreSwatch,
+                Node {
+                    width: px(14.0),
+                    height: px(14.0),
+                    margin: UiRect::horizontal(px(6.0)),
+                    border_radius: BorderRadius::all(px(3.0)),
+                    ..default()
+                },
+                AnimateBackgroundColor,
+                BackgroundColor(Color::WHITE),
+                Pickable::IGNORE,
+            ),
+            (p(ga, ""), Pickable::IGNORE),
+        ],
+    )
+}
+
+/// Whose turn it is, the time on everyone's clock, and how much of the board and how many dots each player has.
+pub fn run_scoreboard(
+    mut commands: Commands,
+    ga: Res<GameAssets>,
+    scoreboard: Query<(Entity, Option<&Children>), With<Scoreboard>>,
+    rows: Query<(&ScoreRow, &Children)>,
+    mut swatches: Query<&mut BackgroundColor, With<ScoreSwatch>>,
+    mut texts: Query<&mut Text>,
+    cells: Query<(&DotCell, &CellColor)>,
+    current_turn: Res<State<CurrentTurn>>,
+    config: Res<Config>,
+    history: Res<GameHistory>,
+    ais: Res<Ais>,
+    palette: Res<PaletteSettings>,
+    (online, clocks): (Res<OnlineGame>, Res<GameClocks>),
+) {
+    let Ok((scoreboard, shown_rows)) = scoreboard.single() else {
+        return;
+    };
+    if shown_rows.map_or(0, |rows| rows.len()) != config.players.len() {
+        commands.entity(scoreboard).despawn_children();
+        for player in 1..=config.players.len() {
+            commands.spawn((score_row(&ga, player), ChildOf(scoreboard)));
+        }
+        return;
+    }
+
+    let mut territory = vec![0; config.players.len() + 1];
+    let mut dots = vec![0; config.players.len() + 1];
+    for (cell, color) in &cells {
+        if let Some(count) = territory.get_mut(color.player) {
+            *count += 1;
+            dots[color.player] += cell.dots.len();
+        }
+    }
+    for (row, children) in &rows {
+        let Some(player) = config.players.get(row.0 - 1) else {
+            continue;
+        };
+        // Out once they've had a go and lost every cell, the server says they've left, or their time ran out here
+        let left = online
+            .leave_reason(row.0)
+            .filter(|_| config.players.iter().any(PlayerConfigEntry::online))
+            .or_else(|| clocks.flagged.contains(&row.0).then_some(LeaveReason::TimedOut));
+        let eliminated = left.is_some() || territory[row.0] == 0 && history.played_moves().iter().any(|m| m.player == row.0);
+        let marker = if eliminated {
+            "x"
+        } else if current_turn.0 == row.0 {
+            ">"
+        } else {
+            " "
+        };
+        let stats = if let Some(reason @ (LeaveReason::Disconnected | LeaveReason::Resigned | LeaveReason::TimedOut)) = left {
+            format!("out ({})", reason.describe())
+        } else if eliminated {
+            "out".to_owned()
+        } else if online.away.contains(&(row.0 as u8)) {
+            format!("{} cells, reconnecting...", territory[row.0])
+        } else {
+            format!("{} cells, {} dots", territory[row.0], dots[row.0])
+        };
+        let clock = match clocks.remaining.get(row.0 - 1) {
+            Some(&secs) if !eliminated => format!("{:>7}  ", format_clock(secs)),
+            Some(_) => format!("{:>7}  ", ""),
+            None => String::new(),
+        };
+        let line = format!("{:<16} {clock}{stats}", player.display_name(row.0, &ais));
+        for (i, child) in children.iter().enumerate() {
+            if let Ok(mut color) = swatches.get_mut(child) {
+                color.0 = palette.player_color(row.0, player).with_alpha(color.0.alpha());
+            } else if let Ok(mut text) = texts.get_mut(child) {
+                let new = if i == 0 { marker } else { &line };
+                if text.0 != *new {
+                    text.0 = new.to_owned();
+                }
             }
         }
     }
 }
+
+pub fn run_connection_text(
+    mut connection_text: Query<&mut Text, With<ConnectionText>>,
+    status: Res<ConnectionStatus>,
+    room_server: Res<RoomServer>,
+    (config, online_game): (Res<Config>, Res<OnlineGame>),
+    end_game: Option<Res<State<EndGame>>>,
+) {
+    let online = config.players.iter().any(PlayerConfigEntry::online) && !end_game.is_some_and(|x| x.game_ended);
+    let server = room_server.0.as_ref().map_or("the server", |x| &x.name);
+    let new = match *status {
+        _ if !online => String::new(),
+        ConnectionStatus::Offline => String::new(),
+        ConnectionStatus::Connecting => format!("Connecting to {server}..."),
+        ConnectionStatus::Connected if online_game.spectating => format!("Watching on {server}"),
+        ConnectionStatus::Connected => format!("Connected to {server}"),
+        ConnectionStatus::Reconnecting { attempt } => format!("Connection lost, reconnecting to {server} ({attempt}/{MAX_RETRIES})..."),
+        ConnectionStatus::Lost => "Connection lost".to_owned(),
+    };
+    for mut text in &mut connection_text {
+        if text.0 != new {
+            text.0.clone_from(&new);
+        }
+    }
+}
+
+pub fn run_online_notice(
+    mut notice_text: Query<&mut Text, With<OnlineNoticeText>>,
+    online: Res<OnlineGame>,
+    config: Res<Config>,
+    ais: Res<Ais>,
+    time: Res<Time>,
+) {
+    let notice = online.notice.filter(|&(_, at)| time.elapsed_secs_f64() - at < NOTICE_SECS);
+    let name = |player: u8| {
+        let player = usize::from(player);
+        config
+            .players
+            .get(player.wrapping_sub(1))
+            .map_or_else(|| format!("Player {player}"), |x| x.display_name(player, &ais))
+    };
+    let new = match notice {
+        None => String::new(),
+        Some((OnlineNotice::Eliminated { player, .. }, _)) if Some(player) == online.me => "You're out of the game".to_owned(),
+        Some((OnlineNotice::Eliminated { player, reason }, _)) => format!("{} {}", name(player), reason.describe()),
+        Some((OnlineNotice::Away { player }, _)) => format!("{} lost their connection, holding their seat for a minute", name(player)),
+        Some((OnlineNotice::Back { player }, _)) => format!("{} is back", name(player)),
+        Some((OnlineNotice::Rejoined, _)) => "Reconnected, and caught up with the game".to_owned(),
+        Some((OnlineNotice::OutOfTurn, _)) => "That move was played out of turn, so it didn't count".to_owned(),
+        Some((OnlineNotice::Rejected(MoveError::NotPlaying), _)) => "Only players still in the game can move".to_owned(),
+        Some((OnlineNotice::Rejected(MoveError::OutOfBounds { .. }), _)) => "The server turned down a move off the board".to_owned(),
+        Some((OnlineNotice::Resynced, _)) => "The server turned down that move, so the board was put back".to_owned(),
+        Some((OnlineNotice::DrawOffered { player }, _)) if online.draw_offers.first() == Some(&player) => format!("{} offers a draw", name(player)),
+        Some((OnlineNotice::DrawOffered { player }, _)) => format!("{} agrees to a draw", name(player)),
+        Some((OnlineNotice::DrawCalledOff { player }, _)) if Some(player) == online.me => "The draw is off".to_owned(),
+        Some((OnlineNotice::DrawCalledOff { player }, _)) => format!("{} called off the draw", name(player)),
+    };
+    for mut text in &mut notice_text {
+        if text.0 != new {
+            text.0.clone_from(&new);
+        }
+    }
+}
+
+fn move_entry(ga: &GameAssets, i: usize, text: String, color: Color) -> impl Bundle {
+    (
+        MoveEntry(i),
+        Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            padding: UiRect::axes(px(4.0), px(2.0)),
+            border_radius: BorderRadius::all(px(3.0)),
+            ..default()
+        },
+        Button,
+        BackgroundColor(Color::NONE),
+        children![
+            (
+                Node {
+                    width: px(10.0),
+                    height: px(10.0),
+                    margin: UiRect::right(px(6.0)),
+                    border_radius: BorderRadius::all(px(2.0)),
+                    ..default()
+                },
+                AnimateBackgroundColor,
+                BackgroundColor(color),
+                Pickable::IGNORE,
+            ),
+            (p(ga, text), Pickable::IGNORE),
+        ],
+        observe(
+            move |_: On<Pointer<Click>>, preview: Res<HistoryPreview>, mut requests: MessageWriter<PreviewMove>| {
+                requests.write(PreviewMove(if preview.0 == Some(i) { None } else { Some(i) }));
+            },
+        ),
+    )
+}
+
+/// Fills the move list, and highlights the move being previewed.
+pub fn run_move_list(
+    mut commands: Commands,
+    ga: Res<GameAssets>,
+    mut move_list: Query<(Entity, &mut BackgroundColor), (With<MoveList>, Without<MoveEntry>)>,
+    mut entries: Query<(&MoveEntry, &mut BackgroundColor)>,
+    mut close_preview: Query<&mut Node, With<ClosePreviewButton>>,
+    log: Res<MoveLog>,
+    preview: Res<HistoryPreview>,
+    config: Res<Config>,
+    palette: Res<PaletteSettings>,
+    ui_opacity: Res<CurrentUiOpacity>,
+) {
+    let Ok((move_list, mut background)) = move_list.single_mut() else {
+        return;
+    };
+    // Translucent, unlike the other backgrounds, so it fades by hand
+    background.0 = Color::srgba(0.0, 0.0, 0.0, 0.6 * ui_opacity.0);
+    if log.is_changed() || palette.is_changed() {
+        commands.entity(move_list).despawn_children();
+        if log.unavailable {
+            commands.spawn((p(&ga, "Not available for this game"), ChildOf(move_list)));
+        }
+        for (i, logged) in log.moves.iter().enumerate() {
+            let Some(player) = config.players.get(logged.player.wrapping_sub(1)) else {
+                continue;
+            };
+            let cascade = if logged.single.did_cascade { " cascade" } else { "" };
+            let text = format!("{:>3}. ({}, {}) +{}{cascade}", i + 1, logged.single.x + 1, logged.single.y + 1, logged.gained);
+            commands.spawn((move_entry(&ga, i, text, palette.player_color(logged.player, player)), ChildOf(move_list)));
+        }
+        // Keep the latest move in view; the layout clamps this to the end of the list
+        commands.entity(move_list).insert(ScrollPosition(Vec2::new(0.0, f32::MAX)));
+    }
+    for (entry, mut color) in &mut entries {
+        color.0 = if preview.0 == Some(entry.0) {
+            Color::srgba(0.4, 0.4, 0.4, ui_opacity.0)
+        } else {
+            Color::NONE
+        };
+    }
+    for mut node in &mut close_preview {
+        node.display = if preview.0.is_some() { Display::Flex } else { Display::None };
+    }
+}
+
+/// Scrolls the move list with the mouse wheel while the pointer is over it.
+pub fn scroll_move_list(
+    mut wheel: MessageReader<MouseWheel>,
+    mut move_list: Query<(&mut ScrollPosition, &ComputedNode, &RelativeCursorPosition), With<MoveList>>,
+) {
+    const LINE_HEIGHT: f32 = 24.0;
+    let dy = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y * LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        })
+        .sum::<f32>();
+    for (mut scroll, node, cursor) in &mut move_list {
+        if dy != 0.0 && cursor.cursor_over() {
+            // The layout clamps the scroll position it uses, but not the one stored here
+            let max = ((node.content_size.y - node.size.y) * node.inverse_scale_factor()).max(0.0);
+            scroll.y = (scroll.y.min(max) - dy).clamp(0.0, max);
+        }
+    }
+}
@@ -1,12 +1,104 @@
 use bevy::prelude::*;
 
 use crate::{
+    clock::CLOCK_PRESETS,
     menu::{MainMenuSubState, MenuState},
-    net::{GameSettings, NetManagerMessage, NetServerboundSender, ServerUrl},
+    net::{GameSettings, NetManagerMessage, NetRequest, NetServerboundSender, Rules, ServerUrl},
     ui_menu::{HostGameUiTree, InfoText},
 };
 
-use super::support::*;
+use super::{lobby, servers, support::*};
+
+/// The room the host is about to create.
+#[derive(Resource, Default)]
+pub struct HostSettings(pub GameSettings);
+
+/// Board sides the server accepts.
+const MIN_SIDE: u8 = 2;
+const MAX_SIDE: u8 = 20;
+
+/// Most players the server seats in one room.
+pub const MAX_ONLINE_PLAYERS: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HostOption {
+    Capacity,
+    Width,
+    Height,
+    Clock,
+    Rules,
+    Public,
+    Spectators,
+}
+
+/// Shows the current value of one of the host's options.
+#[derive(Component)]
+pub struct HostOptionText(HostOption);
+
+impl HostOption {
+    fn step(self, settings: &mut GameSettings, step: isize) {
+        let clamp = |x: u8, min: u8, max: u8| (x as isize + step).clamp(min as isize, max as isize) as u8;
+        match self {
+            Self::Capacity => settings.capacity = clamp(settings.capacity, 2, MAX_ONLINE_PLAYERS),
+            Self::Width => settings.width = clamp(settings.width, MIN_SIDE, MAX_SIDE),
+            Self::Height => settings.height = clamp(settings.height, MIN_SIDE, MAX_SIDE),
+            Self::Clock => {
+                let current = CLOCK_PRESETS.iter().position(|&x| x == settings.clock).unwrap_or(0);
+                settings.clock = CLOCK_PRESETS[(current as isize + step).rem_euclid(CLOCK_PRESETS.len() as isize) as usize];
+            }
+            Self::Rules => {
+                settings.rules = match settings.rules {
+                    Rules::LastStand => Rules::Classic,
+                    Rules::Classic => Rules::LastStand,
+                }
+            }
+            Self::Public => settings.public = !settings.public,
+            Self::Spectators => settings.spectators = !settings.spectators,
+        }
+    }
+
+    fn value(self, settings: &GameSettings) -> String {
+        let yes_no = |x: bool| if x { "Yes" } else { "No" }.to_string();
+        match self {
+            Self::Capacity => settings.capacity.to_string(),
+            Self::Width => settings.width.to_string(),
+            Self::Height => settings.height.to_string(),
+            Self::Clock => settings.clock.name(),
+            Self::Rules => settings.rules.name().into(),
+            Self::Public => yes_no(settings.public),
+            Self::Spectators => yes_no(settings.spectators),
+        }
+    }
+}
+
+fn option_row(ga: &GameAssets, label: &'static str, option: HostOption) -> impl Bundle {
+    (
+        Node {
+            margin: UiRect::top(Val::Px(10.0)),
+            display: Display::Flex,
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        },
+        children![
+            p(ga, label),
+            (
+                left_button(ga),
+                observe(move |_: On<Pointer<Click>>, mut settings: ResMut<HostSettings>| {
+                    option.step(&mut settings.0, -1);
+                })
+            ),
+            (p(ga, ""), HostOptionText(option)),
+            (
+                right_button(ga),
+                observe(move |_: On<Pointer<Click>>, mut settings: ResMut<HostSettings>| {
+                    option.step(&mut settings.0, 1);
+                })
+            ),
+        ],
+    )
+}
 
 pub fn menu(ga: &GameAssets) -> impl Bundle {
     (
@@ -23,7 +115,13 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
         Visibility::Hidden,
         children![
             h1(ga, "Host game"),
-            p(ga, "(customization options coming soon)"),
+            option_row(ga, "Players", HostOption::Capacity),
+            option_row(ga, "Width", HostOption::Width),
+            option_row(ga, "Height", HostOption::Height),
+            option_row(ga, "Clock", HostOption::Clock),
+            option_row(ga, "Rules", HostOption::Rules),
+            option_row(ga, "Public", HostOption::Public),
+            option_row(ga, "Spectators", HostOption::Spectators),
             (
                 Node {
                     margin: UiRect::top(Val::Px(20.0)),
@@ -36,18 +134,17 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                     button_default_bg(ga, "Create game"),
                     observe(
                         |_: On<Pointer<Click>>,
-                         tx: Res<NetServerboundSender>,
-                         server: Res<ServerUrl>,
+                         (mut request, tx, time): (ResMut<NetRequest>, Res<NetServerboundSender>, Res<Time>),
+                         (server, settings): (Res<ServerUrl>, Res<HostSettings>),
                          mut info_texts: Query<(&mut Node, &mut Text), With<InfoText>>| {
-                            tx.force_send(NetManagerMessage::HostGame {
-                                settings: GameSettings {
-                                    capacity: 2,
-                                    width: 6,
-                                    height: 6,
+                            request.send(
+                                NetManagerMessage::HostGame {
+                                    settings: settings.0,
+                                    server: server.clone(),
                                 },
-                                server: server.clone(),
-                            })
-                            .unwrap();
+                                &tx,
+                                &time,
+                            );
                             for (mut node, mut text) in &mut info_texts {
                                 node.display = Display::Flex;
                                 text.0 = "Creating...".into();
@@ -67,7 +164,26 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                 InfoText,
                 p(ga, ""),
             ),
+            lobby::list(),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![(p(ga, ""), servers::CurrentServerText), servers::open_button::<HostGameUiTree>(ga)],
+            ),
             back_to_menu::<HostGameUiTree>(ga, "Back to online menu", MenuState::Main(Some(MainMenuSubState::Online))),
         ],
     )
 }
+
+pub fn run_menu(settings: Res<HostSettings>, mut option_texts: Query<(&mut Text, &HostOptionText)>) {
+    if settings.is_changed() {
+        for (mut text, option) in &mut option_texts {
+            text.0 = option.0.value(&settings.0);
+        }
+    }
+}
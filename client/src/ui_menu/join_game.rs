@@ -1,13 +1,23 @@
 use bevy::prelude::*;
 
 use crate::{
-    GameCode, GameCodeText,
+    GameCode, GameCodeText, MainState,
+    anim::TargetUiOpacity,
     menu::{MainMenuSubState, MenuState},
-    net::{NetManagerMessage, NetServerboundSender, ServerUrl},
+    net::{ConnectionStatus, NetManagerMessage, NetRequest, NetServerboundSender, RoomServer, ServerUrl},
     ui_menu::InfoText,
 };
 
-use super::{JoinGameUiTree, support::*};
+use super::{JoinGameUiTree, JoinMode, WatchEntryUiTree, lobby, servers, support::*};
+
+#[derive(Component)]
+pub struct JoinTitleText;
+
+#[derive(Component)]
+pub struct JoinButton;
+
+#[derive(Component)]
+pub struct PublicJoinButton;
 
 fn letter_button(ga: &GameAssets, letter: char) -> impl Bundle {
     (
@@ -41,7 +51,7 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
         },
         Visibility::Hidden,
         children![
-            h1(ga, "Join game"),
+            (h1(ga, "Join game"), JoinTitleText),
             (
                 Node {
                     margin: UiRect::top(Val::Px(20.0)),
@@ -122,26 +132,55 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                     align_items: AlignItems::Center,
                     ..default()
                 },
-                children![(
-                    button_default_bg(ga, "Join game"),
-                    observe(
-                        |_: On<Pointer<Click>>,
-                         tx: Res<NetServerboundSender>,
-                         game_code: Res<GameCode>,
-                         server: Res<ServerUrl>,
-                         mut info_texts: Query<(&mut Node, &mut Text), With<InfoText>>| {
-                            tx.force_send(NetManagerMessage::JoinLobby {
-                                code: game_code.0.as_ref().unwrap().clone(),
-                                server: server.clone(),
-                            })
-                            .unwrap();
-                            for (mut node, mut text) in &mut info_texts {
-                                node.display = Display::Flex;
-                                text.0 = "Joining...".into();
+                children![
+                    (
+                        button_default_bg(ga, "Join game"),
+                        JoinButton,
+                        observe(
+                            |_: On<Pointer<Click>>,
+                             (mut request, tx, time): (ResMut<NetRequest>, Res<NetServerboundSender>, Res<Time>),
+                             (game_code, mode): (Res<GameCode>, Res<JoinMode>),
+                             (server, mut room_server, mut connection_status): (Res<ServerUrl>, ResMut<RoomServer>, ResMut<ConnectionStatus>),
+                             mut info_texts: Query<(&mut Node, &mut Text), With<InfoText>>| {
+                                let code = game_code.0.as_ref().unwrap().clone();
+                                let server = server.clone();
+                                let status = match *mode {
+                                    JoinMode::Play => {
+                                        request.send(NetManagerMessage::JoinLobby { code, server }, &tx, &time);
+                                        "Joining..."
+                                    }
+                                    JoinMode::Watch => {
+                                        // Straight into the game, without a lobby
+                                        room_server.0 = Some(server.clone());
+                                        *connection_status = ConnectionStatus::Connecting;
+                                        request.send(NetManagerMessage::WatchGame { code, server }, &tx, &time);
+                                        "Connecting..."
+                                    }
+                                };
+                                for (mut node, mut text) in &mut info_texts {
+                                    node.display = Display::Flex;
+                                    text.0 = status.into();
+                                }
                             }
-                        }
-                    )
-                )],
+                        )
+                    ),
+                    (
+                        button_default_bg(ga, "Join a public game"),
+                        PublicJoinButton,
+                        observe(
+                            |_: On<Pointer<Click>>,
+                             (mut request, tx, time): (ResMut<NetRequest>, Res<NetServerboundSender>, Res<Time>),
+                             server: Res<ServerUrl>,
+                             mut info_texts: Query<(&mut Node, &mut Text), With<InfoText>>| {
+                                request.send(NetManagerMessage::JoinPublic { server: server.clone() }, &tx, &time);
+                                for (mut node, mut text) in &mut info_texts {
+                                    node.display = Display::Flex;
+                                    text.0 = "Joining...".into();
+                                }
+                            }
+                        )
+                    ),
+                ],
             ),
             (
                 Node {
@@ -154,7 +193,103 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                 InfoText,
                 p(ga, ""),
             ),
+            lobby::list(),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![(p(ga, ""), servers::CurrentServerText), servers::open_button::<JoinGameUiTree>(ga)],
+            ),
             back_to_menu::<JoinGameUiTree>(ga, "Back to online menu", MenuState::Main(Some(MainMenuSubState::Online))),
         ],
     )
 }
+
+/// Opens the join screen to watch a game instead, from the online menu.
+pub fn watch_entry(ga: &GameAssets) -> impl Bundle {
+    (
+        WatchEntryUiTree,
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(15.0),
+            bottom: Val::Px(15.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        children![(
+            button_default_bg(ga, "Watch game"),
+            observe(
+                |_: On<Pointer<Click>>,
+                 mut next_state: ResMut<NextState<MainState>>,
+                 mut join_game_ui_tree: Query<&mut Visibility, With<JoinGameUiTree>>,
+                 mut ui_opacity: ResMut<TargetUiOpacity>,
+                 mut mode: ResMut<JoinMode>,
+                 mut info_texts: Query<&mut Node, With<InfoText>>| {
+                    next_state.set(MainState::DimForUi);
+                    *join_game_ui_tree.single_mut().unwrap() = Visibility::Visible;
+                    ui_opacity.0 = 1.0;
+                    *mode = JoinMode::Watch;
+                    for mut node in &mut info_texts {
+                        node.display = Display::None;
+                    }
+                }
+            ),
+        )],
+    )
+}
+
+pub fn run_watch_entry(
+    menu_state: Option<Res<State<MenuState>>>,
+    mut entry: Query<&mut Visibility, With<WatchEntryUiTree>>,
+    mut ui_opacity: ResMut<TargetUiOpacity>,
+    mut shown_for: Local<f32>,
+    time: Res<Time>,
+) {
+    let Ok(mut visibility) = entry.single_mut() else {
+        return;
+    };
+    if menu_state.map(|x| **x) != Some(MenuState::Main(Some(MainMenuSubState::Online))) {
+        *shown_for = 0.0;
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *shown_for += time.delta_secs();
+    // Give whatever screen we came from time to fade out first
+    if *shown_for > 0.8 && *visibility == Visibility::Hidden {
+        *visibility = Visibility::Inherited;
+        ui_opacity.0 = 1.0;
+    }
+}
+
+/// Words the join screen for playing or watching.
+pub fn run_menu(
+    mode: Res<JoinMode>,
+    mut texts: ParamSet<(Query<&mut Text, With<JoinTitleText>>, Query<&mut Text>)>,
+    join_button: Query<&Children, With<JoinButton>>,
+    mut public_button: Query<&mut Node, With<PublicJoinButton>>,
+) {
+    if !mode.is_changed() {
+        return;
+    }
+    let (title, label) = match *mode {
+        JoinMode::Play => ("Join game", "Join game"),
+        JoinMode::Watch => ("Watch game", "Watch"),
+    };
+    for mut text in &mut texts.p0() {
+        text.0 = title.into();
+    }
+    if let Ok(children) = join_button.single() {
+        let mut button_texts = texts.p1();
+        let mut button_texts = button_texts.iter_many_mut(children);
+        while let Some(mut text) = button_texts.fetch_next() {
+            text.0 = label.into();
+        }
+    }
+    for mut node in &mut public_button {
+        node.display = if *mode == JoinMode::Play { Display::Flex } else { Display::None };
+    }
+}
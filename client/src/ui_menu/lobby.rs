@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+use crate::net::{NetRequest, RoomLobby};
+
+use super::support::*;
+
+/// Lists who's in the room while it fills up. Its children are rebuilt when someone joins or leaves.
+#[derive(Component)]
+pub struct LobbyList;
+
+pub fn list() -> impl Bundle {
+    (
+        LobbyList,
+        Node {
+            display: Display::None,
+            margin: UiRect::top(Val::Px(10.0)),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::FlexStart,
+            ..default()
+        },
+    )
+}
+
+pub fn run_list(
+    mut commands: Commands,
+    ga: Res<GameAssets>,
+    room_lobby: Res<RoomLobby>,
+    request: Res<NetRequest>,
+    mut lists: Query<(Entity, &mut Node), With<LobbyList>>,
+) {
+    if !room_lobby.is_changed() && !request.is_changed() {
+        return;
+    }
+    let shown = room_lobby.capacity > 0 && request.in_lobby();
+    for (list, mut node) in &mut lists {
+        node.display = if shown { Display::Flex } else { Display::None };
+        if !shown {
+            continue;
+        }
+        commands.entity(list).despawn_children();
+        let RoomLobby { joined, capacity } = *room_lobby;
+        commands.spawn((p(&ga, format!("{joined} of {capacity} players here")), ChildOf(list)));
+        for slot in 1..=capacity {
+            let line = match slot {
+                1 => "[x] Host".to_owned(),
+                _ if slot <= joined => format!("[x] Player {slot}"),
+                _ => format!("[ ] Waiting for player {slot}..."),
+            };
+            commands.spawn((p(&ga, line), ChildOf(list)));
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use bevy_defer::{AsyncCommandsExtension as _, AsyncWorld, fetch};
+
+use crate::{
+    Config, MainState, NeedNewBoard,
+    menu::{MainMenuSubState, MenuRadios, MenuState},
+    replay::{MAX_REPLAYS, REPLAY_SPEEDS, ReplayViewer, Replays, start_replay},
+};
+
+use super::{ReplayListUiTree, ReplayUiTree, ReplaysEntryUiTree, game_hud, support::*};
+
+#[derive(Component)]
+pub struct ReplaySlot(usize);
+
+#[derive(Component)]
+pub struct NoReplaysText;
+
+#[derive(Component)]
+pub struct ReplayPlayButton;
+
+#[derive(Component)]
+pub struct ReplayProgressText;
+
+#[derive(Component)]
+pub struct ReplaySpeedText;
+
+#[derive(Component)]
+pub struct ReplayTimelineFill;
+
+/// The "Replays" button shown on the main menu.
+pub fn entry(ga: &GameAssets) -> impl Bundle {
+    (
+        ReplaysEntryUiTree,
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(15.0),
+            bottom: Val::Px(15.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        children![(
+            button_default_bg(ga, "Replays"),
+            observe(
+                |_: On<Pointer<Click>>,
+                 mut next_state: ResMut<NextState<MainState>>,
+                 mut replay_list_ui_tree: Query<&mut Visibility, With<ReplayListUiTree>>,
+                 mut ui_opacity: ResMut<TargetUiOpacity>| {
+                    next_state.set(MainState::DimForUi);
+                    *replay_list_ui_tree.single_mut().unwrap() = Visibility::Visible;
+                    ui_opacity.0 = 1.0;
+                }
+            ),
+        )],
+    )
+}
+
+pub fn list(ga: &GameAssets) -> impl Bundle {
+    (
+        ReplayListUiTree,
+        Node {
+            display: Display::Flex,
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        Children::spawn((
+            Spawn(h1(ga, "Replays")),
+            Spawn((
+                Node {
+                    margin: UiRect::top(Val::Px(10.0)),
+                    ..default()
+                },
+                p(ga, "No games recorded yet. Finished games show up here."),
+                NoReplaysText,
+            )),
+            SpawnIter((0..MAX_REPLAYS).map(|i| replay_slot(ga, i)).collect::<Vec<_>>().into_iter()),
+            Spawn(back_to_main_menu::<ReplayListUiTree>(ga)),
+        )),
+    )
+}
+
+fn replay_slot(ga: &GameAssets, i: usize) -> impl Bundle {
+    (
+        Node {
+            margin: UiRect::top(Val::Px(5.0)),
+            ..default()
+        },
+        ReplaySlot(i),
+        children![(
+            button_default_bg(ga, ""),
+            observe(
+                move |_: On<Pointer<Click>>,
+                      mut commands: Commands,
+                      replays: Res<Replays>,
+                      mut config: ResMut<Config>,
+                      mut radios: ResMut<MenuRadios>,
+                      mut next_state: ResMut<NextState<MainState>>,
+                      (need_new_board, mut new_board): (Res<State<NeedNewBoard>>, ResMut<NextState<NeedNewBoard>>),
+                      mut ui_opacity: ResMut<TargetUiOpacity>,
+                      ui_tree: Query<Entity, With<ReplayListUiTree>>| {
+                    let Some(game) = replays.get(i) else {
+                        return;
+                    };
+                    start_replay(
+                        &mut commands,
+                        game,
+                        !need_new_board.0,
+                        &mut config,
+                        &mut radios,
+                        &mut next_state,
+                        &mut new_board,
+                    );
+                    fade_out_ui(&mut commands, &mut ui_opacity, &ui_tree);
+                }
+            ),
+        )],
+    )
+}
+
+/// The playback controls shown while watching a replay.
+pub fn controls(ga: &GameAssets) -> impl Bundle {
+    (
+        ReplayUiTree,
+        Node {
+            display: Display::Flex,
+            width: percent(100),
+            height: percent(100),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        Pickable::IGNORE,
+        Visibility::Hidden,
+        children![
+            (
+                Node {
+                    align_self: AlignSelf::FlexEnd,
+                    padding: UiRect::all(px(15.0)),
+                    ..default()
+                },
+                children![
+                    game_hud::reset_view_button(ga),
+                    (
+                        button_default_bg(ga, "Exit replay"),
+                        observe(
+                            |_: On<Pointer<Click>>, mut next_state: ResMut<NextState<MainState>>, mut next_menu_state: ResMut<NextState<MenuState>>| {
+                                next_state.set(MainState::Menu);
+                                next_menu_state.set(MenuState::Main(Some(MainMenuSubState::Main)));
+                            }
+                        ),
+                    ),
+                ],
+            ),
+            (Node { flex_grow: 1.0, ..default() }, Pickable::IGNORE),
+            (
+                Node {
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(px(15.0)),
+                    ..default()
+                },
+                Pickable::IGNORE,
+                children![
+                    (p(ga, ""), ReplayProgressText, Pickable::IGNORE),
+                    (
+                        Node {
+                            width: percent(60),
+                            height: px(10.0),
+                            margin: UiRect::vertical(px(10.0)),
+                            border_radius: BorderRadius::all(px(5.0)),
+                            ..default()
+                        },
+                        AnimateBackgroundColor,
+                        BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+                        children![(
+                            Node {
+                                width: percent(0),
+                                height: percent(100),
+                                border_radius: BorderRadius::all(px(5.0)),
+                                ..default()
+                            },
+                            AnimateBackgroundColor,
+                            BackgroundColor(Color::WHITE),
+                            ReplayTimelineFill,
+                            Pickable::IGNORE,
+                        )],
+                        observe(|trigger: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                            let (Some(viewer), Some(position)) = (viewer.as_mut(), trigger.hit.position) else {
+                                return;
+                            };
+                            // Hit positions run from -0.5 to 0.5 across the bar
+                            let moves = ((position.x + 0.5) * viewer.len() as f32).round() as usize;
+                            viewer.jump_to(moves);
+                        }),
+                    ),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        Pickable::IGNORE,
+                        children![
+                            (
+                                button_default_bg(ga, "|<"),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        viewer.playing = false;
+                                        viewer.jump_to(0);
+                                    }
+                                }),
+                            ),
+                            (
+                                button_default_bg(ga, "<"),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        viewer.playing = false;
+                                        let target = viewer.shown.saturating_sub(1);
+                                        viewer.jump_to(target);
+                                    }
+                                }),
+                            ),
+                            (
+                                button_default_bg(ga, "Pause"),
+                                ReplayPlayButton,
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        viewer.toggle_playing();
+                                    }
+                                }),
+                            ),
+                            (
+                                button_default_bg(ga, ">"),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        viewer.step_forward();
+                                    }
+                                }),
+                            ),
+                            (
+                                button_default_bg(ga, ">|"),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        viewer.playing = false;
+                                        let target = viewer.len();
+                                        viewer.jump_to(target);
+                                    }
+                                }),
+                            ),
+                            (Node { width: px(20.0), ..default() }, Pickable::IGNORE),
+                            (
+                                left_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        let speed = viewer.speed.saturating_sub(1);
+                                        viewer.set_speed(speed);
+                                    }
+                                }),
+                            ),
+                            (p(ga, "1x"), ReplaySpeedText, Pickable::IGNORE),
+                            (
+                                right_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut viewer: Option<ResMut<ReplayViewer>>| {
+                                    if let Some(viewer) = viewer.as_mut() {
+                                        let speed = viewer.speed + 1;
+                                        viewer.set_speed(speed);
+                                    }
+                                }),
+                            ),
+                        ],
+                    ),
+                ],
+            ),
+        ],
+    )
+}
+
+pub fn run_entry(
+    menu_state: Option<Res<State<MenuState>>>,
+    mut entry: Query<&mut Visibility, With<ReplaysEntryUiTree>>,
+    mut ui_opacity: ResMut<TargetUiOpacity>,
+    replays: Res<Replays>,
+    mut shown_for: Local<f32>,
+    time: Res<Time>,
+) {
+    let Ok(mut visibility) = entry.single_mut() else {
+        return;
+    };
+    if menu_state.map(|x| **x) != Some(MenuState::Main(Some(MainMenuSubState::Main))) || replays.is_empty() {
+        *shown_for = 0.0;
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *shown_for += time.delta_secs();
+    // Give whatever screen we came from time to fade out first
+    if *shown_for > 0.8 && *visibility == Visibility::Hidden {
+        *visibility = Visibility::Inherited;
+        ui_opacity.0 = 1.0;
+    }
+}
+
+pub fn run_list(
+    replays: Res<Replays>,
+    mut slots: Query<(&mut Node, &ReplaySlot, &Children)>,
+    buttons: Query<&Children>,
+    mut texts: Query<&mut Text, Without<NoReplaysText>>,
+    mut no_replays: Query<&mut Node, (With<NoReplaysText>, Without<ReplaySlot>)>,
+) {
+    if !replays.is_changed() {
+        return;
+    }
+    if let Ok(mut node) = no_replays.single_mut() {
+        node.display = if replays.is_empty() { Display::Flex } else { Display::None };
+    }
+    for (mut node, &ReplaySlot(i), children) in &mut slots {
+        let Some(game) = replays.get(i) else {
+            node.display = Display::None;
+            continue;
+        };
+        node.display = Display::Flex;
+        let names = game.players.iter().map(|x| x.name.as_str()).collect::<Vec<_>>().join(" vs ");
+        let online = if game.online { ", online" } else { "" };
+        let label = format!("{names} ({}x{}, {} moves{online})", game.width, game.height, game.moves.len());
+        for button in children {
+            let mut button_texts = texts.iter_many_mut(buttons.iter_descendants(*button));
+            while let Some(mut text) = button_texts.fetch_next() {
+                text.0.clone_from(&label);
+            }
+        }
+    }
+}
+
+pub fn run_controls(
+    viewer: Option<Res<ReplayViewer>>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<ReplayProgressText>>,
+        Query<&mut Text, With<ReplaySpeedText>>,
+        Query<&mut Text>,
+    )>,
+    play_button: Query<&Children, With<ReplayPlayButton>>,
+    mut fill: Query<&mut Node, With<ReplayTimelineFill>>,
+) {
+    let Some(viewer) = viewer else {
+        return;
+    };
+    if let Ok(mut text) = texts.p0().single_mut() {
+        text.0 = format!("Move {} / {}", viewer.shown, viewer.len());
+    }
+    if let Ok(mut text) = texts.p1().single_mut() {
+        text.0 = format!("{}x", REPLAY_SPEEDS[viewer.speed]);
+    }
+    if let Ok(children) = play_button.single() {
+        let label = if viewer.playing { "Pause" } else { "Play" };
+        let mut button_texts = texts.p2();
+        let mut button_texts = button_texts.iter_many_mut(children);
+        while let Some(mut text) = button_texts.fetch_next() {
+            if text.0 != label {
+                text.0 = label.into();
+            }
+        }
+    }
+    if let Ok(mut node) = fill.single_mut() {
+        let progress = if viewer.is_empty() { 1.0 } else { viewer.shown as f32 / viewer.len() as f32 };
+        node.width = percent(progress * 100.0);
+    }
+}
+
+pub fn show_controls(mut commands: Commands, controls: Query<Entity, With<ReplayUiTree>>) {
+    let controls = controls.single().unwrap();
+    commands.spawn_task(move || async move {
+        AsyncWorld.sleep(0.75).await;
+        fetch!(controls, Visibility).get_mut(|x| *x = Visibility::Inherited)?;
+        fetch!(TargetUiOpacity).get_mut(|ui_opacity| ui_opacity.0 = 1.0)?;
+        Ok(())
+    });
+}
+
+pub fn hide_controls(mut commands: Commands, controls: Query<Entity, With<ReplayUiTree>>) {
+    let controls = controls.single().unwrap();
+    commands.spawn_task(move || async move {
+        AsyncWorld.sleep(0.75).await;
+        fetch!(controls, Visibility).get_mut(|x| *x = Visibility::Hidden)?;
+        Ok(())
+    });
+}
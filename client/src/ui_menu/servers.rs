@@ -0,0 +1,466 @@
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
+
+use crate::net::{NetManagerMessage, NetServerboundSender, RoomServer, ServerList, ServerStatus, ServerUrl, parse_server_url, pick_server};
+
+use super::{ServerField, ServerForm, ServersUiTree, support::*};
+
+const MAX_SERVER_NAME_LEN: usize = 24;
+const MAX_SERVER_URL_LEN: usize = 128;
+
+/// Holds one row per server, rebuilt whenever the list changes.
+#[derive(Component)]
+pub struct ServerRows;
+
+#[derive(Component)]
+pub struct ServerFieldButton(ServerField);
+
+#[derive(Component)]
+pub struct ServerSubmitButton;
+
+#[derive(Component)]
+pub struct ServerCancelButton;
+
+#[derive(Component)]
+pub struct ServerFormError;
+
+/// The server in use, shown on the screens that connect to it.
+#[derive(Component)]
+pub struct CurrentServerText;
+
+/// The screen to go back to when the server list is closed.
+#[derive(Default, Resource)]
+pub struct ServersReturnTo(Option<Entity>);
+
+/// A button that opens the server list on top of the screen `T`.
+pub fn open_button<T: Component>(ga: &GameAssets) -> impl Bundle {
+    (
+        button_default_bg(ga, "Change server"),
+        observe(
+            |_: On<Pointer<Click>>,
+             mut return_to: ResMut<ServersReturnTo>,
+             mut trees: ParamSet<(Query<(Entity, &mut Visibility), With<T>>, Query<&mut Visibility, With<ServersUiTree>>)>| {
+                if let Ok((tree, mut visibility)) = trees.p0().single_mut() {
+                    return_to.0 = Some(tree);
+                    *visibility = Visibility::Hidden;
+                }
+                if let Ok(mut visibility) = trees.p1().single_mut() {
+                    *visibility = Visibility::Visible;
+                }
+            },
+        ),
+    )
+}
+
+fn field_button(ga: &GameAssets, field: ServerField) -> impl Bundle {
+    (
+        ServerFieldButton(field),
+        button_default_bg(ga, ""),
+        observe(move |_: On<Pointer<Click>>, mut form: ResMut<ServerForm>| {
+            form.focus = if form.focus == Some(field) { None } else { Some(field) };
+        }),
+    )
+}
+
+pub fn menu(ga: &GameAssets) -> impl Bundle {
+    (
+        ServersUiTree,
+        Node {
+            display: Display::Flex,
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        children![
+            h1(ga, "Servers"),
+            (
+                ServerRows,
+                Node {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Stretch,
+                    ..default()
+                },
+            ),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(10.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    (
+                        button_default_bg(ga, "Refresh"),
+                        observe(|_: On<Pointer<Click>>, mut servers: ResMut<ServerList>, tx: Res<NetServerboundSender>| {
+                            servers.ping_all(&tx);
+                        }),
+                    ),
+                    (
+                        button_default_bg(ga, "Pick automatically"),
+                        observe(|_: On<Pointer<Click>>, mut server_url: ResMut<ServerUrl>, servers: Res<ServerList>| {
+                            server_url.chosen = false;
+                            pick_server(&mut server_url, &servers);
+                        }),
+                    ),
+                ],
+            ),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Add a server"),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        children![
+                            p(ga, "Name"),
+                            field_button(ga, ServerField::Name),
+                            p(ga, "Address"),
+                            field_button(ga, ServerField::Url),
+                        ],
+                    ),
+                    (
+                        Node {
+                            margin: UiRect::top(Val::Px(10.0)),
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        children![
+                            (
+                                ServerSubmitButton,
+                                button_default_bg(ga, "Add"),
+                                observe(
+                                    |_: On<Pointer<Click>>,
+                                     mut form: ResMut<ServerForm>,
+                                     mut servers: ResMut<ServerList>,
+                                     mut server_url: ResMut<ServerUrl>,
+                                     tx: Res<NetServerboundSender>| {
+                                        submit_form(&mut form, &mut servers, &mut server_url, &tx);
+                                    }
+                                ),
+                            ),
+                            (
+                                ServerCancelButton,
+                                button_default_bg(ga, "Cancel"),
+                                observe(|_: On<Pointer<Click>>, mut form: ResMut<ServerForm>| {
+                                    *form = ServerForm::default();
+                                }),
+                            ),
+                        ],
+                    ),
+                    (ServerFormError, p(ga, "")),
+                ],
+            ),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    border_radius: BorderRadius::all(Val::Px(5.0)),
+                    ..default()
+                },
+                Button,
+                p(ga, "Back"),
+                Outline::new(Val::Px(5.0), Val::Px(5.0), Color::WHITE),
+                observe(
+                    |_: On<Pointer<Click>>,
+                     return_to: Res<ServersReturnTo>,
+                     mut form: ResMut<ServerForm>,
+                     mut servers_tree: Query<&mut Visibility, With<ServersUiTree>>,
+                     mut visibilities: Query<&mut Visibility, Without<ServersUiTree>>| {
+                        form.focus = None;
+                        if let Ok(mut visibility) = servers_tree.single_mut() {
+                            *visibility = Visibility::Hidden;
+                        }
+                        if let Some(mut visibility) = return_to.0.and_then(|tree| visibilities.get_mut(tree).ok()) {
+                            *visibility = Visibility::Visible;
+                        }
+                    }
+                ),
+            ),
+        ],
+    )
+}
+
+/// Adds the server in the form, or saves the changes to the one being edited.
+fn submit_form(form: &mut ServerForm, servers: &mut ServerList, server_url: &mut ServerUrl, tx: &NetServerboundSender) {
+    let url = match parse_server_url(&form.url) {
+        Ok(url) => url,
+        Err(e) => {
+            form.error = Some(e);
+            return;
+        }
+    };
+    if servers.get(&url).is_some() && form.editing.as_deref() != Some(url.as_str()) {
+        form.error = Some("That server is already listed");
+        return;
+    }
+    let name = match form.name.trim() {
+        "" => url.split_once("://").map_or(&*url, |(_, host)| host).to_owned(),
+        name => name.to_owned(),
+    };
+    match form.editing.as_deref().and_then(|old| servers.0.iter_mut().find(|x| x.url == old)) {
+        Some(server) => {
+            if server_url.url == server.url {
+                (server_url.name, server_url.url) = (name.clone(), url.clone());
+            }
+            server.name = name;
+            server.url.clone_from(&url);
+            server.status = ServerStatus::Pinging;
+        }
+        None => {
+            servers.add_custom(name, url.clone());
+            if let Some(server) = servers.0.last_mut() {
+                server.status = ServerStatus::Pinging;
+            }
+        }
+    }
+    tx.force_send(NetManagerMessage::Ping { url }).unwrap();
+    *form = ServerForm::default();
+}
+
+fn status_text(status: ServerStatus) -> String {
+    match status {
+        ServerStatus::Unknown => "?".into(),
+        ServerStatus::Pinging => "...".into(),
+        ServerStatus::Online { latency } => format!("{} ms", latency.as_millis()),
+        ServerStatus::Offline => "offline".into(),
+    }
+}
+
+fn server_row(ga: &GameAssets, name: &str, url: String, status: ServerStatus, marker: &str) -> impl Bundle {
+    let select_url = url.clone();
+    (
+        Node {
+            margin: UiRect::vertical(Val::Px(3.0)),
+            display: Display::Flex,
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        },
+        children![
+            (
+                button_default_bg(ga, format!("{name:<16}")),
+                observe(move |_: On<Pointer<Click>>, mut server_url: ResMut<ServerUrl>, servers: Res<ServerList>| {
+                    let Some(server) = servers.get(&select_url) else {
+                        return;
+                    };
+                    *server_url = ServerUrl {
+                        name: server.name.clone(),
+                        url: server.url.clone(),
+                        chosen: true,
+                    };
+                }),
+            ),
+            p(ga, format!("{url:<32}")),
+            p(ga, format!("{:>8}", status_text(status))),
+            p(ga, format!("{marker:<15}")),
+        ],
+    )
+}
+
+fn edit_button(ga: &GameAssets, url: String) -> impl Bundle {
+    (
+        button_default_bg(ga, "Edit"),
+        observe(move |_: On<Pointer<Click>>, mut form: ResMut<ServerForm>, servers: Res<ServerList>| {
+            let Some(server) = servers.get(&url) else {
+                return;
+            };
+            *form = ServerForm {
+                name: server.name.clone(),
+                url: server.url.clone(),
+                editing: Some(server.url.clone()),
+                ..default()
+            };
+        }),
+    )
+}
+
+fn remove_button(ga: &GameAssets, url: String) -> impl Bundle {
+    (
+        button_default_bg(ga, "Remove"),
+        observe(
+            move |_: On<Pointer<Click>>, mut servers: ResMut<ServerList>, mut server_url: ResMut<ServerUrl>, mut form: ResMut<ServerForm>| {
+                servers.0.retain(|x| !x.custom || x.url != url);
+                if form.editing.as_deref() == Some(url.as_str()) {
+                    *form = ServerForm::default();
+                }
+                if server_url.url == url {
+                    server_url.chosen = false;
+                    pick_server(&mut server_url, &servers);
+                }
+            },
+        ),
+    )
+}
+
+/// Lists the servers with how they're doing, and keeps the form up to date.
+pub fn run_menu(
+    mut commands: Commands,
+    ga: Res<GameAssets>,
+    rows: Query<Entity, With<ServerRows>>,
+    (servers, server_url, room_server, form): (Res<ServerList>, Res<ServerUrl>, Res<RoomServer>, Res<ServerForm>),
+    mut texts: ParamSet<(
+        Query<&mut Text, With<ServerFormError>>,
+        Query<&mut Text, With<CurrentServerText>>,
+        Query<&mut Text>,
+    )>,
+    (field_buttons, submit_button): (Query<(&ServerFieldButton, &Children)>, Query<&Children, With<ServerSubmitButton>>),
+    mut cancel_button: Query<&mut Node, With<ServerCancelButton>>,
+) {
+    if servers.is_changed() || server_url.is_changed() || room_server.is_changed() {
+        for rows in &rows {
+            commands.entity(rows).despawn_children();
+            for server in &servers.0 {
+                let selected = server.url == server_url.url;
+                let in_room = room_server.0.as_ref().is_some_and(|x| x.url == server.url);
+                let marker = match (selected, in_room) {
+                    (_, true) => "current game",
+                    (true, _) if server_url.chosen => "selected",
+                    (true, _) => "selected (auto)",
+                    _ => "",
+                };
+                let mut row = commands.spawn((server_row(&ga, &server.name, server.url.clone(), server.status, marker), ChildOf(rows)));
+                // Only the ones the player added can be changed
+                if server.custom {
+                    row.with_children(|row| {
+                        row.spawn(edit_button(&ga, server.url.clone()));
+                        row.spawn(remove_button(&ga, server.url.clone()));
+                    });
+                }
+            }
+        }
+        let current = match servers.get(&server_url.url) {
+            _ if server_url.url.is_empty() => "Server: looking for one...".to_owned(),
+            Some(server) => format!("Server: {} ({})", server.name, status_text(server.status)),
+            None => format!("Server: {}", server_url.name),
+        };
+        for mut text in &mut texts.p1() {
+            text.0.clone_from(&current);
+        }
+    }
+
+    if !form.is_changed() {
+        return;
+    }
+    for mut text in &mut texts.p0() {
+        text.0 = form.error.unwrap_or_default().into();
+    }
+    let cursor = |field| if form.focus == Some(field) { "_" } else { "" };
+    let labels = field_buttons
+        .iter()
+        .map(|(button, children)| {
+            let (value, width) = match button.0 {
+                ServerField::Name => (&form.name, 16),
+                ServerField::Url => (&form.url, 32),
+            };
+            (children, format!("{:<width$}", format!("{value}{}", cursor(button.0))))
+        })
+        .chain(
+            submit_button
+                .iter()
+                .map(|children| (children, if form.editing.is_some() { "Save" } else { "Add" }.to_owned())),
+        )
+        .collect::<Vec<_>>();
+    for (children, label) in labels {
+        let mut button_texts = texts.p2();
+        let mut button_texts = button_texts.iter_many_mut(children);
+        while let Some(mut text) = button_texts.fetch_next() {
+            text.0.clone_from(&label);
+        }
+    }
+    for mut node in &mut cancel_button {
+        node.display = if form.editing.is_some() { Display::Flex } else { Display::None };
+    }
+}
+
+/// Types into the focused field of the server form.
+pub fn edit_server_form(
+    mut form: ResMut<ServerForm>,
+    mut keys: MessageReader<KeyboardInput>,
+    servers_ui_tree: Query<&Visibility, With<ServersUiTree>>,
+    mut servers: ResMut<ServerList>,
+    mut server_url: ResMut<ServerUrl>,
+    tx: Res<NetServerboundSender>,
+    mut last_focus: Local<Option<ServerField>>,
+) {
+    let focus_changed = form.focus != *last_focus;
+    *last_focus = form.focus;
+    let Some(field) = form.focus else {
+        keys.clear();
+        return;
+    };
+    if servers_ui_tree.single().is_ok_and(|visibility| *visibility == Visibility::Hidden) {
+        form.focus = None;
+        return;
+    }
+    // Whatever focused the field (like Enter on a focused button) shouldn't also be typed
+    if focus_changed {
+        keys.clear();
+        return;
+    }
+
+    let max_len = match field {
+        ServerField::Name => MAX_SERVER_NAME_LEN,
+        ServerField::Url => MAX_SERVER_URL_LEN,
+    };
+    for key in keys.read().filter(|key| key.state == ButtonState::Pressed) {
+        let value = match field {
+            ServerField::Name => &mut form.name,
+            ServerField::Url => &mut form.url,
+        };
+        match &key.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if value.chars().count() < max_len {
+                        value.push(c);
+                    }
+                }
+            }
+            // Addresses can't have spaces in them
+            Key::Space if field == ServerField::Name && value.chars().count() < max_len => value.push(' '),
+            Key::Backspace => {
+                value.pop();
+            }
+            Key::Tab => {
+                form.focus = Some(match field {
+                    ServerField::Name => ServerField::Url,
+                    ServerField::Url => ServerField::Name,
+                });
+                return;
+            }
+            Key::Enter => {
+                submit_form(&mut form, &mut servers, &mut server_url, &tx);
+                return;
+            }
+            Key::Escape => {
+                form.focus = None;
+                return;
+            }
+            _ => continue,
+        }
+        form.error = None;
+    }
+}
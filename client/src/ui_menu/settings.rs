@@ -1,12 +1,91 @@
 use bevy::prelude::*;
 
-use crate::FlashIntensity;
+use crate::{
+    FlashIntensity,
+    anim::{ANIMATION_SPEEDS, AnimationSettings},
+    audio::AudioSettings,
+    clock::LocalClock,
+    move_preview::AssistSettings,
+    palette::{Palette, PaletteSettings},
+};
 
 use super::{SettingsUiTree, support::*};
 
+#[derive(Component)]
+pub struct FlashIntensityText;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VolumeChannel {
+    Master,
+    Sfx,
+    Music,
+}
+
+impl VolumeChannel {
+    fn volume(self, settings: &AudioSettings) -> f32 {
+        match self {
+            Self::Master => settings.master,
+            Self::Sfx => settings.sfx,
+            Self::Music => settings.music,
+        }
+    }
+
+    fn volume_mut(self, settings: &mut AudioSettings) -> &mut f32 {
+        match self {
+            Self::Master => &mut settings.master,
+            Self::Sfx => &mut settings.sfx,
+            Self::Music => &mut settings.music,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct VolumeText(VolumeChannel);
+
+#[derive(Component)]
+pub struct MuteButton;
+
+#[derive(Component)]
+pub struct MusicButton;
+
+#[derive(Component)]
+pub struct PaletteText;
+
+#[derive(Component)]
+pub struct DotShapesButton;
+
+#[derive(Component)]
+pub struct MovePreviewButton;
+
+#[derive(Component)]
+pub struct ThreatsButton;
+
+#[derive(Component)]
+pub struct AnimationSpeedText;
+
+#[derive(Component)]
+pub struct InstantButton;
+
+#[derive(Component)]
+pub struct FastForwardButton;
+
+#[derive(Component)]
+pub struct LocalClockText;
+
+fn cycle_palette(palette: &mut PaletteSettings, step: isize) {
+    let current = Palette::ALL.iter().position(|x| *x == palette.palette).unwrap_or(0);
+    palette.palette = Palette::ALL[(current as isize + step).rem_euclid(Palette::ALL.len() as isize) as usize];
+}
+
+fn step_animation_speed(animation: &mut AnimationSettings, step: isize) {
+    let current = ANIMATION_SPEEDS
+        .iter()
+        .position(|x| *x >= animation.speed)
+        .unwrap_or(ANIMATION_SPEEDS.len() - 1);
+    animation.speed = ANIMATION_SPEEDS[(current as isize + step).clamp(0, ANIMATION_SPEEDS.len() as isize - 1) as usize];
+}
+
 pub fn menu(ga: &GameAssets) -> impl Bundle {
-    #[derive(Component)]
-    struct FlashIntensityText;
     (
         SettingsUiTree,
         Node {
@@ -39,39 +118,346 @@ pub fn menu(ga: &GameAssets) -> impl Bundle {
                         children![
                             (
                                 left_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>,
-                                     mut flash_intensity: ResMut<FlashIntensity>,
-                                     mut flash_intensity_text: Query<&mut Text, With<FlashIntensityText>>| {
-                                        flash_intensity.0 -= 0.1;
-                                        if flash_intensity.0 < 0.0 {
-                                            flash_intensity.0 = 0.0;
-                                        }
-                                        flash_intensity_text.single_mut().unwrap().0 = format!("{:#1.1}", flash_intensity.0);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut flash_intensity: ResMut<FlashIntensity>| {
+                                    flash_intensity.0 -= 0.1;
+                                    if flash_intensity.0 < 0.0 {
+                                        flash_intensity.0 = 0.0;
+                                    }
+                                })
                             ),
                             (p(ga, "0.3"), FlashIntensityText),
                             (
                                 right_button(ga),
-                                observe(
-                                    |_: On<Pointer<Click>>,
-                                     mut flash_intensity: ResMut<FlashIntensity>,
-                                     mut flash_intensity_text: Query<&mut Text, With<FlashIntensityText>>| {
-                                        flash_intensity.0 += 0.1;
-                                        if flash_intensity.0 > 1.0 {
-                                            flash_intensity.0 = 1.0;
-                                        }
-                                        flash_intensity_text.single_mut().unwrap().0 = format!("{:#1.1}", flash_intensity.0);
-                                    },
-                                )
+                                observe(|_: On<Pointer<Click>>, mut flash_intensity: ResMut<FlashIntensity>| {
+                                    flash_intensity.0 += 0.1;
+                                    if flash_intensity.0 > 1.0 {
+                                        flash_intensity.0 = 1.0;
+                                    }
+                                })
                             )
                         ]
                     )
                 ]
             ),
+            (
+                Node {
+                    margin: UiRect::vertical(Val::Px(15.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Audio"),
+                    volume_row(ga, "Master: ", VolumeChannel::Master),
+                    volume_row(ga, "Effects:", VolumeChannel::Sfx),
+                    volume_row(ga, "Music:  ", VolumeChannel::Music),
+                    (
+                        Node {
+                            margin: UiRect::top(Val::Px(10.0)),
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        children![
+                            (
+                                MuteButton,
+                                button_default_bg(ga, "Mute"),
+                                observe(|_: On<Pointer<Click>>, mut audio: ResMut<AudioSettings>| {
+                                    audio.muted = !audio.muted;
+                                })
+                            ),
+                            (
+                                MusicButton,
+                                button_default_bg(ga, "Music: off"),
+                                observe(|_: On<Pointer<Click>>, mut audio: ResMut<AudioSettings>| {
+                                    audio.music_enabled = !audio.music_enabled;
+                                })
+                            ),
+                        ]
+                    )
+                ]
+            ),
+            (
+                Node {
+                    margin: UiRect::vertical(Val::Px(15.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Player colours"),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        children![
+                            (
+                                left_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut palette: ResMut<PaletteSettings>| {
+                                    cycle_palette(&mut palette, -1);
+                                })
+                            ),
+                            (p(ga, ""), PaletteText),
+                            (
+                                right_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut palette: ResMut<PaletteSettings>| {
+                                    cycle_palette(&mut palette, 1);
+                                })
+                            ),
+                            (
+                                DotShapesButton,
+                                button_default_bg(ga, "Dot shapes: off"),
+                                observe(|_: On<Pointer<Click>>, mut palette: ResMut<PaletteSettings>| {
+                                    palette.dot_shapes = !palette.dot_shapes;
+                                })
+                            ),
+                        ]
+                    )
+                ]
+            ),
+            (
+                Node {
+                    margin: UiRect::vertical(Val::Px(15.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Hints"),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        children![
+                            (
+                                MovePreviewButton,
+                                button_default_bg(ga, "Move preview: on"),
+                                observe(|_: On<Pointer<Click>>, mut assist: ResMut<AssistSettings>| {
+                                    assist.move_preview = !assist.move_preview;
+                                })
+                            ),
+                            (
+                                ThreatsButton,
+                                button_default_bg(ga, "Threats: off"),
+                                observe(|_: On<Pointer<Click>>, mut assist: ResMut<AssistSettings>| {
+                                    assist.threats = !assist.threats;
+                                })
+                            ),
+                        ]
+                    ),
+                ]
+            ),
+            (
+                Node {
+                    margin: UiRect::vertical(Val::Px(15.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Animation"),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        children![
+                            (
+                                left_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut animation: ResMut<AnimationSettings>| {
+                                    step_animation_speed(&mut animation, -1);
+                                })
+                            ),
+                            (p(ga, ""), AnimationSpeedText),
+                            (
+                                right_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut animation: ResMut<AnimationSettings>| {
+                                    step_animation_speed(&mut animation, 1);
+                                })
+                            ),
+                            (
+                                InstantButton,
+                                button_default_bg(ga, "Instant: off"),
+                                observe(|_: On<Pointer<Click>>, mut animation: ResMut<AnimationSettings>| {
+                                    animation.instant = !animation.instant;
+                                })
+                            ),
+                            (
+                                FastForwardButton,
+                                button_default_bg(ga, "Fast-forward bots: on"),
+                                observe(|_: On<Pointer<Click>>, mut animation: ResMut<AnimationSettings>| {
+                                    animation.fast_forward = !animation.fast_forward;
+                                })
+                            ),
+                        ]
+                    ),
+                ]
+            ),
+            (
+                Node {
+                    margin: UiRect::vertical(Val::Px(15.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                children![
+                    h2(ga, "Clock"),
+                    p(ga, "For games on this device. Online rooms have their clock picked by the host."),
+                    (
+                        Node {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        children![
+                            (
+                                left_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut clock: ResMut<LocalClock>| {
+                                    clock.step(-1);
+                                })
+                            ),
+                            (p(ga, ""), LocalClockText),
+                            (
+                                right_button(ga),
+                                observe(|_: On<Pointer<Click>>, mut clock: ResMut<LocalClock>| {
+                                    clock.step(1);
+                                })
+                            ),
+                        ]
+                    ),
+                ]
+            ),
             p(ga, "Looking for the game setup options? They're now in the new Start Game menu!"),
             back_to_main_menu::<SettingsUiTree>(ga)
         ],
     )
 }
+
+fn volume_row(ga: &GameAssets, label: &str, channel: VolumeChannel) -> impl Bundle {
+    (
+        Node {
+            margin: UiRect::vertical(Val::Px(5.0)),
+            display: Display::Flex,
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        children![
+            p(ga, label),
+            (
+                left_button(ga),
+                observe(move |_: On<Pointer<Click>>, mut audio: ResMut<AudioSettings>| {
+                    let volume = channel.volume_mut(&mut audio);
+                    *volume = (*volume - 0.1).max(0.0);
+                })
+            ),
+            (p(ga, ""), VolumeText(channel)),
+            (
+                right_button(ga),
+                observe(move |_: On<Pointer<Click>>, mut audio: ResMut<AudioSettings>| {
+                    let volume = channel.volume_mut(&mut audio);
+                    *volume = (*volume + 0.1).min(1.0);
+                })
+            )
+        ],
+    )
+}
+
+pub fn run_menu(
+    flash_intensity: Res<FlashIntensity>,
+    audio: Res<AudioSettings>,
+    palette: Res<PaletteSettings>,
+    assist: Res<AssistSettings>,
+    animation: Res<AnimationSettings>,
+    local_clock: Res<LocalClock>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<FlashIntensityText>>,
+        Query<(&mut Text, &VolumeText)>,
+        Query<&mut Text, With<PaletteText>>,
+        Query<&mut Text>,
+        Query<&mut Text, With<AnimationSpeedText>>,
+        Query<&mut Text, With<LocalClockText>>,
+    )>,
+    (mute_button, music_button, dot_shapes_button, move_preview_button, threats_button): (
+        Query<&Children, With<MuteButton>>,
+        Query<&Children, With<MusicButton>>,
+        Query<&Children, With<DotShapesButton>>,
+        Query<&Children, With<MovePreviewButton>>,
+        Query<&Children, With<ThreatsButton>>,
+    ),
+    (instant_button, fast_forward_button): (Query<&Children, With<InstantButton>>, Query<&Children, With<FastForwardButton>>),
+) {
+    if flash_intensity.is_changed() {
+        for mut text in &mut texts.p0() {
+            text.0 = format!("{:#1.1}", flash_intensity.0);
+        }
+    }
+    if audio.is_changed() {
+        for (mut text, VolumeText(channel)) in &mut texts.p1() {
+            text.0 = format!("{:>3}%", (channel.volume(&audio) * 100.0).round());
+        }
+    }
+    if palette.is_changed() {
+        for mut text in &mut texts.p2() {
+            text.0 = format!("{:^13}", palette.palette.name());
+        }
+    }
+    if animation.is_changed() {
+        for mut text in &mut texts.p4() {
+            text.0 = format!("{:^5}", format!("{}x", animation.speed));
+        }
+    }
+    if local_clock.is_changed() {
+        for mut text in &mut texts.p5() {
+            text.0 = format!("{:^15}", local_clock.0.name());
+        }
+    }
+    if !audio.is_changed() && !palette.is_changed() && !assist.is_changed() && !animation.is_changed() {
+        return;
+    }
+    let labels = [
+        (mute_button.single(), if audio.muted { "Unmute" } else { "Mute" }),
+        (music_button.single(), if audio.music_enabled { "Music: on" } else { "Music: off" }),
+        (
+            dot_shapes_button.single(),
+            if palette.dot_shapes { "Dot shapes: on" } else { "Dot shapes: off" },
+        ),
+        (
+            move_preview_button.single(),
+            if assist.move_preview { "Move preview: on" } else { "Move preview: off" },
+        ),
+        (threats_button.single(), if assist.threats { "Threats: on" } else { "Threats: off" }),
+        (instant_button.single(), if animation.instant { "Instant: on" } else { "Instant: off" }),
+        (
+            fast_forward_button.single(),
+            if animation.fast_forward {
+                "Fast-forward bots: on"
+            } else {
+                "Fast-forward bots: off"
+            },
+        ),
+    ];
+    for (children, label) in labels {
+        let Ok(children) = children else {
+            continue;
+        };
+        let mut button_texts = texts.p3();
+        let mut button_texts = button_texts.iter_many_mut(children);
+        while let Some(mut text) = button_texts.fetch_next() {
+            text.0 = label.into();
+        }
+    }
+}
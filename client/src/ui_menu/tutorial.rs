@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use crate::{
+    MainState,
+    menu::{MainMenuSubState, MenuState},
+    tutorial::{Tutorial, TutorialPhase, TutorialScript},
+};
+
+use super::{TutorialUiTree, support::*};
+
+#[derive(Component)]
+pub struct TutorialText;
+
+#[derive(Component)]
+pub struct TutorialNextButton;
+
+pub fn menu(ga: &GameAssets) -> impl Bundle {
+    (
+        TutorialUiTree,
+        Node {
+            display: Display::Flex,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            padding: UiRect::top(Val::Px(15.0)),
+            ..default()
+        },
+        Pickable::IGNORE,
+        Visibility::Hidden,
+        children![
+            (
+                Node {
+                    min_width: Val::Px(0.0),
+                    max_width: Val::Percent(60.0),
+                    ..default()
+                },
+                Pickable::IGNORE,
+                p(ga, ""),
+                TutorialText,
+            ),
+            (
+                Node {
+                    margin: UiRect::top(Val::Px(10.0)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                Pickable::IGNORE,
+                children![
+                    (
+                        TutorialNextButton,
+                        button_default_bg(ga, ""),
+                        observe(
+                            |_: On<Pointer<Click>>,
+                             mut tutorial: ResMut<Tutorial>,
+                             scripts: Res<Assets<TutorialScript>>,
+                             mut next_state: ResMut<NextState<MainState>>,
+                             mut next_menu_state: ResMut<NextState<MenuState>>| {
+                                if tutorial.phase != TutorialPhase::Reading {
+                                    return;
+                                }
+                                let step_count = scripts.get(&tutorial.script).map_or(0, |x| x.steps.len());
+                                if tutorial.step + 1 < step_count {
+                                    tutorial.advance();
+                                } else {
+                                    next_state.set(MainState::Menu);
+                                    next_menu_state.set(MenuState::Main(Some(MainMenuSubState::Main)));
+                                }
+                            }
+                        ),
+                    ),
+                    (
+                        button_default_bg(ga, "Exit tutorial"),
+                        observe(
+                            |_: On<Pointer<Click>>, mut next_state: ResMut<NextState<MainState>>, mut next_menu_state: ResMut<NextState<MenuState>>| {
+                                next_state.set(MainState::Menu);
+                                next_menu_state.set(MenuState::Main(Some(MainMenuSubState::Main)));
+                            }
+                        ),
+                    ),
+                ],
+            ),
+        ],
+    )
+}
+
+pub fn run_menu(
+    tutorial: Res<Tutorial>,
+    scripts: Res<Assets<TutorialScript>>,
+    main_state: Res<State<MainState>>,
+    mut ui_tree: Query<&mut Visibility, With<TutorialUiTree>>,
+    mut text: Query<&mut Text, With<TutorialText>>,
+    mut next_button: Query<(&mut Node, &Children), With<TutorialNextButton>>,
+    mut button_texts: Query<&mut Text, Without<TutorialText>>,
+) {
+    let Ok(mut visibility) = ui_tree.single_mut() else {
+        return;
+    };
+    let step_count = scripts.get(&tutorial.script).map_or(0, |x| x.steps.len());
+    let step = scripts.get(&tutorial.script).and_then(|x| x.steps.get(tutorial.step));
+    let (true, MainState::Game, Some(step)) = (tutorial.active, main_state.get(), step) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    let reading = tutorial.phase == TutorialPhase::Reading;
+    let body = match (&step.after, reading && step.force.is_some()) {
+        (Some(after), true) => after,
+        _ => &step.text,
+    };
+    if let Ok(mut text) = text.single_mut()
+        && text.0 != *body
+    {
+        text.0.clone_from(body);
+    }
+
+    let label = if tutorial.step + 1 < step_count { "Next" } else { "Finish" };
+    if let Ok((mut node, children)) = next_button.single_mut() {
+        node.display = if reading { Display::Flex } else { Display::None };
+        let mut texts = button_texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            if text.0 != label {
+                text.0 = label.into();
+            }
+        }
+    }
+}
@@ -186,6 +186,42 @@ impl Ai for Easy {
     }
 }
 
+/// Sizes up the cell at `(x, y)` against the neighbours `player` doesn't own, by how many more dots each needs to cascade.
+///
+/// Returns `None` if a neighbour would cascade first, so building here opens the door to them. Otherwise returns how many neighbours this cell would cascade into first.
+pub fn door_check(grid: &Grid, x: u8, y: u8, player: u8) -> Option<i32> {
+    let cell = grid[y][x];
+    let holes = cell.capacity - cell.dots;
+    let mut neighbors = Vec::new();
+    if x > 0 {
+        neighbors.push(grid[y][x - 1]);
+    }
+    if y > 0 {
+        neighbors.push(grid[y - 1][x]);
+    }
+    if x < grid.width() - 1 {
+        neighbors.push(grid[y][x + 1]);
+    }
+    if y < grid.height() - 1 {
+        neighbors.push(grid[y + 1][x]);
+    }
+    let mut ahead = 0;
+    for neighbor in neighbors {
+        if neighbor.owner == player {
+            continue;
+        }
+        let n_holes = neighbor.capacity - neighbor.dots;
+        if n_holes < holes {
+            // They will cascade first. Don't chance it.
+            return None;
+        } else if n_holes > holes {
+            // We're going to win. Let's do this.
+            ahead += 1;
+        }
+    }
+    Some(ahead)
+}
+
 #[derive(Default)]
 pub struct Medium<const FAIL_CHANCE: u8>(Option<(u8, u8)>);
 
@@ -237,39 +273,12 @@ impl<const FAIL_CHANCE: u8> Medium<FAIL_CHANCE> {
         }
         // Alright, no cascades; let's look for anything else that doesn't shoot us in the foot
         let mut new_candidates = Vec::new();
-        'outer: for &((x, y), eval) in &evals {
+        for &((x, y), eval) in &evals {
             // This is the "don't open the door" check. We don't want to build next to someone who will win.
-            let cell = grid[y][x];
-            let holes = cell.capacity - cell.dots;
-            let mut neighbors = Vec::new();
-            let mut eval = eval; // Copy; we don't want to edit the original evaluation.
-            if x > 0 {
-                neighbors.push(grid[y][x - 1]);
-            }
-            if y > 0 {
-                neighbors.push(grid[y - 1][x]);
-            }
-            if x < grid.width() - 1 {
-                neighbors.push(grid[y][x + 1]);
+            if let Some(bonus) = door_check(grid, x, y, player) {
+                // Okay, we passed the check. It's a candidate move now.
+                new_candidates.push(((x, y), eval + bonus));
             }
-            if y < grid.height() - 1 {
-                neighbors.push(grid[y + 1][x]);
-            }
-            for neighbor in neighbors {
-                if neighbor.owner == player {
-                    continue;
-                }
-                let n_holes = neighbor.capacity - neighbor.dots;
-                if n_holes < holes {
-                    // They will cascade first. Don't chance it.
-                    continue 'outer;
-                } else if n_holes > holes {
-                    // We're going to win. Let's do this.
-                    eval += 1;
-                }
-            }
-            // Okay, we passed the check. It's a candidate move now.
-            new_candidates.push(((x, y), eval));
         }
         let max_eval = new_candidates
             .iter()
@@ -738,6 +747,8 @@ impl<Fallback: Ai + Default, const FALLBACK_CHANCE: u8> Ai for Hard<Fallback, FA
 mod test {
     use rand::rand_core;
 
+    use crate::grid::GridCell;
+
     use super::*;
 
     struct DeterministicRng<const N: usize> {
@@ -787,4 +798,26 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn door_check_test() {
+        let mut grid = Grid::new(3, 3, 2);
+        grid.init_capacity();
+        // Player 2 has a full corner, so player 1 shouldn't build on the edge next to it
+        grid[0u8][0u8] = GridCell {
+            dots: 2,
+            owner: 2,
+            capacity: 2,
+        };
+        assert_eq!(door_check(&grid, 1, 0, 1), None);
+        // The middle is only next to empty edges, which all need more dots than it
+        grid[1u8][1u8] = GridCell {
+            dots: 3,
+            owner: 1,
+            capacity: 4,
+        };
+        assert_eq!(door_check(&grid, 1, 1, 1), Some(4));
+        // Player 2 sees the same corner as theirs
+        assert_eq!(door_check(&grid, 0, 0, 2), Some(2));
+    }
 }
@@ -48,6 +48,10 @@ impl CellState {
     pub fn inner(self) -> u8 {
         self.0
     }
+
+    pub const fn from_inner(inner: u8) -> Self {
+        Self(inner)
+    }
 }
 
 #[derive(Encode, Decode, Copy, Clone, Debug, Hash, PartialEq, Eq)]
@@ -95,13 +99,13 @@ pub struct GameInfo {
 #[derive(Encode, Decode, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct BoardInfo(pub Vec<CellState>);
 
-#[derive(Encode, Decode, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum ProposalType {
     Resign,
     Draw,
 }
 
-#[derive(Encode, Decode, Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Encode, Decode, Serialize, Deserialize, Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum PlayerStatus {
     Normal,
     Elim,
@@ -143,7 +147,7 @@ pub enum WinReason {
     Time,
 }
 
-#[derive(Encode, Decode, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum DrawReason {
     Agreement,
     Progress,
@@ -1,14 +1,20 @@
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, Ordering},
+use std::{
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use common::{grid::Grid, proto::CellState};
+use common::{
+    grid::Grid,
+    proto::{CellState, DrawReason, PlayerStatus, ProposalType},
+};
 use dashmap::{DashMap, mapref::one::Ref};
-use rand::seq::SliceRandom as _;
+use rand::{Rng as _, seq::SliceRandom as _};
 use serde::{Deserialize, Serialize};
 
-use crate::{GameSettings, WsHandler};
+use crate::{GameSettings, Rules, TimeControl, WsHandler, validate::MoveError};
 
 #[derive(Default)]
 pub struct RunningGames {
@@ -46,21 +52,36 @@ impl RunningGames {
 #[derive(Deserialize)]
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum GameServerbound {
-    Move { x: u8, y: u8 },
+    Move {
+        x: u8,
+        y: u8,
+    },
     Resign,
+    /// Offers a draw, or agrees to one that's been offered. Resigning needs nobody to agree, so is the same as [`GameServerbound::Resign`].
+    Propose {
+        kind: ProposalType,
+    },
+    /// Takes back a draw offer, or turns one down.
+    CancelProposal {
+        kind: ProposalType,
+    },
 }
 
-#[derive(Clone, Copy, Serialize)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub enum LeaveReason {
     Disconnected,
     NoLegalMoves,
     Resigned,
+    TimedOut,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum GameClientbound {
     OutOfTurn,
+    Rejected {
+        error: MoveError,
+    },
     InvalidMove {
         grid: Vec<u8>, // Vec<CellState>
     },
@@ -71,20 +92,61 @@ pub enum GameClientbound {
     },
     GameStart {
         me: u8,
+        /// Gets the seat back after a dropped connection.
+        token: String,
+    },
+    /// Sent to a player who has come back, before the eliminations so far and whose turn it is.
+    Rejoined {
+        me: u8,
+        grid: Vec<u8>, // Vec<CellState>
+    },
+    /// A player dropped out and has a while to come back, or has come back.
+    PlayerStatus {
+        player: u8,
+        status: PlayerStatus,
+    },
+    /// Sent to spectators when they connect, before the eliminations so far and whose turn it is.
+    Spectating {
+        width: u8,
+        height: u8,
+        players: u8,
+        grid: Vec<u8>, // Vec<CellState>
     },
     PlayerEliminated {
         player: u8,
         reason: LeaveReason,
     },
+    /// A player offered a draw, or agreed to one already offered. It's a draw once everyone still playing has.
+    RemoteProposal {
+        player: u8,
+        kind: ProposalType,
+    },
+    /// A player turned down or took back a draw offer, so every offer so far is off.
+    ProposalRefused {
+        player: u8,
+        kind: ProposalType,
+    },
+    /// Everyone still playing agreed. Followed by what it led to, like a [`GameClientbound::GameDraw`].
+    ProposalAccepted {
+        kind: ProposalType,
+    },
     WaitingFor {
         players: u8,
     },
+    /// Time left on everyone's clock in milliseconds, by seat, just before a [`GameClientbound::Turn`].
+    /// Only the player whose turn it is has their clock running. Not sent without a clock.
+    Clocks {
+        remaining_ms: Vec<u64>,
+    },
     Turn {
         player: u8,
     },
     GameWin {
         player: u8,
     },
+    GameDraw {
+        reason: DrawReason,
+    },
 }
 
 pub struct GameData {
@@ -95,29 +157,107 @@ pub struct GameData {
     remaining_players: Vec<u8>,
     waiting_count: u8,
     cur_player: u8,
+    settings: GameSettings,
+    /// Players who have had a turn, for [`Rules::Classic`].
+    moved: Vec<u8>,
+    /// Who has left the game so far, to catch spectators up.
+    eliminated: Vec<(u8, LeaveReason)>,
+    /// Each player's session token, for getting their seat back.
+    tokens: Vec<(u8, String)>,
+    /// Players whose connection dropped, and when. They're out if they aren't back within `grace`.
+    disconnected: Vec<(u8, Instant)>,
+    grace: Duration,
+    /// Time left for each seat, as of the start of the current turn. Empty without a clock.
+    clocks: Vec<Duration>,
+    turn_started: Instant,
+    /// Turns started so far, so a clock's timer can tell it's still the same turn.
+    turns: u32,
+    /// The game this is the data of, for timers to come back to.
+    this: Weak<Mutex<GameData>>,
+    /// Players who have offered or agreed to a draw since the last refusal.
+    draw_offers: Vec<u8>,
+    /// Moves in a row without a capture. Claiming an empty cell doesn't count as one.
+    quiet_moves: u32,
+    drawn: Option<DrawReason>,
+}
+
+impl TimeControl {
+    /// What a clock starts at, or is reset to each move. `None` without a clock.
+    fn allowance(self) -> Option<Duration> {
+        match self {
+            Self::Unlimited => None,
+            Self::Fischer { base, .. } => Some(Duration::from_secs(base.into())),
+            Self::PerMove { secs } => Some(Duration::from_secs(secs.into())),
+            Self::Correspondence { days } => {
+                Some(Duration::from_secs(u64::from(days) * 24 * 60 * 60))
+            }
+        }
+    }
 }
 
 impl GameData {
     const GAME_OVER_SENTINEL: u8 = 254;
     const SPECTATOR_SENTINEL: u8 = 255;
+    /// How long a dropped player's seat is held for them.
+    const RECONNECT_GRACE: Duration = Duration::from_secs(60);
+    /// Moves without a capture before the game is drawn. Every move adds a dot, so positions never repeat.
+    const NO_PROGRESS_MOVES: u32 = 60;
+
+    /// Checks a move could be played at all, before looking at whose cell it is.
+    fn check_move(&self, player: u8, x: u8, y: u8) -> Result<(), Option<MoveError>> {
+        let playing = player != Self::SPECTATOR_SENTINEL
+            && (self.waiting_count > 0 || self.remaining_players.contains(&player));
+        if !playing {
+            return Err(Some(MoveError::NotPlaying));
+        }
+        // Nobody's turn until everyone's here, or once the game is over
+        if self.waiting_count > 0 || player != self.cur_player {
+            return Err(None);
+        }
+        let (width, height) = (self.grid.width(), self.grid.height());
+        if x >= width || y >= height {
+            return Err(Some(MoveError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            }));
+        }
+        Ok(())
+    }
 
     fn play_move(&mut self, player: u8, x: u8, y: u8) -> bool {
         if self.grid[y][x].owner != 0 && self.grid[y][x].owner != player {
             return false;
         }
         self.broadcast(GameClientbound::Move { player, x, y });
+        if !self.moved.contains(&player) {
+            self.moved.push(player);
+        }
+        self.stop_clock(player);
         let (new_grid, _) = self.grid.with_move(x, y, player);
         let losers = if let Some(new_grid) = new_grid {
+            let captured = self
+                .grid
+                .grid_inner()
+                .iter()
+                .zip(new_grid.grid_inner())
+                .any(|(old, new)| old.owner != 0 && old.owner != new.owner);
+            self.quiet_moves = if captured { 0 } else { self.quiet_moves + 1 };
             self.grid = new_grid;
             self.remaining_players
                 .iter()
                 .copied()
-                .filter(|&x| {
-                    !self
+                .filter(|&x| match self.settings.rules {
+                    Rules::LastStand => !self
                         .grid
                         .grid_inner()
                         .iter()
-                        .any(|cell| cell.owner == 0 || cell.owner == x)
+                        .any(|cell| cell.owner == 0 || cell.owner == x),
+                    Rules::Classic => {
+                        self.moved.contains(&x)
+                            && !self.grid.grid_inner().iter().any(|cell| cell.owner == x)
+                    }
                 })
                 .collect::<Vec<_>>()
         } else {
@@ -130,20 +270,51 @@ impl GameData {
         for l in losers {
             self.lose(l, LeaveReason::NoLegalMoves);
         }
-        self.advance_turn();
+        if self.quiet_moves >= Self::NO_PROGRESS_MOVES {
+            self.draw(DrawReason::Progress);
+        } else {
+            self.advance_turn();
+        }
         true
     }
 
     fn ready(&mut self, player: &GameHandler) {
         self.senders
             .push((player.sender.as_ref().unwrap().clone(), player.me));
+        if player.me == Self::SPECTATOR_SENTINEL {
+            // Nobody is waiting on them, and the game may well have started already
+            self.catch_up(
+                player,
+                GameClientbound::Spectating {
+                    width: self.grid.width(),
+                    height: self.grid.height(),
+                    players: self.settings.capacity,
+                    grid: self.compressed_grid(),
+                },
+            );
+            return;
+        }
+        if self.waiting_count == 0 {
+            self.rejoin(player);
+            return;
+        }
         self.waiting_count -= 1;
         if self.waiting_count == 0 {
+            let mut rng = rand::rng();
             for (sender, player) in &self.senders {
-                sender(GameClientbound::GameStart { me: *player });
+                if *player != Self::SPECTATOR_SENTINEL {
+                    let token = format!("{:032x}", rng.random::<u128>());
+                    self.tokens.push((*player, token.clone()));
+                    sender(GameClientbound::GameStart { me: *player, token });
+                }
             }
-            self.remaining_players = (1..=self.senders.len()).map(|x| x as u8).collect();
-            self.broadcast(GameClientbound::Turn { player: 1 });
+            let seats = self
+                .senders
+                .iter()
+                .filter(|(_, player)| *player != Self::SPECTATOR_SENTINEL)
+                .count();
+            self.remaining_players = (1..=seats).map(|x| x as u8).collect();
+            self.start_turn();
         } else {
             self.broadcast(GameClientbound::WaitingFor {
                 players: self.waiting_count,
@@ -151,6 +322,114 @@ impl GameData {
         }
     }
 
+    /// Tells a new connection everything it's missed, after `board`: who's out or away, and whose turn it is.
+    fn catch_up(&self, handler: &GameHandler, board: GameClientbound) {
+        handler.send(board);
+        if self.waiting_count > 0 {
+            handler.send(GameClientbound::WaitingFor {
+                players: self.waiting_count,
+            });
+            return;
+        }
+        for &(player, reason) in &self.eliminated {
+            handler.send(GameClientbound::PlayerEliminated { player, reason });
+        }
+        for &(player, _) in &self.disconnected {
+            handler.send(GameClientbound::PlayerStatus {
+                player,
+                status: PlayerStatus::Disconnected,
+            });
+        }
+        if let Some(reason) = self.drawn {
+            handler.send(GameClientbound::GameDraw { reason });
+            return;
+        }
+        for &player in &self.draw_offers {
+            handler.send(GameClientbound::RemoteProposal {
+                player,
+                kind: ProposalType::Draw,
+            });
+        }
+        if self.cur_player == Self::GAME_OVER_SENTINEL {
+            handler.send(GameClientbound::GameWin {
+                player: self.remaining_players[0],
+            });
+        } else {
+            if !self.clocks.is_empty() {
+                handler.send(GameClientbound::Clocks {
+                    remaining_ms: self.remaining_ms(),
+                });
+            }
+            handler.send(GameClientbound::Turn {
+                player: self.cur_player,
+            });
+        }
+    }
+
+    /// Gives a player who dropped out their seat back, on their new connection.
+    fn rejoin(&mut self, player: &GameHandler) {
+        let me = player.me;
+        let sender = player.sender.as_ref().unwrap();
+        // Their old connection may not have noticed it's gone yet
+        self.senders
+            .retain(|(x, seat)| *seat != me || Arc::ptr_eq(x, sender));
+        if let Some(idx) = self.disconnected.iter().position(|&(x, _)| x == me) {
+            self.disconnected.remove(idx);
+            for (sender, _) in self.senders.iter().filter(|(_, seat)| *seat != me) {
+                sender(GameClientbound::PlayerStatus {
+                    player: me,
+                    status: PlayerStatus::Normal,
+                });
+            }
+        }
+        self.catch_up(
+            player,
+            GameClientbound::Rejoined {
+                me,
+                grid: self.compressed_grid(),
+            },
+        );
+    }
+
+    /// Holds a dropped player's seat for a while, and puts them out if they don't come back.
+    /// Before the game starts there's no seat to hold, so it's free for whoever joins next.
+    fn disconnect(&mut self, player: u8) {
+        if self.waiting_count > 0 {
+            self.senders.retain(|&(_, seat)| seat != player);
+            self.remaining_players.push(player);
+            self.waiting_count += 1;
+            self.broadcast(GameClientbound::WaitingFor {
+                players: self.waiting_count,
+            });
+            return;
+        }
+        if !self.remaining_players.contains(&player) {
+            return; // Nothing to hold
+        }
+        self.disconnected.push((player, Instant::now()));
+        self.broadcast(GameClientbound::PlayerStatus {
+            player,
+            status: PlayerStatus::Disconnected,
+        });
+        let grace = self.grace;
+        let game_data = self.this.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            let Some(game_data) = game_data.upgrade() else {
+                return;
+            };
+            let mut data = game_data.lock().unwrap();
+            // Still gone since this drop, rather than back or dropped again since
+            let expired = data
+                .disconnected
+                .iter()
+                .any(|&(x, at)| x == player && at.elapsed() >= grace);
+            if expired {
+                data.lose(player, LeaveReason::Disconnected);
+            }
+        });
+    }
+
     fn broadcast(&self, msg: GameClientbound) {
         for (sender, _) in &self.senders {
             sender(msg.clone());
@@ -180,23 +459,151 @@ impl GameData {
                 break;
             }
         }
-        self.broadcast(GameClientbound::Turn {
-            player: self.cur_player,
-        });
+        self.start_turn();
+    }
+
+    /// Tells everyone it's `cur_player`'s turn, and starts their clock.
+    fn start_turn(&mut self) {
+        let player = self.cur_player;
+        self.turns += 1;
+        self.turn_started = Instant::now();
+        if !self.clocks.is_empty() {
+            let left = &mut self.clocks[usize::from(player - 1)];
+            if let TimeControl::PerMove { .. } | TimeControl::Correspondence { .. } =
+                self.settings.clock
+            {
+                *left = self.settings.clock.allowance().unwrap();
+            }
+            let (left, turn) = (*left, self.turns);
+            self.broadcast(GameClientbound::Clocks {
+                remaining_ms: self.remaining_ms(),
+            });
+            let game_data = self.this.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(left).await;
+                let Some(game_data) = game_data.upgrade() else {
+                    return;
+                };
+                let mut data = game_data.lock().unwrap();
+                // Moved in time if it's another turn by now, or the game is over
+                if data.turns == turn && data.cur_player == player {
+                    data.flag(player);
+                }
+            });
+        }
+        self.broadcast(GameClientbound::Turn { player });
+    }
+
+    /// Takes the time the player took off their clock, and adds on their increment.
+    fn stop_clock(&mut self, player: u8) {
+        let Some(left) = self.clocks.get_mut(usize::from(player - 1)) else {
+            return;
+        };
+        *left = left.saturating_sub(self.turn_started.elapsed());
+        if let TimeControl::Fischer { increment, .. } = self.settings.clock {
+            *left += Duration::from_secs(increment.into());
+        }
+    }
+
+    /// Whether the current player's clock has run out, even if its timer hasn't gone off yet.
+    fn out_of_time(&self) -> bool {
+        self.clocks
+            .get(usize::from(self.cur_player.wrapping_sub(1)))
+            .is_some_and(|&left| self.turn_started.elapsed() >= left)
+    }
+
+    fn flag(&mut self, player: u8) {
+        self.clocks[usize::from(player - 1)] = Duration::ZERO;
+        self.lose(player, LeaveReason::TimedOut);
+    }
+
+    fn remaining_ms(&self) -> Vec<u64> {
+        self.clocks
+            .iter()
+            .zip(1..)
+            .map(|(&left, player)| {
+                let left = if player == self.cur_player {
+                    left.saturating_sub(self.turn_started.elapsed())
+                } else {
+                    left
+                };
+                left.as_millis() as u64
+            })
+            .collect()
     }
 
     fn lose(&mut self, player: u8, reason: LeaveReason) {
+        if self.waiting_count > 0
+            || self.cur_player == Self::GAME_OVER_SENTINEL
+            || !self.remaining_players.contains(&player)
+        {
+            // Not in the game, already out of it, or the game's over
+            return;
+        }
         self.broadcast(GameClientbound::PlayerEliminated { player, reason });
+        self.eliminated.push((player, reason));
+        self.disconnected.retain(|&(x, _)| x != player);
         self.remaining_players.retain(|&x| x != player);
+        self.draw_offers.retain(|&x| x != player);
         if self.remaining_players.len() == 1 {
             self.broadcast(GameClientbound::GameWin {
                 player: self.remaining_players[0],
             });
             self.cur_player = Self::GAME_OVER_SENTINEL; // Now it's nobody's turn!
-        } else if self.cur_player == player {
+            return;
+        }
+        // Everyone left may have agreed to a draw already
+        self.check_agreement();
+        if self.cur_player == player {
             self.advance_turn();
         }
     }
+
+    fn propose(&mut self, player: u8, kind: ProposalType) {
+        let playing = self.waiting_count == 0
+            && self.cur_player != Self::GAME_OVER_SENTINEL
+            && self.remaining_players.contains(&player);
+        match kind {
+            ProposalType::Resign => self.lose(player, LeaveReason::Resigned),
+            ProposalType::Draw if playing && !self.draw_offers.contains(&player) => {
+                self.draw_offers.push(player);
+                self.broadcast(GameClientbound::RemoteProposal { player, kind });
+                self.check_agreement();
+            }
+            ProposalType::Draw => {}
+        }
+    }
+
+    fn cancel_proposal(&mut self, player: u8, kind: ProposalType) {
+        let playing =
+            self.cur_player != Self::GAME_OVER_SENTINEL && self.remaining_players.contains(&player);
+        if kind == ProposalType::Draw && playing && !self.draw_offers.is_empty() {
+            self.draw_offers.clear();
+            self.broadcast(GameClientbound::ProposalRefused { player, kind });
+        }
+    }
+
+    fn check_agreement(&mut self) {
+        let agreed = self
+            .remaining_players
+            .iter()
+            .all(|x| self.draw_offers.contains(x));
+        if !self.draw_offers.is_empty() && agreed && self.cur_player != Self::GAME_OVER_SENTINEL {
+            self.broadcast(GameClientbound::ProposalAccepted {
+                kind: ProposalType::Draw,
+            });
+            self.draw(DrawReason::Agreement);
+        }
+    }
+
+    fn draw(&mut self, reason: DrawReason) {
+        if self.cur_player == Self::GAME_OVER_SENTINEL {
+            return;
+        }
+        self.broadcast(GameClientbound::GameDraw { reason });
+        self.drawn = Some(reason);
+        self.cur_player = Self::GAME_OVER_SENTINEL;
+    }
 }
 
 pub struct Game {
@@ -209,31 +616,79 @@ impl Game {
         grid.init_capacity();
         let mut remaining_players = (1..=settings.capacity).collect::<Vec<_>>();
         remaining_players.shuffle(&mut rand::rng());
+        let clocks = settings
+            .clock
+            .allowance()
+            .map(|x| vec![x; settings.capacity.into()])
+            .unwrap_or_default();
         Self {
-            data: Arc::new(Mutex::new(GameData {
-                grid,
-                senders: Vec::new(),
-                remaining_players,
-                waiting_count: settings.capacity,
-                cur_player: 1,
-            })),
+            data: Arc::new_cyclic(|this| {
+                Mutex::new(GameData {
+                    grid,
+                    senders: Vec::new(),
+                    remaining_players,
+                    waiting_count: settings.capacity,
+                    cur_player: 1,
+                    settings,
+                    moved: Vec::new(),
+                    eliminated: Vec::new(),
+                    tokens: Vec::new(),
+                    disconnected: Vec::new(),
+                    grace: GameData::RECONNECT_GRACE,
+                    clocks,
+                    turn_started: Instant::now(),
+                    turns: 0,
+                    this: this.clone(),
+                    draw_offers: Vec::new(),
+                    quiet_moves: 0,
+                    drawn: None,
+                })
+            }),
         }
     }
 
+    /// A handler for a new socket. `None` if there's no seat for it, or the game doesn't allow spectators.
     pub fn new_handler(
         &self,
-    ) -> impl WsHandler<Serverbound = GameServerbound, Clientbound = GameClientbound> + Send + Sync + use<>
-    {
+        join: JoinAs,
+    ) -> Option<
+        impl WsHandler<Serverbound = GameServerbound, Clientbound = GameClientbound>
+        + Send
+        + Sync
+        + use<>,
+    > {
         let mut data = self.data.lock().unwrap();
-        GameHandler::new(
-            self,
-            data.remaining_players
-                .pop()
-                .unwrap_or(GameData::SPECTATOR_SENTINEL),
-        )
+        let me = match join {
+            JoinAs::Spectator => data
+                .settings
+                .spectators
+                .then_some(GameData::SPECTATOR_SENTINEL)?,
+            JoinAs::Rejoin { token } => data
+                .tokens
+                .iter()
+                .find(|(_, x)| *x == token)
+                .map(|&(player, _)| player)
+                .filter(|x| data.remaining_players.contains(x))?,
+            // Once the game has started, `remaining_players` is who's still playing, not free seats
+            JoinAs::Player if data.waiting_count == 0 => return None,
+            JoinAs::Player => data.remaining_players.pop()?,
+        };
+        Some(GameHandler::new(self, me))
     }
 }
 
+/// Who a new connection to a game is.
+#[derive(Clone, Copy, Debug)]
+pub enum JoinAs<'a> {
+    /// Takes the next free seat, before the game starts.
+    Player,
+    Spectator,
+    /// Takes back the seat given this token with [`GameClientbound::GameStart`].
+    Rejoin {
+        token: &'a str,
+    },
+}
+
 pub struct GameHandler {
     me: u8,
     sender: Option<Arc<dyn Fn(GameClientbound) + Send + Sync>>,
@@ -261,26 +716,35 @@ impl WsHandler for GameHandler {
     async fn receive(&mut self, message: GameServerbound) {
         let mut data = self.game_data.lock().unwrap();
         match message {
-            GameServerbound::Move { x, y } => {
-                if self.me != data.cur_player {
-                    self.send(GameClientbound::OutOfTurn);
-                } else if !data.play_move(self.me, x, y) {
-                    self.send(GameClientbound::InvalidMove {
-                        grid: data.compressed_grid(),
-                    });
+            GameServerbound::Move { x, y } => match data.check_move(self.me, x, y) {
+                Err(Some(error)) => self.send(GameClientbound::Rejected { error }),
+                Err(None) => self.send(GameClientbound::OutOfTurn),
+                Ok(()) if data.out_of_time() => data.flag(self.me),
+                Ok(()) => {
+                    if !data.play_move(self.me, x, y) {
+                        self.send(GameClientbound::InvalidMove {
+                            grid: data.compressed_grid(),
+                        });
+                    }
                 }
-            }
+            },
             GameServerbound::Resign => {
                 data.lose(self.me, LeaveReason::Resigned);
             }
+            GameServerbound::Propose { kind } => data.propose(self.me, kind),
+            GameServerbound::CancelProposal { kind } => data.cancel_proposal(self.me, kind),
         }
     }
 
     async fn close(&mut self) {
-        self.game_data
-            .lock()
-            .unwrap()
-            .lose(self.me, LeaveReason::Disconnected);
+        let mut data = self.game_data.lock().unwrap();
+        let sender = self.sender.as_ref().unwrap();
+        if self.me == GameData::SPECTATOR_SENTINEL {
+            data.senders.retain(|(x, _)| !Arc::ptr_eq(x, sender));
+        } else if data.senders.iter().any(|(x, _)| Arc::ptr_eq(x, sender)) {
+            // Otherwise they're already back on another connection
+            data.disconnect(self.me);
+        }
     }
 
     fn set_send_handler(&mut self, handler: Box<dyn Fn(GameClientbound) + Send + Sync>) {
@@ -288,3 +752,629 @@ impl WsHandler for GameHandler {
         self.game_data.lock().unwrap().ready(self);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use hyper_tungstenite::{WebSocketStream, tungstenite::protocol::Role};
+
+    use crate::TimeControl;
+
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<GameClientbound>>>;
+
+    struct Seat<H> {
+        handler: H,
+        received: Received,
+    }
+
+    impl<H: WsHandler<Serverbound = GameServerbound, Clientbound = GameClientbound>> Seat<H> {
+        fn take(&self) -> Vec<GameClientbound> {
+            std::mem::take(&mut self.received.lock().unwrap())
+        }
+
+        async fn play(&mut self, x: u8, y: u8) -> Vec<GameClientbound> {
+            self.take();
+            self.handler.receive(GameServerbound::Move { x, y }).await;
+            self.take()
+        }
+
+        /// The player this seat was given, once the game has started.
+        fn me(&self) -> Option<u8> {
+            self.received.lock().unwrap().iter().find_map(|x| match x {
+                GameClientbound::GameStart { me, .. } => Some(*me),
+                _ => None,
+            })
+        }
+
+        fn token(&self) -> String {
+            self.received
+                .lock()
+                .unwrap()
+                .iter()
+                .find_map(|x| match x {
+                    GameClientbound::GameStart { token, .. } => Some(token.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        }
+    }
+
+    fn game(capacity: u8, spectators: bool) -> Game {
+        timed(capacity, spectators, TimeControl::Unlimited)
+    }
+
+    fn timed(capacity: u8, spectators: bool, clock: TimeControl) -> Game {
+        Game::new(GameSettings {
+            capacity,
+            width: 3,
+            height: 3,
+            clock,
+            rules: Rules::LastStand,
+            public: false,
+            spectators,
+        })
+    }
+
+    fn sit(
+        game: &Game,
+        join: JoinAs,
+    ) -> Option<Seat<impl WsHandler<Serverbound = GameServerbound, Clientbound = GameClientbound>>>
+    {
+        let mut handler = game.new_handler(join)?;
+        let received = Received::default();
+        handler.set_send_handler(Box::new({
+            let received = received.clone();
+            move |x| received.lock().unwrap().push(x)
+        }));
+        Some(Seat { handler, received })
+    }
+
+    /// Both seats of a two player game, with player 1 first.
+    fn started(
+        game: &Game,
+    ) -> [Seat<impl WsHandler<Serverbound = GameServerbound, Clientbound = GameClientbound>>; 2]
+    {
+        let mut seats = [
+            sit(game, JoinAs::Player).unwrap(),
+            sit(game, JoinAs::Player).unwrap(),
+        ];
+        if seats[0].me() != Some(1) {
+            seats.swap(0, 1);
+        }
+        assert_eq!(seats.each_ref().map(Seat::me), [Some(1), Some(2)]);
+        seats
+    }
+
+    #[tokio::test]
+    async fn rejects_moves_off_the_board() {
+        let game = game(2, true);
+        let [mut first, _second] = started(&game);
+        for (x, y) in [(3, 0), (0, 3), (255, 255), (3, 3)] {
+            let replies = first.play(x, y).await;
+            assert!(
+                matches!(
+                    &replies[..],
+                    [GameClientbound::Rejected {
+                        error: MoveError::OutOfBounds {
+                            width: 3,
+                            height: 3,
+                            ..
+                        }
+                    }]
+                ),
+                "({x}, {y}) got {replies:?}"
+            );
+        }
+        // Still their turn, and the game carries on
+        assert!(matches!(
+            first.play(2, 2).await[..],
+            [
+                GameClientbound::Move {
+                    player: 1,
+                    x: 2,
+                    y: 2
+                },
+                GameClientbound::Turn { player: 2 }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_moves_before_the_game_starts() {
+        let game = game(2, true);
+        let mut early = sit(&game, JoinAs::Player).unwrap();
+        assert!(matches!(
+            early.play(0, 0).await[..],
+            [GameClientbound::OutOfTurn]
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_moves_out_of_turn() {
+        let game = game(2, true);
+        let [mut first, mut second] = started(&game);
+        assert!(matches!(
+            second.play(0, 0).await[..],
+            [GameClientbound::OutOfTurn]
+        ));
+        first.play(0, 0).await;
+        // Player 1's cell now
+        assert!(matches!(
+            second.play(0, 0).await[..],
+            [GameClientbound::InvalidMove { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn spectators_cannot_play() {
+        let game = game(2, true);
+        let [first, _second] = started(&game);
+        let mut spectator = sit(&game, JoinAs::Spectator).unwrap();
+        first.take();
+        assert!(matches!(
+            spectator.play(0, 0).await[..],
+            [GameClientbound::Rejected {
+                error: MoveError::NotPlaying
+            }]
+        ));
+        spectator.handler.receive(GameServerbound::Resign).await;
+        spectator.handler.close().await;
+        assert!(first.take().is_empty());
+        assert_eq!(game.data.lock().unwrap().remaining_players.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn refuses_spectators_when_told_to() {
+        let game = game(2, false);
+        let _seats = started(&game);
+        assert!(sit(&game, JoinAs::Spectator).is_none());
+    }
+
+    #[tokio::test]
+    async fn eliminated_players_cannot_play() {
+        let game = game(3, true);
+        let mut seats = [
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+        ];
+        seats.sort_by_key(Seat::me);
+        let [mut first, mut second, _third] = seats;
+        first.handler.receive(GameServerbound::Resign).await;
+        assert!(matches!(
+            first.play(0, 0).await[..],
+            [GameClientbound::Rejected {
+                error: MoveError::NotPlaying
+            }]
+        ));
+        assert!(matches!(
+            second.play(0, 0).await[..],
+            [
+                GameClientbound::Move { player: 2, .. },
+                GameClientbound::Turn { player: 3 }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn no_seats_once_started() {
+        let game = game(2, true);
+        let _seats = started(&game);
+        assert!(sit(&game, JoinAs::Player).is_none());
+    }
+
+    #[tokio::test]
+    async fn spectators_wait_for_the_start() {
+        let game = game(2, true);
+        let _first = sit(&game, JoinAs::Player).unwrap();
+        let spectator = sit(&game, JoinAs::Spectator).unwrap();
+        assert!(matches!(
+            spectator.take()[..],
+            [
+                GameClientbound::Spectating {
+                    width: 3,
+                    height: 3,
+                    players: 2,
+                    ..
+                },
+                GameClientbound::WaitingFor { players: 1 }
+            ]
+        ));
+        let _second = sit(&game, JoinAs::Player).unwrap();
+        // No seat for them, but they hear the game start
+        assert!(matches!(
+            spectator.take()[..],
+            [GameClientbound::Turn { player: 1 }]
+        ));
+        assert_eq!(spectator.me(), None);
+    }
+
+    #[tokio::test]
+    async fn spectators_catch_up() {
+        let game = game(3, true);
+        let mut seats = [
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+        ];
+        seats.sort_by_key(Seat::me);
+        let [mut first, mut second, _third] = seats;
+        first.play(1, 1).await;
+        second.handler.receive(GameServerbound::Resign).await;
+
+        let spectator = sit(&game, JoinAs::Spectator).unwrap();
+        let replies = spectator.take();
+        let [
+            GameClientbound::Spectating { grid, .. },
+            GameClientbound::PlayerEliminated {
+                player: 2,
+                reason: LeaveReason::Resigned,
+            },
+            GameClientbound::Turn { player: 3 },
+        ] = &replies[..]
+        else {
+            panic!("got {replies:?}");
+        };
+        assert_eq!(grid, &game.data.lock().unwrap().compressed_grid());
+        let centre = CellState::from_inner(grid[4]);
+        assert_eq!(centre.owner().map(u8::from), Some(1));
+
+        // And then sees the game live
+        first.handler.receive(GameServerbound::Resign).await;
+        assert!(matches!(
+            spectator.take()[..],
+            [
+                GameClientbound::PlayerEliminated { player: 1, .. },
+                GameClientbound::GameWin { player: 3 }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejoining_restores_the_seat() {
+        let game = game(2, true);
+        let [mut first, second] = started(&game);
+        let token = first.token();
+        second.take();
+
+        first.handler.close().await;
+        first.take();
+        assert!(matches!(
+            second.take()[..],
+            [GameClientbound::PlayerStatus {
+                player: 1,
+                status: PlayerStatus::Disconnected
+            }]
+        ));
+        assert!(sit(&game, JoinAs::Rejoin { token: "nope" }).is_none());
+
+        let mut back = sit(&game, JoinAs::Rejoin { token: &token }).unwrap();
+        let replies = back.take();
+        let [
+            GameClientbound::Rejoined { me: 1, grid },
+            GameClientbound::Turn { player: 1 },
+        ] = &replies[..]
+        else {
+            panic!("got {replies:?}");
+        };
+        assert_eq!(grid.len(), 9);
+        assert!(matches!(
+            second.take()[..],
+            [GameClientbound::PlayerStatus {
+                player: 1,
+                status: PlayerStatus::Normal
+            }]
+        ));
+        // Still their turn, on the new connection
+        assert!(matches!(
+            back.play(0, 0).await[..],
+            [
+                GameClientbound::Move { player: 1, .. },
+                GameClientbound::Turn { player: 2 }
+            ]
+        ));
+        // Nothing more goes to the old connection
+        assert!(first.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_hold_the_seat() {
+        let game = game(2, true);
+        let (client, server) = tokio::io::duplex(4096);
+        let served = tokio::spawn(crate::serve_stream(
+            WebSocketStream::from_raw_socket(server, Role::Server, None).await,
+            game.new_handler(JoinAs::Player).unwrap(),
+        ));
+        let other = sit(&game, JoinAs::Player).unwrap();
+        // Gone without a Close frame
+        drop(client);
+        served.await.unwrap().ok();
+
+        let dropped = 3 - other.me().unwrap();
+        assert!(matches!(
+            other.take().last(),
+            Some(&GameClientbound::PlayerStatus {
+                player,
+                status: PlayerStatus::Disconnected
+            }) if player == dropped
+        ));
+        let data = game.data.lock().unwrap();
+        assert!(data.disconnected.iter().any(|&(x, _)| x == dropped));
+    }
+
+    #[tokio::test]
+    async fn seats_free_up_before_the_start() {
+        let game = game(3, true);
+        let mut first = sit(&game, JoinAs::Player).unwrap();
+        let second = sit(&game, JoinAs::Player).unwrap();
+        second.take();
+        first.handler.close().await;
+        assert!(matches!(
+            second.take()[..],
+            [GameClientbound::WaitingFor { players: 2 }]
+        ));
+        // Back on a new connection, with no token yet
+        let back = sit(&game, JoinAs::Player).unwrap();
+        let third = sit(&game, JoinAs::Player).unwrap();
+        let mut seats = [&second, &back, &third].map(Seat::me);
+        seats.sort();
+        assert_eq!(seats, [Some(1), Some(2), Some(3)]);
+        assert_eq!(first.me(), None);
+        assert_eq!(game.data.lock().unwrap().senders.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn old_connections_closing_late_are_ignored() {
+        let game = game(2, true);
+        let [mut first, second] = started(&game);
+        let token = first.token();
+        let _back = sit(&game, JoinAs::Rejoin { token: &token }).unwrap();
+        second.take();
+        first.handler.close().await;
+        assert!(second.take().is_empty());
+        assert!(game.data.lock().unwrap().disconnected.is_empty());
+    }
+
+    #[tokio::test]
+    async fn out_once_the_grace_period_is_up() {
+        let game = game(2, true);
+        game.data.lock().unwrap().grace = Duration::from_millis(20);
+        let [mut first, second] = started(&game);
+        let token = first.token();
+        second.take();
+        first.handler.close().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(
+            second.take()[..],
+            [
+                GameClientbound::PlayerStatus { player: 1, .. },
+                GameClientbound::PlayerEliminated {
+                    player: 1,
+                    reason: LeaveReason::Disconnected
+                },
+                GameClientbound::GameWin { player: 2 }
+            ]
+        ));
+        assert!(sit(&game, JoinAs::Rejoin { token: &token }).is_none());
+    }
+
+    #[tokio::test]
+    async fn moves_add_the_increment() {
+        let game = timed(
+            2,
+            true,
+            TimeControl::Fischer {
+                base: 60,
+                increment: 5,
+            },
+        );
+        let [mut first, _second] = started(&game);
+        let replies = first.play(0, 0).await;
+        let [
+            GameClientbound::Move { player: 1, .. },
+            GameClientbound::Clocks { remaining_ms },
+            GameClientbound::Turn { player: 2 },
+        ] = &replies[..]
+        else {
+            panic!("got {replies:?}");
+        };
+        assert!(
+            (60_000..=65_000).contains(&remaining_ms[0]),
+            "{remaining_ms:?}"
+        );
+        assert!(remaining_ms[1] <= 60_000, "{remaining_ms:?}");
+    }
+
+    #[tokio::test]
+    async fn per_move_clocks_reset() {
+        let game = timed(2, true, TimeControl::PerMove { secs: 10 });
+        let [mut first, mut second] = started(&game);
+        first.play(0, 0).await;
+        game.data.lock().unwrap().clocks[0] = Duration::from_secs(1);
+        let replies = second.play(2, 2).await;
+        let [
+            GameClientbound::Move { player: 2, .. },
+            GameClientbound::Clocks { remaining_ms },
+            GameClientbound::Turn { player: 1 },
+        ] = &replies[..]
+        else {
+            panic!("got {replies:?}");
+        };
+        // Player 1 gets a fresh ten seconds, not what they had left
+        assert!(
+            (9_900..=10_000).contains(&remaining_ms[0]),
+            "{remaining_ms:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn flagged_when_the_clock_runs_out() {
+        let game = timed(
+            2,
+            true,
+            TimeControl::Fischer {
+                base: 60,
+                increment: 0,
+            },
+        );
+        game.data.lock().unwrap().clocks = vec![Duration::from_millis(20); 2];
+        let [first, second] = started(&game);
+        first.take();
+        second.take();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(
+            second.take()[..],
+            [
+                GameClientbound::PlayerEliminated {
+                    player: 1,
+                    reason: LeaveReason::TimedOut
+                },
+                GameClientbound::GameWin { player: 2 }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn late_moves_are_not_played() {
+        let game = timed(
+            2,
+            true,
+            TimeControl::Fischer {
+                base: 60,
+                increment: 0,
+            },
+        );
+        game.data.lock().unwrap().clocks = vec![Duration::ZERO; 2];
+        let [mut first, _second] = started(&game);
+        // Before the clock's timer has had a chance to go off
+        assert!(matches!(
+            first.play(0, 0).await[..],
+            [
+                GameClientbound::PlayerEliminated {
+                    player: 1,
+                    reason: LeaveReason::TimedOut
+                },
+                GameClientbound::GameWin { player: 2 }
+            ]
+        ));
+    }
+
+    fn propose(kind: ProposalType) -> GameServerbound {
+        GameServerbound::Propose { kind }
+    }
+
+    #[tokio::test]
+    async fn draws_need_everyone() {
+        let game = game(3, true);
+        let mut seats = [
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+            sit(&game, JoinAs::Player).unwrap(),
+        ];
+        seats.sort_by_key(Seat::me);
+        let [mut first, mut second, mut third] = seats;
+        first.handler.receive(propose(ProposalType::Draw)).await;
+        second.handler.receive(propose(ProposalType::Draw)).await;
+        third.take();
+        third.handler.receive(propose(ProposalType::Draw)).await;
+        assert!(matches!(
+            third.take()[..],
+            [
+                GameClientbound::RemoteProposal {
+                    player: 3,
+                    kind: ProposalType::Draw
+                },
+                GameClientbound::ProposalAccepted {
+                    kind: ProposalType::Draw
+                },
+                GameClientbound::GameDraw {
+                    reason: DrawReason::Agreement
+                }
+            ]
+        ));
+        assert!(matches!(
+            first.play(0, 0).await[..],
+            [GameClientbound::OutOfTurn]
+        ));
+
+        // Anyone turning up later hears it's over
+        let spectator = sit(&game, JoinAs::Spectator).unwrap();
+        assert!(matches!(
+            spectator.take()[..],
+            [
+                GameClientbound::Spectating { .. },
+                GameClientbound::GameDraw {
+                    reason: DrawReason::Agreement
+                }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn declining_calls_off_the_offer() {
+        let game = game(2, true);
+        let [mut first, mut second] = started(&game);
+        first.handler.receive(propose(ProposalType::Draw)).await;
+        first.take();
+        second
+            .handler
+            .receive(GameServerbound::CancelProposal {
+                kind: ProposalType::Draw,
+            })
+            .await;
+        assert!(matches!(
+            first.take()[..],
+            [GameClientbound::ProposalRefused {
+                player: 2,
+                kind: ProposalType::Draw
+            }]
+        ));
+        // Their offer is off, so this is only an offer of its own
+        second.handler.receive(propose(ProposalType::Draw)).await;
+        assert!(matches!(
+            first.take()[..],
+            [GameClientbound::RemoteProposal { player: 2, .. }]
+        ));
+        assert_eq!(game.data.lock().unwrap().drawn, None);
+    }
+
+    #[tokio::test]
+    async fn drawn_without_progress() {
+        let game = game(2, true);
+        let [mut first, mut second] = started(&game);
+        first.play(1, 1).await;
+        second.play(0, 0).await;
+        // Claiming empty cells isn't progress
+        assert_eq!(game.data.lock().unwrap().quiet_moves, 2);
+        game.data.lock().unwrap().quiet_moves = GameData::NO_PROGRESS_MOVES - 1;
+        assert!(matches!(
+            first.play(2, 2).await[..],
+            [
+                GameClientbound::Move { player: 1, .. },
+                GameClientbound::GameDraw {
+                    reason: DrawReason::Progress
+                }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn captures_restart_the_count() {
+        let game = game(2, true);
+        let [mut first, _second] = started(&game);
+        {
+            let mut data = game.data.lock().unwrap();
+            data.grid[0_u8][0_u8].owner = 1;
+            data.grid[0_u8][0_u8].dots = 2;
+            data.grid[0_u8][1_u8].owner = 2;
+            data.grid[0_u8][1_u8].dots = 1;
+            data.quiet_moves = 10;
+        }
+        // The corner bursts into their cell next door
+        first.play(0, 0).await;
+        let data = game.data.lock().unwrap();
+        assert_eq!(data.grid[0_u8][1_u8].owner, 1);
+        assert_eq!(data.quiet_moves, 0);
+    }
+}
@@ -8,13 +8,38 @@ use rustrict::CensorStr as _;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
-use crate::{GameSettings, WsHandler, game::RunningGames};
+use crate::{
+    GameSettings, WsHandler,
+    game::RunningGames,
+    validate::{LobbyError, SettingsError},
+};
+
+/// What room codes are made of. Leaves out letters that are easily confused with others, or with numbers.
+const CODE_LETTERS: [char; 16] = [
+    'A', 'E', 'G', 'I', 'K', 'L', 'N', 'O', 'P', 'S', 'T', 'U', 'V', 'X', 'Y', 'Z',
+];
+const CODE_LEN: usize = 4;
+
+fn well_formed_code(code: &str) -> bool {
+    code.chars().count() == CODE_LEN && code.chars().all(|x| CODE_LETTERS.contains(&x))
+}
 
 struct RoomData {
     settings: GameSettings,
     sockets: Vec<Arc<dyn Fn(LobbyClientbound) + Send + Sync>>,
 }
 
+impl RoomData {
+    fn announce_players(&self) {
+        for sender in &self.sockets {
+            sender(LobbyClientbound::Players {
+                joined: self.sockets.len() as u8,
+                capacity: self.settings.capacity,
+            });
+        }
+    }
+}
+
 #[derive(Default)]
 struct LobbyData {
     rooms: HashMap<String, RoomData>,
@@ -47,10 +72,14 @@ impl Lobby {
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum LobbyServerbound {
     New(GameSettings),
-    Join { code: String },
+    Join {
+        code: String,
+    },
+    /// Joins any public room with a seat free.
+    JoinPublic,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize)]
 #[serde(tag = "ty", rename_all = "snake_case")]
 pub enum LobbyClientbound {
     Created {
@@ -63,6 +92,20 @@ pub enum LobbyClientbound {
     RoomNotFound {
         code: String,
     },
+    NoPublicRooms,
+    InvalidSettings {
+        error: SettingsError,
+        /// `error`, for showing to the player.
+        reason: String,
+    },
+    Rejected {
+        error: LobbyError,
+    },
+    /// Sent to everyone in a room whenever someone joins or leaves it.
+    Players {
+        joined: u8,
+        capacity: u8,
+    },
 }
 
 struct LobbyHandler {
@@ -83,6 +126,37 @@ impl LobbyHandler {
     fn send(&self, data: LobbyClientbound) {
         (self.sender.as_ref().unwrap())(data);
     }
+
+    fn in_room(&self, data: &LobbyData) -> bool {
+        data.rooms.values().any(|room| {
+            room.sockets
+                .iter()
+                .any(|x| Arc::ptr_eq(x, self.sender.as_ref().unwrap()))
+        })
+    }
+
+    fn join(&self, data: &mut LobbyData, code: String) {
+        if let Entry::Occupied(mut room) = data.rooms.entry(code.clone()) {
+            info!("new player joining room {code}");
+            let room_inner = room.get_mut();
+            room_inner.sockets.push(self.sender.clone().unwrap());
+            if room_inner.sockets.len() == room_inner.settings.capacity as usize {
+                let room = room.remove();
+                info!("room {code} filled, announcing room settings");
+                for sender in &room.sockets {
+                    sender(LobbyClientbound::Ready {
+                        code: code.clone(),
+                        settings: room.settings,
+                    });
+                }
+                self.running_games.clone().new_game(code, room.settings);
+            } else {
+                room_inner.announce_players();
+            }
+        } else {
+            self.send(LobbyClientbound::RoomNotFound { code });
+        }
+    }
 }
 
 impl WsHandler for LobbyHandler {
@@ -90,18 +164,26 @@ impl WsHandler for LobbyHandler {
     type Clientbound = LobbyClientbound;
 
     async fn receive(&mut self, message: LobbyServerbound) {
+        if self.in_room(&self.lobby_data.lock().unwrap()) {
+            self.send(LobbyClientbound::Rejected {
+                error: LobbyError::AlreadyInRoom,
+            });
+            return;
+        }
         match message {
             LobbyServerbound::New(settings) => {
+                if let Err(error) = settings.validate() {
+                    self.send(LobbyClientbound::InvalidSettings {
+                        error,
+                        reason: error.to_string(),
+                    });
+                    return;
+                }
                 loop {
                     let code = rand::rng()
-                        .sample_iter(Uniform::new(0, 16).unwrap())
-                        .take(4)
-                        .map(|x| {
-                            [
-                                'A', 'E', 'G', 'I', 'K', 'L', 'N', 'O', 'P', 'S', 'T', 'U', 'V',
-                                'X', 'Y', 'Z',
-                            ][x]
-                        })
+                        .sample_iter(Uniform::new(0, CODE_LETTERS.len()).unwrap())
+                        .take(CODE_LEN)
+                        .map(|x| CODE_LETTERS[x])
                         .collect::<String>();
                     if code.is_inappropriate() {
                         continue;
@@ -109,37 +191,37 @@ impl WsHandler for LobbyHandler {
                     match self.lobby_data.lock().unwrap().rooms.entry(code.clone()) {
                         Entry::Occupied(_) => {} // try again
                         Entry::Vacant(x) => {
-                            x.insert(RoomData {
+                            let x = x.insert_entry(RoomData {
                                 settings,
                                 sockets: vec![self.sender.clone().unwrap()],
                             });
                             info!("created room with code {code}");
                             self.send(LobbyClientbound::Created { code });
+                            x.get().announce_players();
                             break;
                         }
                     }
                 }
             }
             LobbyServerbound::Join { code } => {
-                if let Entry::Occupied(mut room) =
-                    self.lobby_data.lock().unwrap().rooms.entry(code.clone())
-                {
-                    info!("new player joining room {code}");
-                    let room_inner = room.get_mut();
-                    room_inner.sockets.push(self.sender.clone().unwrap());
-                    if room_inner.sockets.len() == room_inner.settings.capacity as usize {
-                        let room = room.remove();
-                        info!("room {code} filled, announcing room settings");
-                        for sender in &room.sockets {
-                            sender(LobbyClientbound::Ready {
-                                code: code.clone(),
-                                settings: room.settings,
-                            });
-                        }
-                        self.running_games.clone().new_game(code, room.settings);
-                    }
-                } else {
-                    self.send(LobbyClientbound::RoomNotFound { code });
+                if !well_formed_code(&code) {
+                    self.send(LobbyClientbound::Rejected {
+                        error: LobbyError::MalformedCode,
+                    });
+                    return;
+                }
+                self.join(&mut self.lobby_data.lock().unwrap(), code);
+            }
+            LobbyServerbound::JoinPublic => {
+                let mut data = self.lobby_data.lock().unwrap();
+                let code = data
+                    .rooms
+                    .iter()
+                    .find(|(_, room)| room.settings.public)
+                    .map(|(code, _)| code.clone());
+                match code {
+                    Some(code) => self.join(&mut data, code),
+                    None => self.send(LobbyClientbound::NoPublicRooms),
                 }
             }
         }
@@ -153,13 +235,12 @@ impl WsHandler for LobbyHandler {
         let rooms: Vec<_> = data
             .rooms
             .iter()
-            .enumerate()
-            .filter(|(_, (_, x))| {
-                x.sockets
+            .filter_map(|(code, room)| {
+                room.sockets
                     .iter()
-                    .any(|x| Arc::ptr_eq(x, self.sender.as_ref().unwrap()))
+                    .position(|x| Arc::ptr_eq(x, self.sender.as_ref().unwrap()))
+                    .map(|idx| (idx, code.clone()))
             })
-            .map(|(x, (y, _))| (x, y.clone()))
             .collect();
         for (idx, code) in rooms {
             let room = data.rooms.get_mut(&code).unwrap();
@@ -169,6 +250,7 @@ impl WsHandler for LobbyHandler {
             } else {
                 debug!("player was not alone in room {code}, removing them");
                 room.sockets.remove(idx);
+                room.announce_players();
             }
         }
     }
@@ -177,3 +259,222 @@ impl WsHandler for LobbyHandler {
         self.sender = Some(handler.into());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{Rules, TimeControl};
+
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<LobbyClientbound>>>;
+
+    fn settings(capacity: u8, width: u8, height: u8) -> GameSettings {
+        GameSettings {
+            capacity,
+            width,
+            height,
+            clock: TimeControl::Unlimited,
+            rules: Rules::LastStand,
+            public: false,
+            spectators: true,
+        }
+    }
+
+    fn lobby() -> Lobby {
+        Lobby::new(Arc::new(RunningGames::new()))
+    }
+
+    fn connect(lobby: &Lobby) -> (LobbyHandler, Received) {
+        let mut handler = LobbyHandler::new(lobby);
+        let received = Received::default();
+        handler.set_send_handler(Box::new({
+            let received = received.clone();
+            move |x| received.lock().unwrap().push(x)
+        }));
+        (handler, received)
+    }
+
+    fn take(received: &Received) -> Vec<LobbyClientbound> {
+        std::mem::take(&mut received.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_settings() {
+        let lobby = lobby();
+        let (mut handler, received) = connect(&lobby);
+        for (bad, expected) in [
+            (settings(0, 6, 6), SettingsError::Players { min: 2, max: 4 }),
+            (settings(1, 6, 6), SettingsError::Players { min: 2, max: 4 }),
+            (
+                settings(200, 6, 6),
+                SettingsError::Players { min: 2, max: 4 },
+            ),
+            (
+                settings(2, 0, 0),
+                SettingsError::BoardSize { min: 2, max: 20 },
+            ),
+            (
+                settings(2, 255, 255),
+                SettingsError::BoardSize { min: 2, max: 20 },
+            ),
+            (
+                settings(2, 6, 21),
+                SettingsError::BoardSize { min: 2, max: 20 },
+            ),
+            (
+                GameSettings {
+                    clock: TimeControl::Fischer {
+                        base: 0,
+                        increment: 0,
+                    },
+                    ..settings(2, 6, 6)
+                },
+                SettingsError::Clock {
+                    min_base: 30,
+                    max_base: 7200,
+                    max_increment: 60,
+                },
+            ),
+            (
+                GameSettings {
+                    clock: TimeControl::Fischer {
+                        base: 300,
+                        increment: u32::MAX,
+                    },
+                    ..settings(2, 6, 6)
+                },
+                SettingsError::Clock {
+                    min_base: 30,
+                    max_base: 7200,
+                    max_increment: 60,
+                },
+            ),
+            (
+                GameSettings {
+                    clock: TimeControl::PerMove { secs: 0 },
+                    ..settings(2, 6, 6)
+                },
+                SettingsError::MoveTime {
+                    min_secs: 5,
+                    max_secs: 600,
+                },
+            ),
+            (
+                GameSettings {
+                    clock: TimeControl::Correspondence { days: 365 },
+                    ..settings(2, 6, 6)
+                },
+                SettingsError::Correspondence {
+                    min_days: 1,
+                    max_days: 14,
+                },
+            ),
+        ] {
+            handler.receive(LobbyServerbound::New(bad)).await;
+            let replies = take(&received);
+            assert!(
+                matches!(&replies[..], [LobbyClientbound::InvalidSettings { error, .. }] if *error == expected),
+                "{bad:?} got {replies:?}"
+            );
+        }
+        assert!(lobby.data.lock().unwrap().rooms.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_codes() {
+        let lobby = lobby();
+        let (mut handler, received) = connect(&lobby);
+        for code in [
+            "",
+            "AEG",
+            "AEGIK",
+            "aegi",
+            "1234",
+            "ÄÄÄÄ",
+            &"A".repeat(10_000),
+        ] {
+            handler
+                .receive(LobbyServerbound::Join { code: code.into() })
+                .await;
+            let replies = take(&received);
+            assert!(
+                matches!(
+                    &replies[..],
+                    [LobbyClientbound::Rejected {
+                        error: LobbyError::MalformedCode
+                    }]
+                ),
+                "{code:?} got {replies:?}"
+            );
+        }
+        handler
+            .receive(LobbyServerbound::Join {
+                code: "AEGI".into(),
+            })
+            .await;
+        assert!(matches!(
+            &take(&received)[..],
+            [LobbyClientbound::RoomNotFound { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn one_seat_per_socket() {
+        let lobby = lobby();
+        let (mut host, received) = connect(&lobby);
+        host.receive(LobbyServerbound::New(settings(2, 6, 6))).await;
+        let Some(LobbyClientbound::Created { code }) = take(&received).into_iter().next() else {
+            panic!("room wasn't created");
+        };
+
+        // Joining their own room would fill it with one player
+        host.receive(LobbyServerbound::Join { code: code.clone() })
+            .await;
+        host.receive(LobbyServerbound::New(settings(2, 6, 6))).await;
+        assert!(matches!(
+            &take(&received)[..],
+            [
+                LobbyClientbound::Rejected {
+                    error: LobbyError::AlreadyInRoom
+                },
+                LobbyClientbound::Rejected {
+                    error: LobbyError::AlreadyInRoom
+                },
+            ]
+        ));
+        let data = lobby.data.lock().unwrap();
+        assert_eq!(data.rooms.len(), 1);
+        assert_eq!(data.rooms[&code].sockets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn leaving_clears_rooms() {
+        let lobby = lobby();
+        let (mut host, _) = connect(&lobby);
+        host.receive(LobbyServerbound::New(settings(3, 6, 6))).await;
+        let code = lobby
+            .data
+            .lock()
+            .unwrap()
+            .rooms
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let (mut guest, guest_received) = connect(&lobby);
+        guest
+            .receive(LobbyServerbound::Join { code: code.clone() })
+            .await;
+
+        host.close().await;
+        assert!(matches!(
+            take(&guest_received).last(),
+            Some(LobbyClientbound::Players {
+                joined: 1,
+                capacity: 3
+            })
+        ));
+        guest.close().await;
+        assert!(lobby.data.lock().unwrap().rooms.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 mod game;
 mod lobby;
+mod validate;
 
 use tracing_subscriber::prelude::*;
 
@@ -17,24 +18,67 @@ use hyper::{
     Request, Response,
     body::{Bytes, Incoming},
 };
-use hyper_tungstenite::{HyperWebsocket, tungstenite::Message};
+use hyper_tungstenite::{HyperWebsocket, WebSocketStream, tungstenite::Message};
 use hyper_util::{
     rt::{TokioExecutor, TokioIo},
     server::conn::auto,
 };
 use indoc::indoc;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use tokio::sync::mpsc;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
 use tracing::{error, info, level_filters::LevelFilter};
 use tracing_subscriber::EnvFilter;
 
-use crate::{game::RunningGames, lobby::Lobby};
+use crate::{
+    game::{JoinAs, RunningGames},
+    lobby::Lobby,
+};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct GameSettings {
     capacity: u8,
     width: u8,
     height: u8,
+    #[serde(default)]
+    clock: TimeControl,
+    #[serde(default)]
+    rules: Rules,
+    /// Listed for anyone to join without the code.
+    #[serde(default)]
+    public: bool,
+    #[serde(default = "default_spectators")]
+    spectators: bool,
+}
+
+const fn default_spectators() -> bool {
+    true
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum TimeControl {
+    #[default]
+    Unlimited,
+    /// Everyone starts with `base` seconds, and gains `increment` after each of their moves.
+    Fischer { base: u32, increment: u32 },
+    /// Every move has to be made within `secs` seconds. Time left over isn't kept.
+    PerMove { secs: u32 },
+    /// As [`TimeControl::PerMove`], but with `days` for each move.
+    Correspondence { days: u32 },
+}
+
+/// When a player is knocked out of the game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rules {
+    /// Once there's nowhere left for them to play: no empty cells, and none of their own.
+    #[default]
+    LastStand,
+    /// As soon as they've lost every cell, once they've had a turn.
+    Classic,
 }
 
 pub trait WsHandler {
@@ -63,14 +107,10 @@ async fn handle_request(
             });
 
             Ok(response)
-        } else if path == "/ws/game" {
-            let Some(id) = request
-                .uri()
-                .query()
-                .filter(|x| x.starts_with("id="))
-                .map(|x| &x[3..])
-                .map(String::from)
-            else {
+        } else if path == "/ws/game" || path == "/ws/watch" {
+            let watch = path == "/ws/watch";
+            let query = request.uri().query().unwrap_or_default();
+            let Some(id) = query_param(query, "id").map(String::from) else {
                 return Ok(Response::builder()
                     .status(400)
                     .body(Full::from(r#"{"error": "expected game ID param"}"#))
@@ -83,7 +123,23 @@ async fn handle_request(
                     .body(Full::from(r#"{"error": "game not found"}"#))
                     .unwrap());
             };
-            let handler = game.new_handler();
+            let token = query_param(query, "token");
+            let join = match token {
+                _ if watch => JoinAs::Spectator,
+                Some(token) => JoinAs::Rejoin { token },
+                None => JoinAs::Player,
+            };
+            let Some(handler) = game.new_handler(join) else {
+                let error = match join {
+                    JoinAs::Spectator => r#"{"error": "game doesn't allow spectators"}"#,
+                    JoinAs::Rejoin { .. } => r#"{"error": "no seat to rejoin"}"#,
+                    JoinAs::Player => r#"{"error": "game is full"}"#,
+                };
+                return Ok(Response::builder()
+                    .status(403)
+                    .body(Full::from(error))
+                    .unwrap());
+            };
 
             let (response, websocket) = hyper_tungstenite::upgrade(&mut request, None)?;
 
@@ -118,11 +174,32 @@ async fn handle_request(
     }
 }
 
+/// The value of `key` in a URL query, like `id=AEGI&token=...`.
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|x| x.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
 async fn serve_websocket<T: Serialize + Send + 'static, U: DeserializeOwned + Send>(
     websocket: HyperWebsocket,
+    handler: impl WsHandler<Serverbound = U, Clientbound = T> + Send,
+) -> anyhow::Result<()> {
+    serve_stream(websocket.await?, handler).await
+}
+
+/// Passes messages between a websocket and its handler until the connection ends, whether it's
+/// closed properly or just drops.
+async fn serve_stream<
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: Serialize + Send + 'static,
+    U: DeserializeOwned + Send,
+>(
+    mut websocket: WebSocketStream<S>,
     mut handler: impl WsHandler<Serverbound = U, Clientbound = T> + Send,
 ) -> anyhow::Result<()> {
-    let mut websocket = websocket.await?;
     let (tx, mut rx) = mpsc::unbounded_channel::<T>();
     let is_closing = Arc::new(AtomicBool::new(false));
     {
@@ -133,40 +210,46 @@ async fn serve_websocket<T: Serialize + Send + 'static, U: DeserializeOwned + Se
             }
         }));
     }
-    loop {
-        futures_util::select! {
-            outbound = rx.recv().fuse() => if let Some(x) = outbound {
-                websocket.send(Message::Binary(bson::serialize_to_vec(&x)?.into())).await?;
-            } else {
-                websocket.send(Message::Close(None)).await?;
-                break;
-            },
-            inbound = websocket.next() => if let Some(x) = inbound {
-                #[allow(clippy::single_match)] // Will support Message::Text as an alternate channel in the future
-                match x? {
-                    Message::Binary(x) => {
-                        let inbound = bson::deserialize_from_slice(&x);
-                        match inbound {
-                            Ok(inbound) => handler.receive(inbound).await,
-                            Err(err) => websocket.send(Message::Binary(bson::serialize_to_vec(&bson::bson!({
-                                "error": "deserialization error",
-                                "details": err.to_string(),
-                            }))?.into())).await?,
+    let result: anyhow::Result<()> = async {
+        loop {
+            futures_util::select! {
+                outbound = rx.recv().fuse() => if let Some(x) = outbound {
+                    websocket.send(Message::Binary(bson::serialize_to_vec(&x)?.into())).await?;
+                } else {
+                    websocket.send(Message::Close(None)).await?;
+                    break;
+                },
+                inbound = websocket.next() => if let Some(x) = inbound {
+                    #[allow(clippy::single_match)] // Will support Message::Text as an alternate channel in the future
+                    match x? {
+                        Message::Binary(x) => {
+                            let inbound = bson::deserialize_from_slice(&x);
+                            match inbound {
+                                Ok(inbound) => handler.receive(inbound).await,
+                                Err(err) => websocket.send(Message::Binary(bson::serialize_to_vec(&bson::bson!({
+                                    "error": "deserialization error",
+                                    "details": err.to_string(),
+                                }))?.into())).await?,
+                            }
                         }
+                        Message::Close(_) if !is_closing.swap(true, Ordering::Relaxed) => {
+                            handler.close().await;
+                        }
+                        _ => {}
                     }
-                    Message::Close(_) => {
-                        is_closing.store(true, Ordering::Relaxed);
-                        handler.close().await;
-                    }
-                    _ => {}
+                } else {
+                    break;
                 }
-            } else {
-                break;
             }
         }
+        Ok(())
     }
-
-    Ok(())
+    .await;
+    // However the connection ended, the handler hears about it once
+    if !is_closing.swap(true, Ordering::Relaxed) {
+        handler.close().await;
+    }
+    result
 }
 
 #[tokio::main]
@@ -0,0 +1,139 @@
+//! Checking what clients send before acting on it. Anything can arrive over the socket, so nothing here trusts the client.
+
+use std::{fmt, ops::RangeInclusive};
+
+use serde::Serialize;
+
+use crate::{GameSettings, TimeControl};
+
+/// Why the server won't host a room with some settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum SettingsError {
+    Players {
+        min: u8,
+        max: u8,
+    },
+    BoardSize {
+        min: u8,
+        max: u8,
+    },
+    Clock {
+        min_base: u32,
+        max_base: u32,
+        max_increment: u32,
+    },
+    MoveTime {
+        min_secs: u32,
+        max_secs: u32,
+    },
+    Correspondence {
+        min_days: u32,
+        max_days: u32,
+    },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Players { min, max } => write!(f, "rooms are for {min} to {max} players"),
+            Self::BoardSize { min, max } => {
+                write!(f, "boards are {min} to {max} cells wide and high")
+            }
+            Self::Clock {
+                min_base,
+                max_base,
+                max_increment,
+            } => write!(
+                f,
+                "clocks start with {min_base} to {max_base} seconds, and add at most {max_increment} seconds a move"
+            ),
+            Self::MoveTime { min_secs, max_secs } => {
+                write!(f, "moves can take {min_secs} to {max_secs} seconds each")
+            }
+            Self::Correspondence { min_days, max_days } => {
+                write!(
+                    f,
+                    "correspondence moves can take {min_days} to {max_days} days each"
+                )
+            }
+        }
+    }
+}
+
+/// Why the lobby turned a request down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum LobbyError {
+    /// Not something [`crate::lobby`] would ever hand out as a room code.
+    MalformedCode,
+    /// Each socket gets one seat, in one room at a time.
+    AlreadyInRoom,
+}
+
+/// Why a move was turned down, other than it being someone else's turn or someone else's cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "ty", rename_all = "snake_case")]
+pub enum MoveError {
+    OutOfBounds {
+        x: u8,
+        y: u8,
+        width: u8,
+        height: u8,
+    },
+    /// Spectators, and players who are out of the game.
+    NotPlaying,
+}
+
+impl GameSettings {
+    pub const PLAYERS: RangeInclusive<u8> = 2..=4;
+    pub const SIDE: RangeInclusive<u8> = 2..=20;
+    pub const CLOCK_BASE_SECS: RangeInclusive<u32> = 30..=7200;
+    pub const MAX_INCREMENT_SECS: u32 = 60;
+    pub const MOVE_SECS: RangeInclusive<u32> = 5..=600;
+    pub const CORRESPONDENCE_DAYS: RangeInclusive<u32> = 1..=14;
+
+    /// Checks the settings a host asked for are within what the server will run.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if !Self::PLAYERS.contains(&self.capacity) {
+            return Err(SettingsError::Players {
+                min: *Self::PLAYERS.start(),
+                max: *Self::PLAYERS.end(),
+            });
+        }
+        if !Self::SIDE.contains(&self.width) || !Self::SIDE.contains(&self.height) {
+            return Err(SettingsError::BoardSize {
+                min: *Self::SIDE.start(),
+                max: *Self::SIDE.end(),
+            });
+        }
+        match self.clock {
+            TimeControl::Unlimited => Ok(()),
+            TimeControl::Fischer { base, increment }
+                if !Self::CLOCK_BASE_SECS.contains(&base)
+                    || increment > Self::MAX_INCREMENT_SECS =>
+            {
+                Err(SettingsError::Clock {
+                    min_base: *Self::CLOCK_BASE_SECS.start(),
+                    max_base: *Self::CLOCK_BASE_SECS.end(),
+                    max_increment: Self::MAX_INCREMENT_SECS,
+                })
+            }
+            TimeControl::Fischer { .. } => Ok(()),
+            TimeControl::PerMove { secs } if !Self::MOVE_SECS.contains(&secs) => {
+                Err(SettingsError::MoveTime {
+                    min_secs: *Self::MOVE_SECS.start(),
+                    max_secs: *Self::MOVE_SECS.end(),
+                })
+            }
+            TimeControl::PerMove { .. } => Ok(()),
+            TimeControl::Correspondence { days } if !Self::CORRESPONDENCE_DAYS.contains(&days) => {
+                Err(SettingsError::Correspondence {
+                    min_days: *Self::CORRESPONDENCE_DAYS.start(),
+                    max_days: *Self::CORRESPONDENCE_DAYS.end(),
+                })
+            }
+            TimeControl::Correspondence { .. } => Ok(()),
+        }
+    }
+}